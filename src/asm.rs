@@ -0,0 +1,557 @@
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use crate::vm::{
+    A, B, COUNTER_CSRS, CSR_MCAUSE, CSR_MEPC, CSR_MTVAL, CSR_MTVEC, Format, I, Instruction, J,
+    OpcodeInfo, Operands, R, S, U, all_opcodes, register_abi_name,
+};
+
+#[cfg(test)]
+mod asm_tests;
+
+/// Why a source line didn't assemble, `line` counts from 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// One instruction, pseudo-instruction or data directive with its operands split on commas
+struct Statement<'a> {
+    line: usize,
+    addr: u32,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+/// Assembles RV32I source into little-endian machine code placed at address 0.
+///
+/// Takes one instruction per line, `#` starts a comment and `name:` defines a label that
+/// branches and jumps can use in place of an offset. Besides the base integer instructions
+/// this understands the `li`, `nop`, `mv`, `j`, and `ret` pseudo-instructions, and the counter
+/// reads `rdcycle`, `rdtime`, `rdinstret` and their `h` halves. CSR instructions take the CSR
+/// as a number, a counter name, or one of `mtvec`, `mepc`, `mcause` and `mtval`.
+/// Registers are written `x0`..`x31` or by their ABI names.
+///
+/// Data goes in with `.byte`, `.half` and `.word`, which take comma separated values (`.word`
+/// also takes labels), `.ascii "text"` and `.space n` for `n` zero bytes. Nothing is aligned
+/// for you, so keep instructions after data on a halfword boundary.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    // first pass gives every label an address, second encodes with all of them known
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+    let mut addr = 0;
+    for (index, line) in source.lines().enumerate() {
+        let line_no = index + 1;
+        let mut text = strip_comment(line).trim();
+        while let Some((label, rest)) = text.split_once(':')
+            && !label.contains('"')
+        {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(error(line_no, format!("invalid label '{}'", label)));
+            }
+            if labels.insert(label.to_string(), addr).is_some() {
+                return Err(error(line_no, format!("duplicate label '{}'", label)));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands = if rest.trim().is_empty() {
+            vec![]
+        } else if mnemonic == ".ascii" {
+            // the string may hold commas of its own
+            vec![rest.trim()]
+        } else {
+            rest.split(',').map(str::trim).collect()
+        };
+        let statement = Statement {
+            line: line_no,
+            addr,
+            mnemonic: mnemonic.to_lowercase(),
+            operands,
+        };
+        let len = statement.len().map_err(|message| error(line_no, message))?;
+        addr = addr.checked_add(len).ok_or_else(|| {
+            error(
+                line_no,
+                "program runs past the end of the address space".to_string(),
+            )
+        })?;
+        statements.push(statement);
+    }
+
+    let mut bytes = Vec::with_capacity(addr as usize);
+    for statement in &statements {
+        let encoded = statement
+            .encode(&labels)
+            .map_err(|message| error(statement.line, message))?;
+        bytes.extend(encoded);
+    }
+    Ok(bytes)
+}
+
+fn error(line: usize, message: String) -> AsmError {
+    AsmError { line, message }
+}
+
+/// `line` up to a `#` that isn't inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+impl Statement<'_> {
+    /// Number of bytes this statement assembles to, only `li` and data can take more than a word
+    fn len(&self) -> Result<u32, String> {
+        let count = self.operands.len() as u32;
+        match self.mnemonic.as_str() {
+            "li" => {
+                self.expect_operands(2)?;
+                let val = parse_imm(self.operands[1])?;
+                Ok(if fits_signed(val, 12) { 4 } else { 8 })
+            }
+            ".byte" => Ok(count),
+            ".half" => Ok(2 * count),
+            ".word" => Ok(4 * count),
+            ".ascii" => {
+                self.expect_operands(1)?;
+                Ok(parse_string(self.operands[0])?.len() as u32)
+            }
+            ".space" => {
+                self.expect_operands(1)?;
+                match parse_imm(self.operands[0])? {
+                    size if size >= 0 => Ok(size as u32),
+                    size => Err(format!("'.space' can't reserve {} bytes", size)),
+                }
+            }
+            _ => Ok(4),
+        }
+    }
+
+    fn expect_operands(&self, count: usize) -> Result<(), String> {
+        if self.operands.len() != count {
+            return Err(format!(
+                "'{}' takes {} operands, found {}",
+                self.mnemonic,
+                count,
+                self.operands.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Little-endian bytes of this statement with every label resolved
+    fn encode(&self, labels: &HashMap<String, u32>) -> Result<Vec<u8>, String> {
+        if self.mnemonic.starts_with('.') {
+            return self.encode_data(labels);
+        }
+        let words = self.encode_instruction(labels)?;
+        Ok(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+    }
+
+    fn encode_data(&self, labels: &HashMap<String, u32>) -> Result<Vec<u8>, String> {
+        let value = |operand: &str, bits: u32| -> Result<u32, String> {
+            let val = match labels.get(operand) {
+                Some(&label) => label as i64,
+                None if is_label(operand) => return Err(format!("unknown label '{}'", operand)),
+                None => parse_imm(operand)?,
+            };
+            // either signed or unsigned values that fit are fine, like `.byte -1` or `.byte 255`
+            if !(-(1 << (bits - 1))..(1 << bits)).contains(&val) {
+                return Err(format!("value {} doesn't fit in {} bits", val, bits));
+            }
+            Ok(val as u32)
+        };
+        let mut bytes = vec![];
+        match self.mnemonic.as_str() {
+            ".byte" => {
+                for operand in &self.operands {
+                    bytes.push(value(operand, 8)? as u8);
+                }
+            }
+            ".half" => {
+                for operand in &self.operands {
+                    bytes.extend((value(operand, 16)? as u16).to_le_bytes());
+                }
+            }
+            ".word" => {
+                for operand in &self.operands {
+                    bytes.extend(value(operand, 32)?.to_le_bytes());
+                }
+            }
+            ".ascii" => bytes = parse_string(self.operands[0])?,
+            ".space" => bytes.resize(self.len()? as usize, 0),
+            _ => return Err(format!("unknown directive '{}'", self.mnemonic)),
+        }
+        Ok(bytes)
+    }
+
+    fn encode_instruction(&self, labels: &HashMap<String, u32>) -> Result<Vec<u32>, String> {
+        Ok(self
+            .instructions(labels)?
+            .iter()
+            .map(Instruction::encode)
+            .collect())
+    }
+
+    /// What this statement assembles to, pseudo-instructions expand to one or two
+    fn instructions(&self, labels: &HashMap<String, u32>) -> Result<Vec<Instruction>, String> {
+        let ops = &self.operands;
+        let target = |operand: &str| -> Result<i64, String> {
+            match labels.get(operand) {
+                Some(&label) => Ok(label as i64 - self.addr as i64),
+                None if is_label(operand) => Err(format!("unknown label '{}'", operand)),
+                None => parse_imm(operand),
+            }
+        };
+
+        // pseudo-instructions
+        match self.mnemonic.as_str() {
+            "nop" => {
+                self.expect_operands(0)?;
+                return Ok(vec![build("ADDI", i_operands(0, 0, 0)?)?]);
+            }
+            "mv" => {
+                self.expect_operands(2)?;
+                let (rd, rs1) = (parse_register(ops[0])?, parse_register(ops[1])?);
+                return Ok(vec![build("ADDI", i_operands(rd, rs1, 0)?)?]);
+            }
+            "j" => {
+                self.expect_operands(1)?;
+                return Ok(vec![build("JAL", j_operands(0, target(ops[0])?)?)?]);
+            }
+            "ret" => {
+                self.expect_operands(0)?;
+                return Ok(vec![build("JALR", i_operands(0, 1, 0)?)?]);
+            }
+            "li" => {
+                self.expect_operands(2)?;
+                let rd = parse_register(ops[0])?;
+                let val = parse_imm(ops[1])?;
+                if fits_signed(val, 12) {
+                    return Ok(vec![build("ADDI", i_operands(rd, 0, val)?)?]);
+                }
+                // addi sign extends its immediate, so round the upper part to compensate
+                let val = val as u32;
+                let upper = val.wrapping_add(0x800) >> 12;
+                let lower = (val << 20) as i32 >> 20;
+                return Ok(vec![
+                    build("LUI", Operands::U(U::new(rd, upper)))?,
+                    build("ADDI", i_operands(rd, rd, lower as i64)?)?,
+                ]);
+            }
+            counter if counter.starts_with("rd") => {
+                if let Some((_, csr)) = COUNTER_CSRS.iter().find(|(name, _)| counter[2..] == **name)
+                {
+                    // csrrs rd, csr, x0
+                    self.expect_operands(1)?;
+                    let rd = parse_register(ops[0])?;
+                    return Ok(vec![build(
+                        "CSRRS",
+                        Operands::I(I::new(rd, 0, *csr as u32)),
+                    )?]);
+                }
+            }
+            _ => {}
+        }
+
+        // atomics may ask for acquire or release ordering
+        let (mnemonic, aq, rl) = match self.mnemonic.rsplit_once('.') {
+            Some((base, "aq")) => (base, true, false),
+            Some((base, "rl")) => (base, false, true),
+            Some((base, "aqrl")) => (base, true, true),
+            _ => (self.mnemonic.as_str(), false, false),
+        };
+        let name = match mnemonic {
+            // the opcode table spells these differently
+            "sltiu" => "SLTUI".to_string(),
+            // `fence.i`, `lr.w`, `amoadd.w` and so on lose their dot
+            other => other.replace('.', "").to_uppercase(),
+        };
+        let info = opcode(&name)?;
+        let operands = match info.format {
+            // atomics take their address as `(register)` with no offset
+            Format::R if info.opcode == 0b0101111 => {
+                let (rd, rs2, addr) = if name == "LRW" {
+                    self.expect_operands(2)?;
+                    (parse_register(ops[0])?, 0, ops[1])
+                } else {
+                    self.expect_operands(3)?;
+                    (parse_register(ops[0])?, parse_register(ops[1])?, ops[2])
+                };
+                let (offset, rs1) = parse_mem_operand(addr)?;
+                if offset != 0 {
+                    return Err(format!("'{}' doesn't take an offset", self.mnemonic));
+                }
+                Operands::A(A::new(rd, rs1, rs2, aq, rl))
+            }
+            Format::R => {
+                self.expect_operands(3)?;
+                Operands::R(R::new(
+                    parse_register(ops[0])?,
+                    parse_register(ops[1])?,
+                    parse_register(ops[2])?,
+                ))
+            }
+            Format::I => match info.opcode {
+                // loads
+                0b0000011 => {
+                    self.expect_operands(2)?;
+                    let (offset, rs1) = parse_mem_operand(ops[1])?;
+                    i_operands(parse_register(ops[0])?, rs1, offset)?
+                }
+                0b1100111 => match ops.len() {
+                    1 => i_operands(1, parse_register(ops[0])?, 0)?,
+                    _ => {
+                        self.expect_operands(2)?;
+                        let (offset, rs1) = parse_mem_operand(ops[1])?;
+                        i_operands(parse_register(ops[0])?, rs1, offset)?
+                    }
+                },
+                // `csrrw rd, csr, rs1`, the immediate forms take a 5 bit value in place of rs1
+                0b1110011 if name.starts_with("CSR") => {
+                    self.expect_operands(3)?;
+                    let rd = parse_register(ops[0])?;
+                    let csr = parse_csr(ops[1])?;
+                    let source = if name.ends_with('I') {
+                        match parse_imm(ops[2])? {
+                            uimm @ 0..32 => uimm as u8,
+                            uimm => {
+                                return Err(format!("immediate {} doesn't fit in 5 bits", uimm));
+                            }
+                        }
+                    } else {
+                        parse_register(ops[2])?
+                    };
+                    Operands::I(I::new(rd, source, csr))
+                }
+                0b0001111 | 0b1110011 => {
+                    self.expect_operands(0)?;
+                    // plain `fence` orders everything, iorw before iorw
+                    let imm = if name == "FENCE" { 0xff } else { 0 };
+                    Operands::I(I::new(0, 0, imm))
+                }
+                _ => {
+                    self.expect_operands(3)?;
+                    let (rd, rs1) = (parse_register(ops[0])?, parse_register(ops[1])?);
+                    let imm = parse_imm(ops[2])?;
+                    if info.funct7.is_some() {
+                        if !(0..32).contains(&imm) {
+                            return Err(format!("shift amount {} is out of range", imm));
+                        }
+                        Operands::I(I::new(rd, rs1, imm as u32))
+                    } else {
+                        i_operands(rd, rs1, imm)?
+                    }
+                }
+            },
+            Format::S => {
+                self.expect_operands(2)?;
+                let rs2 = parse_register(ops[0])?;
+                let (offset, rs1) = parse_mem_operand(ops[1])?;
+                check_signed(offset, 12)?;
+                Operands::S(S::new(rs1, rs2, offset as u32))
+            }
+            Format::B => {
+                self.expect_operands(3)?;
+                let (rs1, rs2) = (parse_register(ops[0])?, parse_register(ops[1])?);
+                let offset = target(ops[2])?;
+                check_signed(offset, 13)?;
+                check_even(offset)?;
+                Operands::B(B::new(rs1, rs2, offset as u32))
+            }
+            Format::U => {
+                self.expect_operands(2)?;
+                let imm = parse_imm(ops[1])?;
+                if !(-0x80000..0x100000).contains(&imm) {
+                    return Err(format!("immediate {} doesn't fit in 20 bits", imm));
+                }
+                Operands::U(U::new(parse_register(ops[0])?, imm as u32))
+            }
+            Format::J => match ops.len() {
+                // `jal label` links through ra
+                1 => j_operands(1, target(ops[0])?)?,
+                _ => {
+                    self.expect_operands(2)?;
+                    j_operands(parse_register(ops[0])?, target(ops[1])?)?
+                }
+            },
+        };
+        Ok(vec![build(&name, operands)?])
+    }
+}
+
+fn opcode(name: &str) -> Result<&'static OpcodeInfo, String> {
+    all_opcodes()
+        .iter()
+        .find(|info| info.mnemonic == name)
+        .ok_or_else(|| format!("unknown instruction '{}'", name.to_lowercase()))
+}
+
+/// The instruction called `name` in the opcode table with `operands`
+fn build(name: &str, operands: Operands) -> Result<Instruction, String> {
+    Instruction::new(opcode(name)?, operands)
+        .ok_or_else(|| format!("invalid operands for '{}'", name.to_lowercase()))
+}
+
+fn i_operands(rd: u8, rs1: u8, imm: i64) -> Result<Operands, String> {
+    check_signed(imm, 12)?;
+    Ok(Operands::I(I::new(rd, rs1, imm as u32)))
+}
+
+fn j_operands(rd: u8, offset: i64) -> Result<Operands, String> {
+    check_signed(offset, 21)?;
+    check_even(offset)?;
+    Ok(Operands::J(J::new(rd, offset as u32)))
+}
+
+fn fits_signed(val: i64, bits: u32) -> bool {
+    (-(1 << (bits - 1))..(1 << (bits - 1))).contains(&val)
+}
+
+fn check_signed(val: i64, bits: u32) -> Result<(), String> {
+    if fits_signed(val, bits) {
+        Ok(())
+    } else {
+        Err(format!("immediate {} doesn't fit in {} bits", val, bits))
+    }
+}
+
+fn check_even(offset: i64) -> Result<(), String> {
+    if offset % 2 != 0 {
+        return Err(format!("jump offset {} isn't a multiple of 2", offset));
+    }
+    Ok(())
+}
+
+/// Decimal or `0x` hex, optionally negative. Anything up to `u32::MAX` is accepted
+/// so `li` can take full width constants, the encoders check their own ranges
+fn parse_imm(text: &str) -> Result<i64, String> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let magnitude = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse::<i64>(),
+    }
+    .map_err(|_| format!("invalid immediate '{}'", text))?;
+    let val = if negative { -magnitude } else { magnitude };
+    if !(i32::MIN as i64..=u32::MAX as i64).contains(&val) {
+        return Err(format!("immediate {} is out of range", text));
+    }
+    // full width constants like 0xffffffff mean the same as their signed form
+    Ok(if val > i32::MAX as i64 {
+        val as u32 as i32 as i64
+    } else {
+        val
+    })
+}
+
+/// A double quoted string with `\n`, `\t`, `\0`, `\\` and `\"` escapes
+fn parse_string(text: &str) -> Result<Vec<u8>, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|rest| rest.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, found '{}'", text))?;
+    let mut bytes = vec![];
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '"')) => c,
+                _ => return Err(format!("invalid escape in '{}'", text)),
+            },
+            '"' => return Err(format!("unescaped '\"' in '{}'", text)),
+            c => c,
+        };
+        let mut buf = [0; 4];
+        bytes.extend(c.encode_utf8(&mut buf).bytes());
+    }
+    Ok(bytes)
+}
+
+/// `x0`..`x31`, an ABI name, or `fp`
+pub(crate) fn parse_register(text: &str) -> Result<u8, String> {
+    if let Some(index) = text.strip_prefix('x')
+        && let Ok(index) = index.parse::<u8>()
+        && index < 32
+    {
+        return Ok(index);
+    }
+    if text == "fp" {
+        return Ok(8);
+    }
+    (0..32)
+        .find(|&index| register_abi_name(index) == text)
+        .map(|index| index as u8)
+        .ok_or_else(|| format!("unknown register '{}'", text))
+}
+
+/// A CSR number, a counter like `cycle`, or one of the trap CSRs like `mepc`
+fn parse_csr(text: &str) -> Result<u32, String> {
+    let named = [
+        ("mtvec", CSR_MTVEC),
+        ("mepc", CSR_MEPC),
+        ("mcause", CSR_MCAUSE),
+        ("mtval", CSR_MTVAL),
+    ];
+    if let Some((_, csr)) = COUNTER_CSRS
+        .iter()
+        .chain(&named)
+        .find(|(name, _)| *name == text)
+    {
+        return Ok(*csr as u32);
+    }
+    match parse_imm(text) {
+        Ok(csr @ 0..0x1000) => Ok(csr as u32),
+        _ => Err(format!("unknown CSR '{}'", text)),
+    }
+}
+
+/// `offset(reg)` as used by loads, stores, and `jalr`, the offset may be left out
+fn parse_mem_operand(text: &str) -> Result<(i64, u8), String> {
+    let (offset, rest) = text
+        .split_once('(')
+        .ok_or_else(|| format!("expected 'offset(register)', found '{}'", text))?;
+    let register = rest
+        .strip_suffix(')')
+        .ok_or_else(|| format!("missing ')' in '{}'", text))?;
+    let offset = match offset.trim() {
+        "" => 0,
+        offset => parse_imm(offset)?,
+    };
+    Ok((offset, parse_register(register.trim())?))
+}
@@ -0,0 +1,165 @@
+use crate::asm::{AsmError, assemble};
+use crate::vm::ArchState;
+
+fn words(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn test_assemble_loop() {
+    let source = "
+        # x1 = 3 * 10
+        li x1, 0
+        li sp, 10
+    loop:
+        addi ra, ra, 3
+        addi x2, x2, -1
+        bne x2, zero, loop
+    ";
+    let bytes = assemble(source).unwrap();
+    assert_eq!(
+        words(&bytes),
+        [0x00000093, 0x00a00113, 0x00308093, 0xfff10113, 0xfe011ce3]
+    );
+
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(bytes, 0);
+    for _ in 0..2 + 10 * 3 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.get_register(1), 30);
+    assert_eq!(state.get_register(2), 0);
+    assert_eq!(state.pc, 20);
+}
+
+#[test]
+fn test_assemble_pseudo() {
+    let source = "
+        li a0, 0x12345fff
+        mv a1, a0
+        j end
+        nop
+    end: sw a1, 4(sp)
+        ret
+    ";
+    assert_eq!(
+        words(&assemble(source).unwrap()),
+        [
+            0x12346537, // lui a0, 0x12346
+            0xfff50513, // addi a0, a0, -1
+            0x00050593, // addi a1, a0, 0
+            0x0080006f, // jal x0, 8
+            0x00000013, // addi x0, x0, 0
+            0x00b12223, // sw a1, 4(sp)
+            0x00008067, // jalr x0, 0(ra)
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_csr() {
+    let source = "
+        csrrw t0, mtvec, a0
+        csrrs t1, mcause, x0
+        csrrc x0, mepc, a1
+        csrrwi x0, mtval, 8
+        csrrsi t2, cycle, 31
+        csrrci x0, 0x800, 1
+    ";
+    assert_eq!(
+        words(&assemble(source).unwrap()),
+        [
+            0x305512f3, 0x34202373, 0x3415b073, 0x34345073, 0xc00fe3f3, 0x8000f073
+        ]
+    );
+    let err = |source| assemble(source).unwrap_err().message;
+    assert_eq!(
+        err("csrrwi x0, mtval, 32"),
+        "immediate 32 doesn't fit in 5 bits"
+    );
+    assert_eq!(err("csrrw x0, 0x1000, x1"), "unknown CSR '0x1000'");
+    assert_eq!(err("csrrw x0, mstatus, x1"), "unknown CSR 'mstatus'");
+}
+
+#[test]
+fn test_assemble_atomic_ordering() {
+    let source = "
+        lr.w.aq t0, (a0)
+        amoswap.w.aqrl t0, t1, (a0)
+        sc.w.rl t0, t1, (a0)
+    ";
+    assert_eq!(
+        words(&assemble(source).unwrap()),
+        [0x140522af, 0x0e6522af, 0x1a6522af]
+    );
+}
+
+#[test]
+fn test_assemble_errors() {
+    let err = |source| assemble(source).unwrap_err();
+    assert_eq!(
+        err("nop\nbeq x1, x2, nowhere"),
+        AsmError {
+            line: 2,
+            message: "unknown label 'nowhere'".to_string()
+        }
+    );
+    assert_eq!(err("add x1, x2, x32").message, "unknown register 'x32'");
+    assert_eq!(
+        err("addi x1, x1, 2048").message,
+        "immediate 2048 doesn't fit in 12 bits"
+    );
+    assert_eq!(err("frob x1").message, "unknown instruction 'frob'");
+}
+
+#[test]
+fn test_assemble_data() {
+    let source = r#"
+        lw a0, 12(zero)
+        lw a1, 16(zero)
+        j done
+    table:
+        .word 0x12345678, table
+        .byte 1, -1
+        .half 0xbeef
+        .ascii "a,b # \"c\"\n"
+        .space 2
+    done: lhu a2, 22(zero)
+    "#;
+    let bytes = assemble(source).unwrap();
+    assert_eq!(bytes.len(), 40);
+    assert_eq!(&bytes[20..24], [1, 0xff, 0xef, 0xbe]);
+    assert_eq!(&bytes[24..36], b"a,b # \"c\"\n\0\0");
+
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(bytes, 0);
+    for _ in 0..4 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.get_register(10), 0x12345678);
+    assert_eq!(
+        state.get_register(11),
+        12,
+        "a label in .word is its address"
+    );
+    assert_eq!(state.get_register(12), 0xbeef);
+
+    assert_eq!(
+        assemble(".byte 256").unwrap_err().message,
+        "value 256 doesn't fit in 8 bits"
+    );
+    assert_eq!(
+        assemble(".ascii abc").unwrap_err().message,
+        "expected a quoted string, found 'abc'"
+    );
+
+    let err = assemble(".space 0x7fffffff\n.space 0x7fffffff\nnop\nnop").unwrap_err();
+    assert_eq!(err.line, 3);
+    assert_eq!(
+        err.message,
+        "program runs past the end of the address space"
+    );
+}
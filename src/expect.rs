@@ -0,0 +1,146 @@
+use std::fmt::Write;
+
+use crate::{vm::ArchState, watch};
+
+#[cfg(test)]
+mod expect_tests;
+
+/// One expected value, `expr` is any watch expression, see [`watch::evaluate`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expectation {
+    pub expr: String,
+    pub value: u32,
+}
+
+/// Parses a flat JSON object such as `{"x10": 42, "pc": "0x1040"}`.
+///
+/// Values are JSON numbers or strings holding a decimal or `0x` hex number.
+/// Negative numbers wrap to their two's complement.
+pub fn parse(json: &str) -> Result<Vec<Expectation>, String> {
+    let mut parser = Parser { src: json, pos: 0 };
+    let mut expectations = vec![];
+    parser.expect("{")?;
+    if !parser.eat("}") {
+        loop {
+            let expr = parser.string()?;
+            parser.expect(":")?;
+            let value = if parser.peek() == Some('"') {
+                parser.string()?
+            } else {
+                parser.bare()
+            };
+            expectations.push(Expectation {
+                value: parse_number(&value)?,
+                expr,
+            });
+            if parser.eat("}") {
+                break;
+            }
+            parser.expect(",")?;
+        }
+    }
+    parser.skip_whitespace();
+    if parser.pos < json.len() {
+        return Err(format!("unexpected '{}'", &json[parser.pos..]));
+    }
+    Ok(expectations)
+}
+
+/// Checks every expectation against `state`, returning one line per mismatch
+pub fn check(state: &ArchState, expectations: &[Expectation]) -> Vec<String> {
+    let mut diff = vec![];
+    for expectation in expectations {
+        let mut line = String::new();
+        match watch::evaluate(&expectation.expr, state) {
+            Ok(actual) if actual == expectation.value => continue,
+            Ok(actual) => {
+                let _ = write!(
+                    line,
+                    "{}: expected 0x{:08x}, got 0x{:08x}",
+                    expectation.expr, expectation.value, actual
+                );
+            }
+            Err(err) => {
+                let _ = write!(line, "{}: {}", expectation.expr, err);
+            }
+        }
+        diff.push(line);
+    }
+    diff
+}
+
+fn parse_number(text: &str) -> Result<u32, String> {
+    let parsed = if let Some(hex) = text.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).map_err(|err| err.to_string())
+    } else if text.starts_with('-') {
+        text.parse::<i32>()
+            .map(|val| val as u32)
+            .map_err(|err| err.to_string())
+    } else {
+        text.parse::<u32>().map_err(|err| err.to_string())
+    };
+    parsed.map_err(|err| format!("'{}': {}", text, err))
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.src.len() - self.rest().trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    /// Consumes `token` if the input continues with it
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at '{}'", token, self.rest()))
+        }
+    }
+
+    /// A quoted string, escapes aren't supported since keys are watch expressions
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let len = self
+            .rest()
+            .find('"')
+            .ok_or_else(|| "unterminated string".to_string())?;
+        let text = self.rest()[..len].to_string();
+        self.pos += len + 1;
+        Ok(text)
+    }
+
+    /// An unquoted value, up to the next separator
+    fn bare(&mut self) -> String {
+        self.skip_whitespace();
+        let len = self
+            .rest()
+            .find(|c: char| c == ',' || c == '}' || c.is_whitespace())
+            .unwrap_or(self.rest().len());
+        let text = self.rest()[..len].to_string();
+        self.pos += len;
+        text
+    }
+}
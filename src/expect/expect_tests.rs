@@ -0,0 +1,52 @@
+use crate::{test_fixtures, vm::ArchState};
+
+use super::{Expectation, check, parse};
+
+/// Runs the accumulator program off the end of memory
+fn accumulator() -> ArchState {
+    let mut state = test_fixtures::accumulator();
+    state.run(u64::MAX);
+    state
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(
+        parse(r#"{"x10": 42, "pc": "0x1040", "mem[0x10]:byte": -1}"#),
+        Ok(vec![
+            Expectation {
+                expr: "x10".to_string(),
+                value: 42
+            },
+            Expectation {
+                expr: "pc".to_string(),
+                value: 0x1040
+            },
+            Expectation {
+                expr: "mem[0x10]:byte".to_string(),
+                value: 0xFFFFFFFF
+            },
+        ])
+    );
+    assert_eq!(parse(" { } "), Ok(vec![]));
+    assert!(parse(r#"{"x1": 1"#).is_err());
+    assert!(parse(r#"{"x1": "one"}"#).is_err());
+    assert!(parse(r#"{"x1": 1} trailing"#).is_err());
+}
+
+#[test]
+fn test_check() {
+    let state = accumulator();
+
+    let passing = parse(r#"{"x1": 64, "pc": "0x100", "x3": 0}"#).unwrap();
+    assert!(check(&state, &passing).is_empty());
+
+    let failing = parse(r#"{"x1": 63, "pc": "0x100", "x32": 0}"#).unwrap();
+    assert_eq!(
+        check(&state, &failing),
+        vec![
+            "x1: expected 0x0000003f, got 0x00000040".to_string(),
+            "x32: unknown register 'x32'".to_string(),
+        ]
+    );
+}
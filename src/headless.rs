@@ -0,0 +1,199 @@
+use std::{cmp::Ordering, fmt::Write, io};
+
+use crate::vm::{self, ArchState, Instruction, InstructionStats, RunReport, Segment};
+
+#[cfg(test)]
+mod headless_tests;
+
+/// Loads `program` and runs at most `max` instructions of it, for the `run` subcommand.
+/// Returns the final state and instruction counts as text, or as JSON if `json` is set
+pub fn run_program(
+    state: &mut ArchState,
+    program: &[Segment],
+    max: u64,
+    json: bool,
+) -> io::Result<String> {
+    state.load_segments(program)?;
+    let report = state.run(max);
+    Ok(if json {
+        report_json(&report, state.stats())
+    } else {
+        report_text(&report) + &state.stats().report()
+    })
+}
+
+/// The stop reason and exit status, then one register per line
+pub fn report_text(report: &RunReport) -> String {
+    let mut out = format!("instructions run {} ({})\n", report.steps, report.stop);
+    match report.exit_status {
+        Some(status) => writeln!(out, "exit status {}", status).unwrap(),
+        None => writeln!(out, "exit status none").unwrap(),
+    }
+    writeln!(out, "pc  0x{:08x}", report.pc).unwrap();
+    for (index, reg) in report.registers.iter().enumerate() {
+        writeln!(out, "x{: <2} 0x{:08x}", index, reg).unwrap();
+    }
+    out
+}
+
+/// Same as [`report_text`] as one JSON object, registers are keyed `x0` to `x31`
+/// and the instruction counts go in a nested `stats` object
+pub fn report_json(report: &RunReport, stats: &InstructionStats) -> String {
+    let stop = report
+        .stop
+        .to_string()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"");
+    let exit_status = report
+        .exit_status
+        .map_or("null".to_string(), |status| status.to_string());
+    let mut out = format!(
+        "{{\"steps\": {}, \"stop\": \"{}\", \"exit_status\": {}, \"pc\": {}",
+        report.steps, stop, exit_status, report.pc
+    );
+    for (index, reg) in report.registers.iter().enumerate() {
+        write!(out, ", \"x{}\": {}", index, reg).unwrap();
+    }
+    write!(
+        out,
+        ", \"stats\": {{\"arithmetic\": {}, \"loads\": {}, \"stores\": {}, \"branches_taken\": {}, \
+         \"branches_not_taken\": {}, \"jumps\": {}, \"system\": {}}}",
+        stats.arithmetic,
+        stats.loads,
+        stats.stores,
+        stats.branches_taken,
+        stats.branches_not_taken,
+        stats.jumps,
+        stats.system
+    )
+    .unwrap();
+    out.push_str("}\n");
+    out
+}
+
+/// The program run when no file is given
+pub fn demo_program() -> Vec<Segment> {
+    vec![
+        Segment::from_bytes(
+            vec![
+                0x93, 0x00, 0x80, 0x3e, 0x13, 0x81, 0x00, 0x7d, 0x93, 0x01, 0x81, 0xc1, 0x13, 0x82,
+                0x01, 0x83, 0x93, 0x02, 0x82, 0x3e, 0x17, 0x03, 0x01, 0x00, 0x13, 0x03, 0xc3, 0xfe,
+                0x13, 0x03, 0x43, 0x00, 0x83, 0x23, 0x03, 0x00,
+            ],
+            0,
+        ),
+        Segment::from_bytes(0xdeadbeef_u32.to_le_bytes().to_vec(), 0x10004),
+    ]
+}
+
+/// One line describing the word at `addr` and what it decodes to
+pub fn decode_at(state: &ArchState, addr: u32) -> String {
+    match state.fetch(addr as usize) {
+        Some(word) => match vm::try_interpret_bytes(word) {
+            Some(inst) => format!("{:08x}: {:08x} {}", addr, word, inst),
+            None => format!("{:08x}: {:08x} unknown", addr, word),
+        },
+        None => format!("{:08x}: outside memory", addr),
+    }
+}
+
+/// How much of a blob decodes to known instructions under one byte order
+pub struct EndianCount {
+    pub valid: usize,
+    pub total: usize,
+    /// The first few words and their decode, `None` where the word isn't a known instruction
+    pub sample: Vec<(u32, Option<Instruction>)>,
+}
+
+/// Decodes every whole word in `bytes` with the given byte order
+pub fn count_valid(bytes: &[u8], little_endian: bool) -> EndianCount {
+    let words = bytes.chunks_exact(4).map(|chunk| {
+        let chunk = [chunk[0], chunk[1], chunk[2], chunk[3]];
+        if little_endian {
+            u32::from_le_bytes(chunk)
+        } else {
+            u32::from_be_bytes(chunk)
+        }
+    });
+    let mut count = EndianCount {
+        valid: 0,
+        total: 0,
+        sample: vec![],
+    };
+    for word in words {
+        let inst = vm::try_interpret_bytes(word);
+        count.total += 1;
+        if inst.is_some() {
+            count.valid += 1;
+        }
+        if count.sample.len() < 8 {
+            count.sample.push((word, inst));
+        }
+    }
+    count
+}
+
+/// Report comparing how well a blob decodes as little and big endian
+pub fn compare_endian(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    let little = count_valid(bytes, true);
+    let big = count_valid(bytes, false);
+    for (name, count) in [("little endian", &little), ("big endian", &big)] {
+        let _ = writeln!(
+            out,
+            "{}: {}/{} valid instructions",
+            name, count.valid, count.total
+        );
+        for (word, inst) in &count.sample {
+            match inst {
+                Some(inst) => {
+                    let _ = writeln!(out, "  {:08x} {}", word, inst);
+                }
+                None => {
+                    let _ = writeln!(out, "  {:08x} unknown", word);
+                }
+            }
+        }
+    }
+    let _ = writeln!(
+        out,
+        "{}",
+        match little.valid.cmp(&big.valid) {
+            Ordering::Greater => "likely little endian",
+            Ordering::Less => "likely big endian",
+            Ordering::Equal => "inconclusive",
+        }
+    );
+    out
+}
+
+/// Parses a memory region written `ADDR:LEN`, with the address in hex and the length
+/// a size like `256` or `4K`, e.g. `0x1000:4K`
+pub fn parse_region(arg: &str) -> Result<(u32, usize), String> {
+    let (addr, len) = arg
+        .split_once(':')
+        .ok_or_else(|| format!("expected 'ADDR:LEN', found '{}'", arg))?;
+    let addr = addr.trim();
+    let addr = u32::from_str_radix(addr.strip_prefix("0x").unwrap_or(addr), 16)
+        .map_err(|_| format!("'{}' isn't a hex address", addr))?;
+    Ok((addr, parse_size(len)?))
+}
+
+/// Parses a memory size such as `4096`, `64K`, `1M`, or `4G` into bytes.
+/// Suffixes are powers of 1024 and the size has to fit the 32 bit address space
+pub fn parse_size(arg: &str) -> Result<usize, String> {
+    let arg = arg.trim();
+    let (digits, scale) = match arg.char_indices().last() {
+        Some((at, 'k' | 'K')) => (&arg[..at], 1 << 10),
+        Some((at, 'm' | 'M')) => (&arg[..at], 1 << 20),
+        Some((at, 'g' | 'G')) => (&arg[..at], 1 << 30),
+        _ => (arg, 1),
+    };
+    let size = digits
+        .parse::<u64>()
+        .map_err(|err| format!("invalid size '{}': {}", arg, err))?
+        .checked_mul(scale)
+        .filter(|size| (1..=1 << 32).contains(size))
+        .ok_or_else(|| format!("size '{}' must be between 1 byte and 4G", arg))?;
+    Ok(size as usize)
+}
@@ -0,0 +1,75 @@
+use crate::vm::ArchState;
+
+use super::{
+    compare_endian, count_valid, decode_at, demo_program, parse_region, parse_size, run_program,
+};
+
+#[test]
+fn test_compare_endian() {
+    // addi x1, x0, 1000; addi x2, x1, 2000; add x3, x1, x2; sw x3, 0(x0) as a toolchain emits them
+    let words = [0x3e800093_u32, 0x7d008113, 0x002081b3, 0x00302023];
+    let blob = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect::<Vec<u8>>();
+
+    let little = count_valid(&blob, true);
+    let big = count_valid(&blob, false);
+    assert_eq!(little.total, 4);
+    assert_eq!(little.valid, 4);
+    assert!(little.valid > big.valid);
+    assert!(compare_endian(&blob).ends_with("likely little endian\n"));
+}
+
+#[test]
+fn test_decode_at() {
+    let mut state = ArchState::with_mem(2_usize.pow(17));
+    state.load_segments(&demo_program()).unwrap();
+    assert_eq!(
+        decode_at(&state, 0),
+        "00000000: 3e800093 ADDI rd:  x1/ra | rs1: x0/zero | imm: 0b001111101000"
+    );
+    assert_eq!(decode_at(&state, 0x10000), "00010000: 00000000 unknown");
+    assert_eq!(decode_at(&state, 2_u32.pow(17)), "00020000: outside memory");
+}
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("4096"), Ok(4096));
+    assert_eq!(parse_size("64K"), Ok(64 * 1024));
+    assert_eq!(parse_size("1m"), Ok(1024 * 1024));
+    assert_eq!(parse_size("4G"), Ok(1 << 32));
+    assert!(parse_size("").is_err());
+    assert!(parse_size("M").is_err());
+    assert!(parse_size("1.5M").is_err());
+    assert!(parse_size("12Q").is_err());
+    assert!(parse_size("0").is_err());
+    assert!(parse_size("5G").is_err());
+}
+
+#[test]
+fn test_parse_region() {
+    assert_eq!(parse_region("0x1000:4K"), Ok((0x1000, 4096)));
+    assert_eq!(parse_region("ff:16"), Ok((0xff, 16)));
+    assert!(parse_region("0x1000").is_err());
+    assert!(parse_region("zz:16").is_err());
+    assert!(parse_region("0x10:0").is_err());
+}
+
+#[test]
+fn test_run_program() {
+    // the demo loads 0xdeadbeef into x7 with its ninth instruction
+    let mut state = ArchState::with_mem(2_usize.pow(20));
+    let text = run_program(&mut state, &demo_program(), 9, false).unwrap();
+    assert!(text.starts_with("instructions run 9 (instruction budget exhausted)\n"));
+    assert!(text.contains("exit status none\n"));
+    assert!(text.contains("x7  0xdeadbeef\n"));
+    assert!(text.contains("  loads                       1  11.1%\n"));
+
+    let mut state = ArchState::with_mem(2_usize.pow(20));
+    let json = run_program(&mut state, &demo_program(), 9, true).unwrap();
+    assert!(json.starts_with("{\"steps\": 9, "));
+    assert!(json.contains("\"exit_status\": null"));
+    assert!(json.contains(&format!("\"x7\": {}", 0xdeadbeef_u32)));
+    assert!(json.ends_with("\"stats\": {\"arithmetic\": 8, \"loads\": 1, \"stores\": 0, \"branches_taken\": 0, \"branches_not_taken\": 0, \"jumps\": 0, \"system\": 0}}\n"));
+}
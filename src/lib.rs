@@ -0,0 +1,9 @@
+pub mod asm;
+pub mod expect;
+pub mod headless;
+pub mod ui;
+pub mod vm;
+pub mod watch;
+
+#[cfg(test)]
+mod test_fixtures;
@@ -1,33 +1,358 @@
-use std::{error::Error, fs, path::PathBuf};
+use std::{collections::BTreeMap, error::Error, fs, path::PathBuf};
 
-use clap::{arg, command, Arg, ValueHint};
+use clap::{Arg, ArgAction, ArgMatches, Command, ValueHint, command};
 use ratatui::crossterm::{event::DisableMouseCapture, execute};
-
-mod ui;
-mod vm;
+use riscv_rust_emulator::{
+    expect, headless, ui,
+    vm::{self, ArchState, Segment, TimingModel},
+};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = command!()
         .arg(Arg::new("file").short('f').value_hint(ValueHint::FilePath))
+        .arg(
+            Arg::new("export-mem-hex")
+                .long("export-mem-hex")
+                .value_hint(ValueHint::FilePath)
+                .global(true)
+                .help("Write the final memory image as a $readmemh hex file on exit"),
+        )
+        .arg(
+            Arg::new("step")
+                .long("step")
+                .value_parser(clap::value_parser!(u64))
+                .help("Run exactly this many instructions without the TUI, then print the state"),
+        )
+        .arg(
+            Arg::new("watch")
+                .long("watch")
+                .action(ArgAction::Append)
+                .help(
+                    "Expression to show in the watch panel, e.g. 'mem[0x1000]:word' or 'x10 + x11'",
+                ),
+        )
+        .arg(
+            Arg::new("reset-vector")
+                .long("reset-vector")
+                .visible_alias("entry")
+                .value_parser(parse_address)
+                .help("Address pc starts at after loading or reset instead of 0 or the file's entry point, e.g. 0x1000"),
+        )
+        .arg(
+            Arg::new("halt-addr")
+                .long("halt-addr")
+                .value_parser(parse_address)
+                .global(true)
+                .help("Halt when a store hits this address, exiting with the stored value"),
+        )
+        .arg(
+            Arg::new("decode-at")
+                .long("decode-at")
+                .value_parser(parse_address)
+                .help("Print the decoded instruction at this address of the loaded program, then exit"),
+        )
+        .arg(
+            Arg::new("expect")
+                .long("expect")
+                .value_hint(ValueHint::FilePath)
+                .help("Check the state after the run against a JSON file, e.g. {\"x10\": 42}"),
+        )
+        .arg(
+            Arg::new("run")
+                .long("run")
+                .action(ArgAction::SetTrue)
+                .help("Start the TUI running instead of paused"),
+        )
+        .arg(
+            Arg::new("view-limit")
+                .long("view-limit")
+                .value_parser(parse_address)
+                .help("Only scroll the memory view up to this address, e.g. 0x20000. [ and ] adjust it"),
+        )
+        .arg(
+            Arg::new("random-regs")
+                .long("random-regs")
+                .value_parser(clap::value_parser!(u64))
+                .global(true)
+                .help("Fill x1..x31 with random values from this seed instead of zero on reset"),
+        )
+        .arg(
+            Arg::new("mem-size")
+                .long("mem-size")
+                .value_parser(headless::parse_size)
+                .default_value("1M")
+                .global(true)
+                .help("Bytes of memory to allocate, e.g. 64K or 4G. Programs must fit below it"),
+        )
+        .arg(
+            Arg::new("trace")
+                .long("trace")
+                .value_hint(ValueHint::FilePath)
+                .global(true)
+                .help("Log every instruction with the registers and memory it changed to this file"),
+        )
+        .arg(
+            Arg::new("stack-reserved")
+                .long("stack-reserved")
+                .value_parser(clap::value_parser!(u32))
+                .global(true)
+                .help("Bytes to leave free above the stack, sp starts this far below the top of memory [default: 16]"),
+        )
+        .arg(
+            Arg::new("input")
+                .long("input")
+                .value_hint(ValueHint::FilePath)
+                .global(true)
+                .help("Queue this file's bytes for the program to read from the input device at 0x10000010"),
+        )
+        .arg(
+            Arg::new("idle-limit")
+                .long("idle-limit")
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .global(true)
+                .help("Pause when pc and the registers stay the same for this many instructions in a row, e.g. on 'j .'"),
+        )
+        .arg(
+            Arg::new("timing")
+                .long("timing")
+                .action(ArgAction::SetTrue)
+                .global(true)
+                .help("Count cycles by instruction class, e.g. 2 per load and 4 per multiply, instead of 1 each"),
+        )
+        .arg(
+            Arg::new("dump-mem")
+                .long("dump-mem")
+                .num_args(2)
+                .value_names(["ADDR:LEN", "FILE"])
+                .global(true)
+                .help("Write this region of memory to a file once the run ends, e.g. 0x1000:4K out.bin"),
+        )
+        .arg(
+            Arg::new("compare-endian")
+                .long("compare-endian")
+                .action(ArgAction::SetTrue)
+                .help(
+                    "Report whether each segment decodes better as little or big endian, then exit",
+                ),
+        )
+        .subcommand(
+            Command::new("run")
+                .about("Run without the TUI and print the final registers and exit status")
+                .arg(Arg::new("file").short('f').value_hint(ValueHint::FilePath))
+                .arg(
+                    Arg::new("entry")
+                        .long("entry")
+                        .value_parser(parse_address)
+                        .help("Address to start at instead of 0 or the ELF entry point"),
+                )
+                .arg(
+                    Arg::new("max-insns")
+                        .long("max-insns")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("Stop after this many instructions, by default runs until exit"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the final state as a JSON object"),
+                ),
+        )
         .get_matches();
-    let default_program = if let Some(file) = args.get_one::<String>("file") {
-        vec![(fs::read(file).unwrap(), 0)]
-    } else {
-        vec![
-            (
-                vec![
-                    0x3e, 0x80, 0x00, 0x93, 0x7d, 0x00, 0x81, 0x13, 0xc1, 0x81, 0x01, 0x93, 0x83,
-                    0x01, 0x82, 0x13, 0x3e, 0x82, 0x02, 0x93, 0x00, 0x01, 0x03, 0x17, 0xfe, 0xc3,
-                    0x03, 0x13, 0x00, 0x43, 0x03, 0x13, 0x00, 0x03, 0x23, 0x83,
-                ],
-                0,
-            ),
-            (vec![0xde, 0xad, 0xbe, 0xef], 0x10004),
-        ]
-    };
 
-    let res = ui::GUI::run_tui(default_program);
+    if let Some(("run", run_args)) = args.subcommand() {
+        return run(run_args);
+    }
+
+    let mem_size = *args.get_one::<usize>("mem-size").unwrap();
+    let (default_program, entry, symbols) = load_program(args.get_one::<String>("file"), mem_size)?;
+
+    if args.get_flag("compare-endian") {
+        for segment in &default_program {
+            println!("segment at 0x{:08x}", segment.offset);
+            print!("{}", headless::compare_endian(&(segment.source)()?));
+        }
+        return Ok(());
+    }
+
+    let entry = args.get_one::<u32>("reset-vector").copied().or(entry);
+    let mut state = build_state(&args, entry, symbols)?;
+
+    if let Some(addr) = args.get_one::<u32>("decode-at") {
+        state.load_segments(&default_program)?;
+        println!("{}", headless::decode_at(&state, *addr));
+        return Ok(());
+    }
+
+    // stdout belongs to the TUI, so only batch runs get host syscalls
+    if args.contains_id("expect") || args.contains_id("step") {
+        state.set_syscall_handler(Box::new(vm::HostSyscalls));
+    }
+
+    if let Some(path) = args.get_one::<String>("expect") {
+        let expectations = expect::parse(&fs::read_to_string(path)?)?;
+        state.load_segments(&default_program)?;
+        let steps = args.get_one::<u64>("step").copied().unwrap_or(u64::MAX);
+        println!("instructions run {}", state.run(steps).steps);
+        state.set_trace(None);
+        write_dumps(&state, &args)?;
+        let diff = expect::check(&state, &expectations);
+        if diff.is_empty() {
+            println!("all {} expectations met", expectations.len());
+            return Ok(());
+        }
+        for line in diff {
+            println!("{}", line);
+        }
+        std::process::exit(1);
+    }
+
+    if let Some(steps) = args.get_one::<u64>("step") {
+        state.load_segments(&default_program)?;
+        let report = state.run(*steps);
+        println!("instructions run {} ({})", report.steps, report.stop);
+        print!("{}{}", state.summary(), state.stats().report());
+        state.set_trace(None);
+        write_dumps(&state, &args)?;
+        if let Some(status) = state.exit_status {
+            std::process::exit(status as i32);
+        }
+        return Ok(());
+    }
+
+    let watches = args
+        .get_many::<String>("watch")
+        .map(|exprs| exprs.cloned().collect())
+        .unwrap_or_default();
+    let res = ui::GUI::run_tui(
+        state,
+        default_program,
+        watches,
+        !args.get_flag("run"),
+        args.get_one::<u32>("view-limit")
+            .map(|limit| *limit as usize),
+    );
     ratatui::restore();
     execute!(std::io::stdout(), DisableMouseCapture)?;
-    res
+    let state = res?;
+
+    write_dumps(&state.lock().unwrap(), &args)?;
+    state.lock().unwrap().set_trace(None);
+    if let Some(status) = state.lock().unwrap().exit_status {
+        std::process::exit(status as i32);
+    }
+    Ok(())
+}
+
+/// The `run` subcommand, exits with the program's exit status if it set one
+fn run(args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let mem_size = *args.get_one::<usize>("mem-size").unwrap();
+    let (program, entry, symbols) = load_program(args.get_one::<String>("file"), mem_size)?;
+    let entry = args.get_one::<u32>("entry").copied().or(entry);
+    let mut state = build_state(args, entry, symbols)?;
+    state.set_syscall_handler(Box::new(vm::HostSyscalls));
+    let max = args
+        .get_one::<u64>("max-insns")
+        .copied()
+        .unwrap_or(u64::MAX);
+    print!(
+        "{}",
+        headless::run_program(&mut state, &program, max, args.get_flag("json"))?
+    );
+    // exiting skips destructors, so flush the trace first
+    state.set_trace(None);
+    write_dumps(&state, args)?;
+    if let Some(status) = state.exit_status {
+        std::process::exit(status as i32);
+    }
+    Ok(())
+}
+
+/// A machine set up from the flags the TUI and the `run` subcommand share, starting at `entry`
+/// if one is given
+fn build_state(
+    args: &ArgMatches,
+    entry: Option<u32>,
+    symbols: BTreeMap<u32, String>,
+) -> Result<ArchState, Box<dyn Error>> {
+    let mut state = ArchState::with_mem(*args.get_one::<usize>("mem-size").unwrap());
+    if let Some(entry) = entry {
+        state.set_entry(entry);
+    }
+    state.halt_addr = args.get_one::<u32>("halt-addr").copied();
+    state.register_seed = args.get_one::<u64>("random-regs").copied();
+    state.symbols = symbols;
+    if let Some(reserved) = args.get_one::<u32>("stack-reserved") {
+        state.stack_reserved = Some(*reserved);
+    }
+    state.idle_limit = args.get_one::<u32>("idle-limit").copied();
+    install_input(&mut state, args)?;
+    install_trace(&mut state, args)?;
+    if args.get_flag("timing") {
+        state.set_timing(Some(TimingModel::default()));
+    }
+    Ok(state)
+}
+
+/// Points the trace at the `--trace` file, if one was given
+fn install_trace(state: &mut ArchState, args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = args.get_one::<String>("trace") {
+        state.set_trace(Some(vm::trace_file(path)?));
+    }
+    Ok(())
+}
+
+/// Maps an input device holding the `--input` file at [`vm::INPUT_ADDR`], if one was given
+fn install_input(state: &mut ArchState, args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = args.get_one::<String>("input") {
+        let device = vm::InputDevice::new(&fs::read(path)?);
+        state.map_mmio(vm::INPUT_ADDR..vm::INPUT_ADDR + 8, Box::new(device));
+    }
+    Ok(())
+}
+
+/// Writes the `--export-mem-hex` image and the `--dump-mem` region, whichever were given
+fn write_dumps(state: &ArchState, args: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = args.get_one::<String>("export-mem-hex") {
+        fs::write(path, state.export_mem_hex())?;
+    }
+    if let Some(mut values) = args.get_many::<String>("dump-mem") {
+        let (addr, len) = headless::parse_region(values.next().unwrap())?;
+        fs::write(values.next().unwrap(), state.dump(addr, len))?;
+    }
+    Ok(())
+}
+
+/// Segments to load, the entry point, and the symbol table
+type Program = (Vec<Segment>, Option<u32>, BTreeMap<u32, String>);
+
+/// Reads `file`, or the demo program if there isn't one, along with its entry point and symbols.
+/// ELF files and Intel HEX or S-record files with a start address start there, flat binaries
+/// are loaded at 0. ELF segments must fit in `mem_size` bytes
+fn load_program(file: Option<&String>, mem_size: usize) -> Result<Program, Box<dyn Error>> {
+    let Some(file) = file else {
+        return Ok((headless::demo_program(), None, BTreeMap::new()));
+    };
+    let path = PathBuf::from(file);
+    let data = fs::read(&path)?;
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    if data.starts_with(b"\x7fELF") {
+        let (entry, segments) = vm::elf_segments(&data, mem_size)?;
+        Ok((segments, Some(entry), vm::elf_symbols(&data)?))
+    } else if let Some("hex" | "ihex" | "ihx") = extension.as_deref() {
+        let (entry, segments) = vm::ihex_segments(&String::from_utf8_lossy(&data))?;
+        Ok((segments, entry, BTreeMap::new()))
+    } else if let Some("srec" | "s19" | "s28" | "s37" | "mot") = extension.as_deref() {
+        let (entry, segments) = vm::srec_segments(&String::from_utf8_lossy(&data))?;
+        Ok((segments, entry, BTreeMap::new()))
+    } else {
+        Ok((vec![Segment::from_file(path, 0)], None, BTreeMap::new()))
+    }
+}
+
+/// Parses an address given in hex, with or without a `0x` prefix
+fn parse_address(arg: &str) -> Result<u32, String> {
+    u32::from_str_radix(arg.trim_start_matches("0x"), 16).map_err(|err| err.to_string())
 }
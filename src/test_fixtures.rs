@@ -0,0 +1,13 @@
+//! Programs shared by the unit tests of several modules
+
+use crate::vm::ArchState;
+
+/// 256 bytes of memory filled with `addi x1, x1, 1`, so x1 counts the instructions run
+/// until fetching past the end stops it
+pub(crate) fn accumulator() -> ArchState {
+    const MEM: usize = 2_usize.pow(8);
+    let mut state = ArchState::with_mem(MEM);
+    let op = 0x00108093_u32;
+    state.load((0..MEM / 4).flat_map(|_| op.to_le_bytes()).collect(), 0);
+    state
+}
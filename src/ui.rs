@@ -1,9 +1,10 @@
 use std::{
     error::Error,
+    fs,
     io::Stdout,
-    os::linux::raw::stat,
     sync::{
         Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
         mpsc::{Receiver, Sender, channel},
     },
     thread,
@@ -18,24 +19,343 @@ use ratatui::{
         },
         execute,
     },
-    layout::{Constraint, Layout, Margin, Position, Rect},
+    layout::{Constraint, Layout, Position, Rect},
     prelude::CrosstermBackend,
     style::{Color, Style, Stylize},
-    text::Text,
+    text::{Line, Text},
     widgets::{
-        Block, Cell, Row, ScrollDirection, Scrollbar, ScrollbarOrientation, ScrollbarState, Table,
-        TableState,
+        Block, Cell, Clear, Row, ScrollDirection, Scrollbar, ScrollbarOrientation, ScrollbarState,
+        Table, TableState,
     },
 };
 
-use crate::vm::{self, ArchState, Instruction};
+use crate::{
+    asm, headless,
+    vm::{self, ArchState, Instruction, InstructionStats, Segment, VmError, VmEvent},
+    watch,
+};
+
+mod trail;
+use trail::{Flow, PcTrail, TRAIL_LIMIT, TrailEntry};
+
+#[cfg(test)]
+mod ui_tests;
+
+/// Shortens `text` to fit in `width` columns, marking the cut with an ellipsis
+fn truncate_to_width(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut out = text.chars().take(width - 1).collect::<String>();
+    out.push('…');
+    out
+}
+
+/// Decodes `rows` words around `center`, which sits in the middle row unless that would start
+/// the window below address 0. Words outside memory come back `None`
+fn code_window(state: &ArchState, center: u32, rows: usize) -> Vec<(u32, Option<Instruction>)> {
+    let before = (rows / 2) as u32 * 4;
+    let start = center.checked_sub(before).unwrap_or(center % 4);
+    (0..rows as u32)
+        .map(|row| start.wrapping_add(row * 4))
+        .map(|addr| {
+            let inst = state.fetch(addr as usize).map(vm::interpret_bytes);
+            (addr, inst)
+        })
+        .collect()
+}
+
+/// What the text being typed is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Prompt {
+    Goto,
+    /// Pattern to find in memory
+    Search,
+    /// New value for this register
+    Register(usize),
+    /// Region of memory and the file to write it to
+    Dump,
+}
+
+/// Parses a new value for register `index` in hex, with or without `0x`. x0 can't be changed
+fn parse_register_value(index: usize, text: &str) -> Result<u32, String> {
+    if index == 0 {
+        return Err("x0 is always zero".to_string());
+    }
+    let text = text.trim();
+    u32::from_str_radix(text.strip_prefix("0x").unwrap_or(text), 16)
+        .map_err(|_| format!("'{}' isn't a 32 bit hex value", text))
+}
+
+/// How register and watch values are written, `d` cycles through them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum NumberFormat {
+    #[default]
+    Hex,
+    Unsigned,
+    Signed,
+}
+
+impl NumberFormat {
+    fn next(self) -> Self {
+        match self {
+            NumberFormat::Hex => NumberFormat::Unsigned,
+            NumberFormat::Unsigned => NumberFormat::Signed,
+            NumberFormat::Signed => NumberFormat::Hex,
+        }
+    }
+
+    /// `val` padded to the same width for every value, so columns line up
+    fn format(self, val: u32) -> String {
+        match self {
+            NumberFormat::Hex => format!("0x{:0>8X}", val),
+            NumberFormat::Unsigned => format!("{:>10}", val),
+            NumberFormat::Signed => format!("{:>11}", vm::transmute_to_signed(val)),
+        }
+    }
+}
+
+/// How many bytes each memory table cell shows, `v` cycles through them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Grouping {
+    #[default]
+    Byte,
+    Half,
+    Word,
+}
+
+impl Grouping {
+    fn next(self) -> Self {
+        match self {
+            Grouping::Byte => Grouping::Half,
+            Grouping::Half => Grouping::Word,
+            Grouping::Word => Grouping::Byte,
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            Grouping::Byte => 1,
+            Grouping::Half => 2,
+            Grouping::Word => 4,
+        }
+    }
+}
+
+/// Hex for each `width` byte group of `bytes`, read little-endian like a load would.
+/// A short group at the end is read the same way
+fn group_bytes(bytes: &[u8], width: usize) -> Vec<String> {
+    bytes
+        .chunks(width)
+        .map(|group| {
+            group
+                .iter()
+                .rev()
+                .map(|byte| format!("{:02x}", byte))
+                .collect()
+        })
+        .collect()
+}
+
+/// One line per instruction kind, most common first, cut to `width`. The count comes
+/// first so it survives a narrow pane, and a bar scaled so the most common fills what
+/// is left follows the name
+fn stats_rows(stats: &InstructionStats, width: usize) -> Vec<String> {
+    let rows = stats.rows();
+    let most = rows[0].1.max(1);
+    // count and name columns, each followed by a space
+    let bar_width = width.saturating_sub(8 + 1 + 18 + 1) as u64;
+    rows.into_iter()
+        .map(|(name, count)| {
+            let bar = "#".repeat((count * bar_width / most) as usize);
+            let row = format!("{: >8} {: <18} {}", count, name, bar);
+            truncate_to_width(row.trim_end(), width)
+        })
+        .collect()
+}
+
+/// Where `g` moves the memory view
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum GotoTarget {
+    Addr(u32),
+    Pc,
+    Register(usize),
+}
+
+/// Parses what was typed after `g`: `pc`, a register like `sp` or `x2`, or a hex address
+/// with or without `0x`. Register names win over hex, so `a0` is the register
+fn parse_goto(text: &str) -> Result<GotoTarget, String> {
+    let text = text.trim();
+    if text == "pc" {
+        return Ok(GotoTarget::Pc);
+    }
+    if let Ok(index) = asm::parse_register(text) {
+        return Ok(GotoTarget::Register(index as usize));
+    }
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u32::from_str_radix(digits, 16)
+        .map(GotoTarget::Addr)
+        .map_err(|_| format!("can't go to '{}'", text))
+}
+
+/// Parses what was typed after `D`, a region like `0x1000:256` then the file to write it to
+fn parse_dump(text: &str) -> Result<(u32, usize, String), String> {
+    let (region, path) = text
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| "expected 'ADDR:LEN FILE'".to_string())?;
+    let (addr, len) = headless::parse_region(region)?;
+    Ok((addr, len, path.trim().to_string()))
+}
+
+/// Parses what was typed after `/` into the bytes to find. Bare hex bytes such as `de ad`
+/// are matched in memory order, `0x` words such as `0xdeadbeef` little-endian like a load
+/// sees them. Both can be mixed and run longer than a word
+fn parse_pattern(text: &str) -> Result<Vec<u8>, String> {
+    let mut pattern = vec![];
+    for token in text.split_whitespace() {
+        match token.strip_prefix("0x") {
+            Some(digits) if digits.len() <= 8 => {
+                let word = u32::from_str_radix(digits, 16)
+                    .map_err(|_| format!("'{}' isn't a hex word", token))?;
+                pattern.extend(word.to_le_bytes());
+            }
+            None if token.len() <= 2 => pattern.push(
+                u8::from_str_radix(token, 16)
+                    .map_err(|_| format!("'{}' isn't a hex byte", token))?,
+            ),
+            _ => return Err(format!("'{}' is neither a byte nor a word", token)),
+        }
+    }
+    if pattern.is_empty() {
+        return Err("nothing to search for".to_string());
+    }
+    Ok(pattern)
+}
+
+/// Ticks `state` once for the executor thread, recording the jump in `trail`. A fault is left
+/// in [`ArchState::fault`] and pauses the state rather than ending the thread, so a reload
+/// can run the program again
+fn execute_step(state: &mut ArchState, trail: &Mutex<PcTrail>) {
+    let pc = state.pc as u32;
+    let inst = state.get_instruction();
+    match (state.tick(), inst) {
+        (Ok(()), Ok(inst)) => trail.lock().unwrap().record(pc, &inst, state.pc as u32),
+        (Ok(()), Err(_)) => {}
+        (Err(err), _) => {
+            state.fault = Some(err);
+            state.paused = true;
+        }
+    }
+}
+
+/// Runs up to `count` instructions with [`execute_step`], stopping early once `state` pauses.
+/// Returns how many ran
+fn execute_batch(state: &mut ArchState, trail: &Mutex<PcTrail>, count: u64) -> u64 {
+    let mut ran = 0;
+    while ran < count && !state.paused {
+        execute_step(state, trail);
+        ran += 1;
+    }
+    ran
+}
+
+/// Body of the executor thread, runs `state` as `commands` say until `quit` fires or the UI
+/// hangs up. Returns the number of instructions run. Only a single instruction runs per
+/// `steps` message while paused
+fn run_executor(
+    state: &Mutex<ArchState>,
+    trail: &Mutex<PcTrail>,
+    speed: &AtomicU64,
+    commands: Receiver<ExecutorCommand>,
+    steps: Receiver<()>,
+    quit: Receiver<()>,
+) -> u64 {
+    let mut inst_count = 0;
+    let mut mode = ExecutorCommand::Pause;
+    while quit.try_recv().is_err() {
+        // a pause sent while running lands between batches
+        while let Ok(command) = commands.try_recv() {
+            mode = command;
+        }
+        while mode == ExecutorCommand::Pause && steps.try_recv().is_err() {
+            match commands.recv() {
+                Ok(command) => mode = command,
+                Err(_) => return inst_count,
+            }
+        }
+        let mut arch_state = state.lock().unwrap();
+        inst_count += match mode {
+            ExecutorCommand::Pause => {
+                execute_step(&mut arch_state, trail);
+                1
+            }
+            ExecutorCommand::Resume => {
+                execute_batch(&mut arch_state, trail, speed.load(Ordering::Relaxed))
+            }
+            ExecutorCommand::FreeRun => execute_batch(&mut arch_state, trail, FREE_RUN_BATCH),
+        };
+        if arch_state.paused {
+            mode = ExecutorCommand::Pause;
+        }
+        drop(arch_state);
+        if mode == ExecutorCommand::Resume {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    inst_count
+}
+
+/// Instructions the executor runs per batch when the TUI starts, it sleeps a millisecond
+/// between batches so this is roughly instructions per millisecond
+pub const DEFAULT_SPEED: u64 = 16;
+/// Fastest `+` goes, keeps a batch short enough that the UI can still take the lock
+const MAX_SPEED: u64 = 1 << 16;
+/// Batch size while free running, which doesn't sleep between batches
+const FREE_RUN_BATCH: u64 = MAX_SPEED;
+
+/// Sent to the executor thread whenever the pause state changes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutorCommand {
+    Pause,
+    /// Run at the speed set with `+` and `-`
+    Resume,
+    /// Run as fast as possible until a breakpoint, fault, or exit
+    FreeRun,
+}
+
+impl From<bool> for ExecutorCommand {
+    fn from(paused: bool) -> Self {
+        if paused {
+            ExecutorCommand::Pause
+        } else {
+            ExecutorCommand::Resume
+        }
+    }
+}
+
+/// Creates the pause channel with the starting state already queued for the executor
+fn executor_channel(paused: bool) -> (Sender<ExecutorCommand>, Receiver<ExecutorCommand>) {
+    let (sender, receiver) = channel();
+    let _ = sender.send(ExecutorCommand::from(paused));
+    (sender, receiver)
+}
 
 pub struct GUI {
     pause: bool,
     step: bool,
     terminal: Terminal<CrosstermBackend<Stdout>>,
-    pause_sender: Sender<bool>,
+    pause_sender: Sender<ExecutorCommand>,
     step_sender: Sender<()>,
+    segments: Vec<Segment>,
+    watches: Vec<String>,
+    /// Recently executed pcs, recorded by the executor thread
+    trail: Arc<Mutex<PcTrail>>,
+    /// Instructions per batch while running, read by the executor thread
+    speed: Arc<AtomicU64>,
 }
 
 #[derive(Default, Debug)]
@@ -45,110 +365,504 @@ struct GUIState {
     reg_table_state: TableState,
     reg_scroll_pos: usize,
     last_mouse_pos: Position,
+    status: Option<String>,
+    /// Fault the executor paused on, shown in red until the program is resumed or reloaded
+    last_error: Option<VmError>,
+    watch_rows: Vec<String>,
+    /// Bytes of memory the table can scroll through, the whole memory when `None`
+    view_limit: Option<usize>,
+    /// Start of the memory row or the instruction under the mouse, where `b` toggles a
+    /// breakpoint, `w` watchpoints, and `u` runs to
+    selected_addr: Option<u32>,
+    /// Byte changed by the store that last paused on a watchpoint, its row is highlighted
+    watch_hit: Option<u32>,
+    /// Copy of the pc trail for this frame, newest first
+    trail: Vec<TrailEntry>,
+    trail_table_state: TableState,
+    trail_scroll_pos: usize,
+    /// Set with `t`, the arrow keys scroll the trail instead of whatever is under the mouse
+    trail_focused: bool,
+    /// Text typed after `g` or enter on a register, keys go here instead of commands
+    /// until enter or escape
+    prompt: Option<(Prompt, String)>,
+    /// Register row under the mouse, enter edits it
+    selected_reg: Option<usize>,
+    /// Byte the last goto or search landed on, highlighted in the memory table
+    goto_hit: Option<u32>,
+    /// Where the last `/` search matched, `n` and `N` move through them
+    search_hits: Vec<u32>,
+    search_index: usize,
+    /// Set with `c`, the memory pane shows the code around pc instead
+    show_code: bool,
+    /// Instructions per millisecond while running, `+` and `-` double and halve it
+    speed: u64,
+    /// Set with `f`, running ignores `speed` and goes as fast as it can
+    free_run: bool,
+    /// How registers and watches are shown
+    number_format: NumberFormat,
+    /// Bytes per memory table cell
+    grouping: Grouping,
 }
 
 #[derive(Default, Debug)]
 struct Inputs {
     exit: bool,
     step: bool,
+    step_back: bool,
     toggle_pause: bool,
+    reload: bool,
+    toggle_breakpoint: bool,
+    run_to_cursor: bool,
+    step_over: bool,
+    toggle_watchpoint: bool,
+    shrink_view: bool,
+    grow_view: bool,
+    faster: bool,
+    slower: bool,
+    free_run: bool,
+    cycle_format: bool,
+    cycle_grouping: bool,
+    focus_trail: bool,
+    toggle_code: bool,
+    /// What was typed after `g` once enter is pressed
+    goto: Option<Result<GotoTarget, String>>,
+    /// What was typed after `/` once enter is pressed
+    search: Option<Result<Vec<u8>, String>>,
+    next_hit: bool,
+    prev_hit: bool,
+    edit_register: bool,
+    /// Register and the value typed for it once enter is pressed
+    set_register: Option<Result<(usize, u32), String>>,
+    /// Start, length, and file typed after `D` once enter is pressed
+    dump: Option<Result<(u32, usize, String), String>>,
     scroll_dir: Option<ScrollDirection>,
     mouse_loc: Option<(u16, u16)>,
 }
 
+impl GUIState {
+    /// Scrolls the memory table to `addr` and highlights it
+    fn show(&mut self, addr: u32) {
+        // the view limit would clamp the scroll short of the address
+        if self.view_limit.is_some_and(|limit| addr as usize >= limit) {
+            self.view_limit = None;
+        }
+        self.mem_scroll_pos = addr as usize / 16;
+        self.goto_hit = Some(addr);
+    }
+
+    /// Moves a fault the executor paused on into `last_error`, returning whether there was one
+    fn take_fault(&mut self, state: &mut ArchState) -> bool {
+        let Some(err) = state.fault.take() else {
+            return false;
+        };
+        self.last_error = Some(err);
+        self.status = Some("paused on fault, r reloads".to_string());
+        true
+    }
+
+    /// Lists every queued watched write in the status line, oldest first, and highlights
+    /// the last one. Returns whether there were any
+    fn take_watch_events(&mut self, state: &mut ArchState) -> bool {
+        let Some(last) = state.watch_events.back() else {
+            return false;
+        };
+        self.watch_hit = Some(last.addr);
+        self.status = Some(
+            state
+                .watch_events
+                .drain(..)
+                .map(|event| {
+                    format!(
+                        "0x{:08x} wrote 0x{:02x} over 0x{:02x} at 0x{:08x}",
+                        event.pc, event.new, event.old, event.addr
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("; "),
+        );
+        true
+    }
+
+    /// Clears what the last stop highlighted once execution carries on
+    fn resume(&mut self) {
+        self.last_error = None;
+        self.watch_hit = None;
+        self.goto_hit = None;
+    }
+
+    fn search_status(&self) -> String {
+        format!(
+            "match {}/{} at 0x{:08x}",
+            self.search_index + 1,
+            self.search_hits.len(),
+            self.search_hits[self.search_index]
+        )
+    }
+}
+
 impl GUI {
     /// (GUI, Pause Reciever, Step Receiver)
-    /// Pause reveiver will send a boolean indicating execution should be paused when the value changes
+    /// Pause reveiver will send a command when the pause state changes, starting with `paused`
     /// Step reciever will send a blank value when a step should be executed, and should not send when unpaused
-    pub fn new() -> (Self, Receiver<bool>, Receiver<()>) {
-        let (pause_sender, pause_recv) = std::sync::mpsc::channel();
+    pub fn new(
+        segments: Vec<Segment>,
+        watches: Vec<String>,
+        paused: bool,
+    ) -> (Self, Receiver<ExecutorCommand>, Receiver<()>) {
+        let (pause_sender, pause_recv) = executor_channel(paused);
         let (step_sender, step_recv) = std::sync::mpsc::channel();
         (
             Self {
-                pause: true,
+                pause: paused,
                 step: false,
                 terminal: ratatui::init(),
                 pause_sender,
                 step_sender,
+                segments,
+                watches,
+                trail: Arc::new(Mutex::new(PcTrail::new(TRAIL_LIMIT))),
+                speed: Arc::new(AtomicU64::new(DEFAULT_SPEED)),
             },
             pause_recv,
             step_recv,
         )
     }
 
-    pub fn run_tui(to_load: Vec<(Vec<u8>, usize)>) -> Result<(), Box<dyn Error>> {
-        let mut state = ArchState::new();
-        for data in to_load {
-            state.load(data.0, data.1);
-        }
+    /// Loads the segments into `state` and runs the TUI until the user quits, returning the final machine state.
+    /// Each watch expression is re-evaluated and shown every frame, see [`watch::evaluate`].
+    /// Execution starts right away unless `paused` is set, running [`DEFAULT_SPEED`] instructions
+    /// per millisecond until `+` and `-` change it.
+    /// The memory table only scrolls through the first `view_limit` bytes, `[` and `]` halve and double it
+    pub fn run_tui(
+        mut state: ArchState,
+        to_load: Vec<Segment>,
+        watches: Vec<String>,
+        paused: bool,
+        view_limit: Option<usize>,
+    ) -> Result<Arc<Mutex<ArchState>>, Box<dyn Error>> {
+        state.load_segments(&to_load)?;
+        state.interactive = true;
 
-        let (mut gui, pause_rx, step_rx) = GUI::new();
+        let (mut gui, pause_rx, step_rx) = GUI::new(to_load, watches, paused);
 
         let state_mutex = Arc::new(Mutex::new(state));
         let (quit_tx, quit_rx) = channel();
 
         let arch_state_mutex = Arc::clone(&state_mutex);
+        let trail = Arc::clone(&gui.trail);
+        let speed = Arc::clone(&gui.speed);
         let _ = thread::spawn(move || {
-            let mut inst_count = 0;
-            let mut pause = true;
-            while quit_rx.try_recv().is_err() {
-                while pause && step_rx.try_recv().is_err() {
-                    match pause_rx.recv() {
-                        Ok(b) => pause = b,
-                        Err(_) => {}
-                    }
-                }
-                inst_count += 1;
-                match arch_state_mutex.lock().unwrap().tick() {
-                    Ok(_) => {}
-                    Err(_) => break,
-                }
-            }
+            let inst_count = run_executor(
+                &arch_state_mutex,
+                &trail,
+                &speed,
+                pause_rx,
+                step_rx,
+                quit_rx,
+            );
             println!("instructions run {}", inst_count)
         });
 
-        gui.run_ui(Arc::clone(&state_mutex))?;
+        gui.run_ui(Arc::clone(&state_mutex), view_limit)?;
         quit_tx.send(())?;
-        Ok(())
+        Ok(state_mutex)
     }
 
-    fn run_ui(&mut self, state_mutex: Arc<Mutex<ArchState>>) -> Result<(), Box<dyn Error>> {
+    fn run_ui(
+        &mut self,
+        state_mutex: Arc<Mutex<ArchState>>,
+        view_limit: Option<usize>,
+    ) -> Result<(), Box<dyn Error>> {
         execute!(std::io::stdout(), EnableMouseCapture)?;
         let mut gui_state = GUIState {
             mem_table_state: TableState::new(),
+            view_limit,
+            speed: self.speed.load(Ordering::Relaxed),
             ..Default::default()
         };
 
         loop {
-            let arch_state = state_mutex.lock().unwrap();
             self.terminal.autoresize()?;
             let mut log_event = None;
             let inputs = if poll(Duration::from_millis(100)).is_ok_and(|has_event| has_event) {
                 if let Ok(event) = read() {
                     log_event = Some(event.clone());
-                    GUI::handle_input(event)
+                    GUI::handle_input(event, &mut gui_state.prompt)
                 } else {
                     Inputs::default()
                 }
             } else {
                 Inputs::default()
             };
+            // waiting on input doesn't hold the lock, so the executor runs meanwhile
+            let mut arch_state = state_mutex.lock().unwrap();
 
             inputs
                 .mouse_loc
                 .inspect(|(x, y)| gui_state.last_mouse_pos = Position::new(*x, *y));
 
+            if inputs.reload {
+                gui_state.status = Some(match arch_state.load_segments(&self.segments) {
+                    Ok(_) => {
+                        self.trail.lock().unwrap().clear();
+                        gui_state.last_error = None;
+                        "reloaded program".to_string()
+                    }
+                    Err(err) => format!("reload failed: {}", err),
+                });
+                self.pause = true;
+                let _ = self.pause_sender.send(ExecutorCommand::from(self.pause));
+            }
+
+            if inputs.faster || inputs.slower {
+                gui_state.speed = if inputs.faster {
+                    (gui_state.speed * 2).min(MAX_SPEED)
+                } else {
+                    (gui_state.speed / 2).max(1)
+                };
+                self.speed.store(gui_state.speed, Ordering::Relaxed);
+                gui_state.status = Some(format!("speed: {} ipms", gui_state.speed));
+            }
+
+            if inputs.shrink_view || inputs.grow_view {
+                let limit = gui_state.view_limit.unwrap_or(arch_state.mem.len());
+                let limit = if inputs.shrink_view {
+                    (limit / 2).max(16)
+                } else {
+                    limit.saturating_mul(2).min(arch_state.mem.len())
+                };
+                gui_state.view_limit = Some(limit);
+                gui_state.status = Some(format!("showing memory up to 0x{:x}", limit));
+            }
+
+            if inputs.edit_register {
+                match gui_state.selected_reg {
+                    Some(index) => {
+                        gui_state.prompt = Some((Prompt::Register(index), String::new()))
+                    }
+                    None => gui_state.status = Some("hover a register to edit it".to_string()),
+                }
+            }
+            match &gui_state.prompt {
+                Some((Prompt::Goto, input)) => {
+                    gui_state.status = Some(format!("goto: {}", input));
+                }
+                Some((Prompt::Search, input)) => {
+                    gui_state.status = Some(format!("/{}", input));
+                }
+                Some((Prompt::Dump, input)) => {
+                    gui_state.status = Some(format!("dump ADDR:LEN FILE: {}", input));
+                }
+                Some((Prompt::Register(index), input)) => {
+                    gui_state.status = Some(format!(
+                        "x{}/{} = {}",
+                        index,
+                        vm::register_abi_name(*index),
+                        input
+                    ));
+                }
+                None => {}
+            }
+            if let Some(edit) = &inputs.set_register {
+                gui_state.status = Some(match edit {
+                    Ok((index, val)) => {
+                        arch_state.set_register(*index, *val);
+                        format!("set x{} to 0x{:08x}", index, val)
+                    }
+                    Err(err) => err.clone(),
+                });
+            }
+            if let Some(dump) = &inputs.dump {
+                gui_state.status = Some(match dump {
+                    Ok((addr, len, path)) => match fs::write(path, arch_state.dump(*addr, *len)) {
+                        Ok(()) => format!("wrote {} bytes from 0x{:08x} to {}", len, addr, path),
+                        Err(err) => format!("dump failed: {}", err),
+                    },
+                    Err(err) => err.clone(),
+                });
+            }
+            if let Some(goto) = &inputs.goto {
+                let addr = goto.clone().map(|target| match target {
+                    GotoTarget::Addr(addr) => addr,
+                    GotoTarget::Pc => arch_state.pc as u32,
+                    GotoTarget::Register(index) => arch_state.get_register(index),
+                });
+                gui_state.status = Some(match addr {
+                    Ok(addr) if addr as usize >= arch_state.mem.len() => {
+                        format!("0x{:08x} is outside memory", addr)
+                    }
+                    Ok(addr) => {
+                        gui_state.show(addr);
+                        format!("showing 0x{:08x}", addr)
+                    }
+                    Err(err) => err,
+                });
+            }
+            if let Some(search) = &inputs.search {
+                gui_state.status = Some(match search {
+                    Ok(pattern) => {
+                        gui_state.search_hits = arch_state.find_bytes(pattern);
+                        gui_state.search_index = 0;
+                        match gui_state.search_hits.first() {
+                            Some(&addr) => {
+                                gui_state.show(addr);
+                                gui_state.search_status()
+                            }
+                            None => "no matches".to_string(),
+                        }
+                    }
+                    Err(err) => err.clone(),
+                });
+            }
+            if inputs.next_hit || inputs.prev_hit {
+                let hits = gui_state.search_hits.len();
+                gui_state.status = Some(if hits == 0 {
+                    "no search results, / starts a search".to_string()
+                } else {
+                    // both directions wrap around
+                    gui_state.search_index = if inputs.next_hit {
+                        (gui_state.search_index + 1) % hits
+                    } else {
+                        (gui_state.search_index + hits - 1) % hits
+                    };
+                    gui_state.show(gui_state.search_hits[gui_state.search_index]);
+                    gui_state.search_status()
+                });
+            }
+
+            if inputs.step_back && self.pause {
+                gui_state.status = Some(if arch_state.step_back() {
+                    self.trail.lock().unwrap().pop();
+                    format!("stepped back to 0x{:08x}", arch_state.pc)
+                } else {
+                    "no earlier state recorded".to_string()
+                });
+            }
+
+            if inputs.toggle_breakpoint {
+                // without a hovered row the breakpoint goes on the current instruction
+                let addr = gui_state.selected_addr.unwrap_or(arch_state.pc as u32);
+                gui_state.status = Some(if arch_state.remove_breakpoint(addr) {
+                    format!("cleared breakpoint at 0x{:08x}", addr)
+                } else {
+                    arch_state.add_breakpoint(addr);
+                    format!("set breakpoint at 0x{:08x}", addr)
+                });
+            }
+
+            if inputs.run_to_cursor {
+                match gui_state.selected_addr {
+                    Some(addr) => {
+                        // the executor pauses itself once pc gets there
+                        arch_state.run_to(addr);
+                        arch_state.paused = false;
+                        gui_state.watch_hit = None;
+                        self.pause = false;
+                        let _ = self.pause_sender.send(ExecutorCommand::Resume);
+                        gui_state.status = Some(format!("running to 0x{:08x}", addr));
+                    }
+                    None => gui_state.status = Some("hover a row to run to it".to_string()),
+                }
+            }
+
+            // a call runs until it returns, anything else is a plain step
+            let mut step = inputs.step;
+            if inputs.step_over && self.pause {
+                match arch_state.call_return_addr() {
+                    Some(addr) => {
+                        // recursion can pause early, when a deeper call reaches the same address
+                        arch_state.run_to(addr);
+                        arch_state.paused = false;
+                        gui_state.watch_hit = None;
+                        self.pause = false;
+                        let _ = self.pause_sender.send(ExecutorCommand::Resume);
+                        gui_state.status = Some(format!("stepping over to 0x{:08x}", addr));
+                    }
+                    None => step = true,
+                }
+            }
+
+            if inputs.toggle_watchpoint {
+                // watches every byte of the hovered row, or stops watching it
+                gui_state.status = Some(match gui_state.selected_addr {
+                    Some(start) => {
+                        let row = start..start + 16;
+                        let removed = row
+                            .clone()
+                            .filter(|addr| arch_state.remove_watchpoint(*addr))
+                            .count();
+                        if removed > 0 {
+                            format!("cleared watchpoints at 0x{:08x}", start)
+                        } else {
+                            row.for_each(|addr| arch_state.add_watchpoint(addr));
+                            format!("watching 0x{:08x}", start)
+                        }
+                    }
+                    None => "hover a memory row to watch it".to_string(),
+                });
+            }
+
+            // the executor already paused itself on EBREAK, a breakpoint, a watchpoint,
+            // or a fault, catch the UI up
+            if gui_state.take_fault(&mut arch_state) || gui_state.take_watch_events(&mut arch_state)
+            {
+                self.pause = true;
+            } else if arch_state.paused && !self.pause {
+                self.pause = true;
+                gui_state.status = Some(match arch_state.last_event {
+                    Some(event @ VmEvent::IdleLoop { .. }) => event.to_string(),
+                    _ => "breakpoint hit".to_string(),
+                });
+            }
+
+            if inputs.cycle_format {
+                gui_state.number_format = gui_state.number_format.next();
+                gui_state.status = Some(format!(
+                    "showing values as {}",
+                    match gui_state.number_format {
+                        NumberFormat::Hex => "hex",
+                        NumberFormat::Unsigned => "unsigned decimal",
+                        NumberFormat::Signed => "signed decimal",
+                    }
+                ));
+            }
+            if inputs.cycle_grouping {
+                gui_state.grouping = gui_state.grouping.next();
+                gui_state.status = Some(format!(
+                    "showing memory as {}",
+                    match gui_state.grouping {
+                        Grouping::Byte => "bytes",
+                        Grouping::Half => "little-endian halfwords",
+                        Grouping::Word => "little-endian words",
+                    }
+                ));
+            }
+            if inputs.toggle_code {
+                gui_state.show_code = !gui_state.show_code;
+            }
+            if inputs.focus_trail {
+                gui_state.trail_focused = !gui_state.trail_focused;
+            }
+            gui_state.trail = self
+                .trail
+                .lock()
+                .unwrap()
+                .entries()
+                .rev()
+                .copied()
+                .collect();
+
+            gui_state.watch_rows = self
+                .watches
+                .iter()
+                .map(|expr| match watch::evaluate(expr, &arch_state) {
+                    Ok(val) => format!("{}: {}", expr, gui_state.number_format.format(val)),
+                    Err(err) => format!("{}: {}", expr, err),
+                })
+                .collect();
+
             self.terminal.draw(|frame| {
-                GUI::draw(
-                    frame,
-                    self.pause,
-                    arch_state.pc as usize,
-                    &(0..32).map(|i| arch_state.get_register(i)).collect(),
-                    &arch_state.get_instruction().unwrap_or(Instruction::nop()),
-                    &arch_state.mem,
-                    &mut gui_state,
-                    &inputs,
-                );
+                GUI::draw(frame, self.pause, &arch_state, &mut gui_state, &inputs);
 
                 if cfg!(debug_assertions) {
                     frame.render_widget(
@@ -162,16 +876,39 @@ impl GUI {
                 break;
             }
 
-            self.step = inputs.step;
+            self.step = step;
             self.pause = self.pause != inputs.toggle_pause;
 
             if inputs.toggle_pause {
-                let _ = self.pause_sender.send(self.pause);
+                if !self.pause {
+                    arch_state.paused = false;
+                    gui_state.resume();
+                }
+                gui_state.free_run = false;
+                let _ = self.pause_sender.send(ExecutorCommand::from(self.pause));
+            }
+
+            if inputs.free_run {
+                // f again drops back to the speed set with + and -
+                gui_state.free_run = self.pause || !gui_state.free_run;
+                if self.pause {
+                    self.pause = false;
+                    arch_state.paused = false;
+                    gui_state.resume();
+                }
+                let command = if gui_state.free_run {
+                    gui_state.status = Some("free running".to_string());
+                    ExecutorCommand::FreeRun
+                } else {
+                    gui_state.status = Some(format!("speed: {} ipms", gui_state.speed));
+                    ExecutorCommand::Resume
+                };
+                let _ = self.pause_sender.send(command);
             }
 
             if self.step && self.pause {
                 let _ = self.step_sender.send(());
-                let _ = self.pause_sender.send(self.pause);
+                let _ = self.pause_sender.send(ExecutorCommand::from(self.pause));
             }
 
             if self.step || !self.pause {
@@ -184,18 +921,34 @@ impl GUI {
         Ok(())
     }
 
+    /// Renders one frame of `state`. Only touches `frame`, so it can be drawn to a test backend
     fn draw(
         frame: &mut Frame,
         paused: bool,
-        pc: usize,
-        registers: &Vec<u32>,
-        instruction: &Instruction,
-        mem: &Vec<u8>,
+        state: &ArchState,
         gui_state: &mut GUIState,
         inputs: &Inputs,
     ) {
+        let pc = state.pc as usize;
+        let mem = &state.mem;
+        let instruction = state.get_instruction().unwrap_or(Instruction::nop());
         let columns = Layout::horizontal([Constraint::Fill(1), Constraint::Min(3 * 16 + 8 + 4)]);
-        let [register_area, main_area] = columns.areas(frame.area());
+        let [lhs_area, main_area] = columns.areas(frame.area());
+        let watch_height = if gui_state.watch_rows.is_empty() {
+            0
+        } else {
+            gui_state.watch_rows.len() as u16 + 2
+        };
+        let trail_height = if gui_state.trail.is_empty() { 0 } else { 10 };
+        // a row per instruction kind
+        let stats_height = 7 + 2;
+        let [register_area, stats_area, trail_area, watch_area] = Layout::vertical([
+            Constraint::Fill(1),
+            Constraint::Length(stats_height),
+            Constraint::Length(trail_height),
+            Constraint::Length(watch_height),
+        ])
+        .areas(lhs_area);
         let rhs_rows = Layout::vertical([Constraint::Fill(1), Constraint::Length(8)]);
         let [mem_area, control_area] = rhs_rows.areas(main_area);
         let register_area_block = Block::bordered();
@@ -204,6 +957,26 @@ impl GUI {
         frame.render_widget(&register_area_block, register_area);
         frame.render_widget(&mem_area_block, mem_area);
         frame.render_widget(&control_area_block, control_area);
+        let stats_area_block = Block::bordered().title("mix");
+        frame.render_widget(
+            Text::raw(
+                stats_rows(
+                    state.stats(),
+                    stats_area_block.inner(stats_area).width as usize,
+                )
+                .join("\n"),
+            ),
+            stats_area_block.inner(stats_area),
+        );
+        frame.render_widget(stats_area_block, stats_area);
+        if !gui_state.watch_rows.is_empty() {
+            let watch_area_block = Block::bordered().title("watch");
+            frame.render_widget(
+                Text::raw(gui_state.watch_rows.join("\n")),
+                watch_area_block.inner(watch_area),
+            );
+            frame.render_widget(watch_area_block, watch_area);
+        }
 
         inputs.scroll_dir.inspect(|dir| {
             let scroll_motion = if *dir == ScrollDirection::Forward {
@@ -211,6 +984,12 @@ impl GUI {
             } else {
                 -1
             };
+            if gui_state.trail_focused || trail_area.contains(gui_state.last_mouse_pos) {
+                gui_state.trail_scroll_pos = gui_state
+                    .trail_scroll_pos
+                    .saturating_add_signed(scroll_motion);
+                return;
+            }
             if mem_area.contains(gui_state.last_mouse_pos) {
                 gui_state.mem_scroll_pos = gui_state
                     .mem_scroll_pos
@@ -224,33 +1003,85 @@ impl GUI {
         });
         *gui_state.reg_table_state.offset_mut() = gui_state.reg_scroll_pos;
 
-        // Memory readout
-        gui_state.mem_scroll_pos = gui_state
-            .mem_scroll_pos
-            .clamp(0, mem.len().saturating_sub(mem_area.height as usize) + 2);
+        // Memory readout, scrolled a row of 16 bytes at a time
+        let view_rows = gui_state
+            .view_limit
+            .unwrap_or(mem.len())
+            .min(mem.len())
+            .div_ceil(16);
+        // less the borders and the header row
+        let visible_rows = (mem_area.height as usize).saturating_sub(3);
+        let max_scroll = view_rows.saturating_sub(visible_rows);
+        gui_state.mem_scroll_pos = gui_state.mem_scroll_pos.min(max_scroll);
         let mem_scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight);
         let mem_table_even_style: Style = Style::new();
         let mem_table_odd_style: Style = Style::new().underlined();
+        let breakpoint_style = Style::new().fg(Color::Red);
+        let watchpoint_style = Style::new().fg(Color::Yellow);
+        let watch_hit_style = Style::new().reversed();
+        // red is taken by breakpoints
+        let written_style = Style::new().fg(Color::Green).bold();
+        let goto_style = Style::new().bg(Color::Cyan);
+        // device registers may change without a store, or on a load
+        let device_style = Style::new().fg(Color::Magenta).italic();
+        let width = gui_state.grouping.width();
+
+        // body rows start under the header
+        let mem_table_area = mem_area_block.inner(mem_area);
+        let mouse = gui_state.last_mouse_pos;
+        let hovered_row = (mem_table_area.contains(mouse) && mouse.y > mem_table_area.y)
+            .then(|| (mouse.y - mem_table_area.y - 1) as usize);
+        gui_state.selected_addr =
+            hovered_row.map(|row| ((gui_state.mem_scroll_pos + row) * 16) as u32);
+        gui_state.mem_table_state.select(hovered_row);
 
         let mem_table = Table::new(
-            (0..mem_area.height as usize - 2).map(|i| {
+            (0..(mem_area.height as usize).saturating_sub(2)).map(|i| {
                 let start_addr = (gui_state.mem_scroll_pos + i) * 16;
                 let mut cols = vec![Cell::new(format!("{:08x}", start_addr))];
-                for offset in 0..16 {
-                    cols.push(Cell::new(format!(
-                        "{:02x}|",
-                        mem.get(start_addr + offset).unwrap_or(&0)
-                    )));
+                let bytes = (start_addr as u32..start_addr as u32 + 16)
+                    .map(|addr| state.peek_byte(addr).unwrap_or(0))
+                    .collect::<Vec<u8>>();
+                for (group, text) in group_bytes(&bytes, width).into_iter().enumerate() {
+                    let cell = Cell::new(format!("{}|", text));
+                    let first = (start_addr + group * width) as u32;
+                    let addrs = first..first + width as u32;
+                    // while stepping, mark what the last instruction stored
+                    let written =
+                        paused && addrs.clone().any(|addr| state.last_written.contains(&addr));
+                    cols.push(
+                        if gui_state.goto_hit.is_some_and(|hit| addrs.contains(&hit)) {
+                            cell.style(goto_style)
+                        } else if written {
+                            cell.style(written_style)
+                        } else if addrs.clone().any(|addr| state.is_mmio(addr)) {
+                            cell.style(device_style)
+                        } else {
+                            cell
+                        },
+                    );
                 }
-                Row::new(cols).style(if i % 2 == 0 {
+                let style = if i % 2 == 0 {
                     mem_table_even_style
                 } else {
                     mem_table_odd_style
+                };
+                let row = start_addr as u32..start_addr as u32 + 16;
+                let style = if row.clone().any(|addr| state.breakpoints().contains(&addr)) {
+                    style.patch(breakpoint_style)
+                } else if row.clone().any(|addr| state.watchpoints().contains(&addr)) {
+                    style.patch(watchpoint_style)
+                } else {
+                    style
+                };
+                Row::new(cols).style(match gui_state.watch_hit {
+                    Some(addr) if row.contains(&addr) => style.patch(watch_hit_style),
+                    _ => style,
                 })
             }),
             [
                 vec![Constraint::Min(10)],
-                vec![Constraint::Length(3); 16],
+                vec![Constraint::Length(2 * width as u16 + 1); 16 / width],
                 vec![Constraint::Length(1)],
             ]
             .concat(),
@@ -260,6 +1091,7 @@ impl GUI {
                 [
                     vec![Cell::new("--------")],
                     (0..16)
+                        .step_by(width)
                         .map(|i| Cell::new(format!("{:02x}", i)))
                         .collect::<Vec<Cell>>(),
                 ]
@@ -270,17 +1102,15 @@ impl GUI {
         )
         .row_highlight_style(Style::new().fg(Color::Black).bg(Color::Gray));
 
-        frame.render_stateful_widget(
-            mem_table,
-            mem_area_block.inner(mem_area),
-            &mut gui_state.mem_table_state,
-        );
+        frame.render_stateful_widget(mem_table, mem_table_area, &mut gui_state.mem_table_state);
         frame.render_stateful_widget(
             mem_scrollbar,
             mem_area,
-            &mut ScrollbarState::new(mem.len() - mem_area.height as usize)
-                .position(gui_state.mem_scroll_pos),
+            &mut ScrollbarState::new(max_scroll).position(gui_state.mem_scroll_pos),
         );
+        if gui_state.show_code {
+            Self::draw_code(frame, state, gui_state, mem_area);
+        }
 
         // pc & reg readouts
         let [pc_area, reg_table_area] =
@@ -290,9 +1120,19 @@ impl GUI {
         gui_state.reg_scroll_pos = gui_state
             .reg_scroll_pos
             .clamp(0, 32_usize.saturating_sub(reg_table_area.height as usize));
+        gui_state.selected_reg = reg_table_area
+            .contains(mouse)
+            .then(|| gui_state.reg_scroll_pos + (mouse.y - reg_table_area.y) as usize)
+            .filter(|index| *index < 32);
+        gui_state.reg_table_state.select(gui_state.selected_reg);
 
+        let symbol = match state.nearest_symbol(pc as u32) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+0x{:x}", name, offset),
+            None => String::new(),
+        };
         frame.render_widget(
-            Text::raw(format!("pc : 0x{0:0>8X} | {0:0>10}", pc)),
+            Text::raw(format!("pc : 0x{0:0>8X} | {0:0>10}\n     {1}", pc, symbol)),
             pc_area,
         );
 
@@ -302,14 +1142,15 @@ impl GUI {
             (0..32)
                 .map(|i| {
                     Row::new([Cell::new(format!(
-                        "x{: <2}: 0x{1:0>8X} | {1:0>10}",
-                        i,
-                        registers.get(i).unwrap()
+                        "{: <7}: {}",
+                        format!("x{}/{}", i, vm::register_abi_name(i)),
+                        gui_state.number_format.format(state.get_register(i))
                     ))])
                 })
                 .collect::<Vec<Row>>(),
             [Constraint::Fill(1)],
-        );
+        )
+        .row_highlight_style(Style::new().fg(Color::Black).bg(Color::Gray));
 
         frame.render_stateful_widget(reg_table, reg_table_area, &mut gui_state.reg_table_state);
         frame.render_stateful_widget(
@@ -319,29 +1160,227 @@ impl GUI {
                 .position(gui_state.reg_scroll_pos),
         );
 
+        if !gui_state.trail.is_empty() {
+            Self::draw_trail(frame, state, gui_state, trail_area);
+        }
+
         let [instruction_area, ui_area] =
             Layout::vertical([Constraint::Length(1), Constraint::Min(1)])
                 .areas(control_area_block.inner(control_area));
 
-        frame.render_widget(Text::raw(format!("{}", instruction)), instruction_area);
         frame.render_widget(
-            Text::raw(format!("\n{}", if paused { "||" } else { ">>" })),
-            ui_area,
+            Text::raw(truncate_to_width(
+                &instruction.to_string(),
+                instruction_area.width as usize,
+            )),
+            instruction_area,
+        );
+        let mut controls = Text::raw(format!(
+            "\n{} {}\ninstructions {} cycles {} speed: {}\n{}",
+            match (paused, gui_state.free_run) {
+                (true, _) => "||",
+                (false, false) => ">>",
+                (false, true) => ">>>",
+            },
+            state.stats().summary(),
+            state.instret(),
+            state.cycle_count(),
+            if gui_state.free_run && !paused {
+                "max".to_string()
+            } else {
+                format!("{} ipms", gui_state.speed)
+            },
+            gui_state.status.as_deref().unwrap_or("")
+        ));
+        if let Some(err) = &gui_state.last_error {
+            controls.push_line(Line::styled(
+                format!("error: {}", err),
+                Style::new().fg(Color::Red),
+            ));
+        }
+        frame.render_widget(controls, ui_area);
+    }
+
+    /// The code around pc drawn over the memory pane, breakpoints in red.
+    /// The hovered instruction replaces the hovered memory row as the one `b` and `u` act on
+    fn draw_code(frame: &mut Frame, state: &ArchState, gui_state: &mut GUIState, area: Rect) {
+        let block = Block::bordered().title("code");
+        let inner = block.inner(area);
+        let pc = state.pc as u32;
+        let window = code_window(state, pc, inner.height as usize);
+        let mouse = gui_state.last_mouse_pos;
+        let hovered_row = inner.contains(mouse).then(|| (mouse.y - inner.y) as usize);
+        gui_state.selected_addr = hovered_row.map(|row| window[row].0);
+        let rows = window
+            .into_iter()
+            .map(|(addr, inst)| {
+                let text = match inst {
+                    Some(inst) => inst.to_string(),
+                    None => "--".to_string(),
+                };
+                let line = format!(
+                    "{} {:08x}  {}",
+                    if addr == pc { ">" } else { " " },
+                    addr,
+                    text
+                );
+                let row = Row::new([Cell::new(truncate_to_width(&line, inner.width as usize))]);
+                if addr == pc {
+                    row.style(Style::new().reversed())
+                } else if state.breakpoints().contains(&addr) {
+                    row.style(Style::new().fg(Color::Red))
+                } else {
+                    row
+                }
+            })
+            .collect::<Vec<Row>>();
+        frame.render_widget(Clear, area);
+        frame.render_widget(&block, area);
+        frame.render_stateful_widget(
+            Table::new(rows, [Constraint::Fill(1)])
+                .row_highlight_style(Style::new().fg(Color::Black).bg(Color::Gray)),
+            inner,
+            &mut TableState::new().with_selected(hovered_row),
+        );
+    }
+
+    /// The pc trail panel, newest first and indented by call depth
+    fn draw_trail(frame: &mut Frame, state: &ArchState, gui_state: &mut GUIState, area: Rect) {
+        let block = Block::bordered().title("trail");
+        let block = if gui_state.trail_focused {
+            block.border_style(Style::new().fg(Color::Yellow))
+        } else {
+            block
+        };
+        let table_area = block.inner(area);
+        let max_scroll = gui_state
+            .trail
+            .len()
+            .saturating_sub(table_area.height as usize);
+        gui_state.trail_scroll_pos = gui_state.trail_scroll_pos.min(max_scroll);
+        *gui_state.trail_table_state.offset_mut() = gui_state.trail_scroll_pos;
+
+        let trail_table = Table::new(
+            gui_state.trail.iter().map(|entry| {
+                let symbol = match state.nearest_symbol(entry.pc) {
+                    Some((name, 0)) => name.to_string(),
+                    Some((name, offset)) => format!("{}+0x{:x}", name, offset),
+                    None => String::new(),
+                };
+                let flow = match entry.flow {
+                    Flow::Next => "",
+                    Flow::Taken => "->",
+                    Flow::Call => "call",
+                    Flow::Return => "ret",
+                };
+                Row::new([Cell::new(format!(
+                    "{}{:08x} {: <4} {}",
+                    "  ".repeat(entry.depth.min(8)),
+                    entry.pc,
+                    flow,
+                    symbol
+                ))])
+            }),
+            [Constraint::Fill(1)],
+        );
+        frame.render_widget(&block, area);
+        frame.render_stateful_widget(trail_table, table_area, &mut gui_state.trail_table_state);
+        frame.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight),
+            area,
+            &mut ScrollbarState::new(max_scroll).position(gui_state.trail_scroll_pos),
         );
     }
 
-    fn handle_input(event: Event) -> Inputs {
+    /// Turns an event into the commands it triggers. While a prompt is open keys are typed
+    /// into it instead, enter submits it and escape cancels
+    fn handle_input(event: Event, prompt: &mut Option<(Prompt, String)>) -> Inputs {
+        if let (Some((kind, input)), Event::Key(key_event)) = (prompt.as_mut(), &event) {
+            let submitted = match key_event.code {
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    return Inputs::default();
+                }
+                KeyCode::Backspace => {
+                    input.pop();
+                    return Inputs::default();
+                }
+                KeyCode::Enter => Ok(input.as_str()),
+                KeyCode::Esc => Err("cancelled".to_string()),
+                _ => return Inputs::default(),
+            };
+            let inputs = match *kind {
+                Prompt::Goto => Inputs {
+                    goto: Some(submitted.and_then(parse_goto)),
+                    ..Default::default()
+                },
+                Prompt::Search => Inputs {
+                    search: Some(submitted.and_then(parse_pattern)),
+                    ..Default::default()
+                },
+                Prompt::Dump => Inputs {
+                    dump: Some(submitted.and_then(parse_dump)),
+                    ..Default::default()
+                },
+                Prompt::Register(index) => Inputs {
+                    set_register: Some(
+                        submitted
+                            .and_then(|text| parse_register_value(index, text))
+                            .map(|val| (index, val)),
+                    ),
+                    ..Default::default()
+                },
+            };
+            *prompt = None;
+            return inputs;
+        }
         match event {
             Event::Key(key_event) => match key_event.code {
+                KeyCode::Char('g') => {
+                    *prompt = Some((Prompt::Goto, String::new()));
+                    Inputs::default()
+                }
+                KeyCode::Char('/') => {
+                    *prompt = Some((Prompt::Search, String::new()));
+                    Inputs::default()
+                }
+                KeyCode::Char('D') => {
+                    *prompt = Some((Prompt::Dump, String::new()));
+                    Inputs::default()
+                }
                 KeyCode::Char(c) => Inputs {
                     exit: c == 'q',
                     toggle_pause: c == ' ',
+                    reload: c == 'r',
+                    toggle_breakpoint: c == 'b',
+                    run_to_cursor: c == 'u',
+                    step_over: c == 'o',
+                    toggle_watchpoint: c == 'w',
+                    shrink_view: c == '[',
+                    faster: c == '+' || c == '=',
+                    slower: c == '-',
+                    free_run: c == 'f',
+                    cycle_format: c == 'd',
+                    cycle_grouping: c == 'v',
+                    grow_view: c == ']',
+                    focus_trail: c == 't',
+                    toggle_code: c == 'c',
+                    next_hit: c == 'n',
+                    prev_hit: c == 'N',
+                    ..Default::default()
+                },
+                KeyCode::Enter => Inputs {
+                    edit_register: true,
                     ..Default::default()
                 },
                 KeyCode::Right => Inputs {
                     step: true,
                     ..Default::default()
                 },
+                KeyCode::Left => Inputs {
+                    step_back: true,
+                    ..Default::default()
+                },
                 KeyCode::Down => Inputs {
                     scroll_dir: Some(ScrollDirection::Forward),
                     ..Default::default()
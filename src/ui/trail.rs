@@ -0,0 +1,86 @@
+use std::collections::VecDeque;
+
+use crate::vm::Instruction;
+
+#[cfg(test)]
+mod trail_tests;
+
+/// Instructions the pc trail panel remembers
+pub(super) const TRAIL_LIMIT: usize = 64;
+
+/// Where an instruction sent pc
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Flow {
+    Next,
+    Taken,
+    Call,
+    Return,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct TrailEntry {
+    pub pc: u32,
+    pub flow: Flow,
+    /// Calls minus returns before this instruction ran, counted since the trail was cleared
+    pub depth: usize,
+}
+
+/// The most recently executed pcs, oldest first. Filled by the executor thread and read by the UI
+#[derive(Debug)]
+pub(super) struct PcTrail {
+    entries: VecDeque<TrailEntry>,
+    limit: usize,
+    depth: usize,
+}
+
+impl PcTrail {
+    pub(super) fn new(limit: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            limit,
+            depth: 0,
+        }
+    }
+
+    /// Records `inst`, which ran at `pc` and left pc at `next_pc`
+    pub(super) fn record(&mut self, pc: u32, inst: &Instruction, next_pc: u32) {
+        let flow = if inst.is_call() {
+            Flow::Call
+        } else if inst.is_return() {
+            Flow::Return
+        } else if inst.is_branch_or_jump() && next_pc != pc + 4 && next_pc != pc + 2 {
+            Flow::Taken
+        } else {
+            Flow::Next
+        };
+        if self.entries.len() == self.limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TrailEntry {
+            pc,
+            flow,
+            depth: self.depth,
+        });
+        match flow {
+            Flow::Call => self.depth += 1,
+            Flow::Return => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    /// Forgets the newest entry, for when the instruction it records is stepped back over
+    pub(super) fn pop(&mut self) {
+        if let Some(entry) = self.entries.pop_back() {
+            self.depth = entry.depth;
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.entries.clear();
+        self.depth = 0;
+    }
+
+    pub(super) fn entries(&self) -> impl DoubleEndedIterator<Item = &TrailEntry> {
+        self.entries.iter()
+    }
+}
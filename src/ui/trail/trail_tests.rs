@@ -0,0 +1,62 @@
+use super::{Flow, PcTrail, TrailEntry};
+use crate::vm::{Instruction, interpret_bytes};
+
+#[test]
+fn test_trail_is_bounded() {
+    let mut trail = PcTrail::new(4);
+    for pc in (0..40).step_by(4) {
+        trail.record(pc, &Instruction::nop(), pc + 4);
+    }
+    assert_eq!(
+        trail.entries().map(|entry| entry.pc).collect::<Vec<u32>>(),
+        [24, 28, 32, 36],
+        "only the newest entries are kept"
+    );
+    trail.pop();
+    assert_eq!(trail.entries().count(), 3);
+    trail.clear();
+    assert_eq!(trail.entries().count(), 0);
+}
+
+#[test]
+fn test_trail_call_depth() {
+    let call = interpret_bytes(0x010000ef); // jal ra, 16
+    let ret = interpret_bytes(0x00008067); // jalr x0, 0(ra)
+    let beq = interpret_bytes(0x00000463); // beq x0, x0, 8
+    let mut trail = PcTrail::new(8);
+    trail.record(0, &call, 16);
+    trail.record(16, &beq, 24);
+    trail.record(24, &ret, 4);
+    trail.record(4, &beq, 8);
+    assert_eq!(
+        trail.entries().copied().collect::<Vec<_>>(),
+        [
+            TrailEntry {
+                pc: 0,
+                flow: Flow::Call,
+                depth: 0
+            },
+            TrailEntry {
+                pc: 16,
+                flow: Flow::Taken,
+                depth: 1
+            },
+            TrailEntry {
+                pc: 24,
+                flow: Flow::Return,
+                depth: 1
+            },
+            TrailEntry {
+                pc: 4,
+                flow: Flow::Next,
+                depth: 0
+            },
+        ]
+    );
+
+    // stepping back over the return puts the depth back inside the call
+    trail.pop();
+    trail.pop();
+    trail.record(24, &Instruction::nop(), 28);
+    assert_eq!(trail.entries().last().unwrap().depth, 1);
+}
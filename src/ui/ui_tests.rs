@@ -0,0 +1,454 @@
+use std::{
+    sync::{Mutex, atomic::AtomicU64, mpsc::channel},
+    thread,
+};
+
+use ratatui::{
+    Terminal,
+    backend::TestBackend,
+    crossterm::event::{Event, KeyCode, KeyEvent},
+    style::Color,
+};
+
+use super::trail::{PcTrail, TRAIL_LIMIT};
+use super::{
+    ExecutorCommand, GUI, GUIState, GotoTarget, Grouping, Inputs, NumberFormat, Prompt,
+    code_window, execute_batch, execute_step, executor_channel, group_bytes, parse_goto,
+    parse_pattern, parse_register_value, run_executor, stats_rows, truncate_to_width,
+};
+use crate::vm::{ArchState, HostSyscalls, InputDevice, Instruction, InstructionStats, VmError};
+
+#[test]
+fn test_truncate_to_width() {
+    let text = "ADDI rd:  x1/ra | rs1: x0/zero | imm: 0b000000000001";
+    assert_eq!(truncate_to_width(text, 100), text);
+    assert_eq!(truncate_to_width(text, text.len()), text);
+    assert_eq!(truncate_to_width(text, 12), "ADDI rd:  x…");
+    assert_eq!(truncate_to_width(text, 12).chars().count(), 12);
+    assert_eq!(truncate_to_width(text, 1), "…");
+    assert_eq!(truncate_to_width(text, 0), "");
+}
+
+#[test]
+fn test_draw_layout() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // addi x1, x0, 7
+    state.load(0x00700093_u32.to_le_bytes().to_vec(), 0);
+    state.tick().unwrap();
+
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    let mut gui_state = GUIState {
+        watch_rows: vec!["x1: 0x00000007 | 0000000007".to_string()],
+        ..Default::default()
+    };
+    terminal
+        .draw(|frame| GUI::draw(frame, true, &state, &mut gui_state, &Inputs::default()))
+        .unwrap();
+
+    let buffer = terminal.backend().buffer();
+    let lines = (0..buffer.area.height)
+        .map(|y| {
+            (0..buffer.area.width)
+                .map(|x| buffer[(x, y)].symbol())
+                .collect::<String>()
+        })
+        .collect::<Vec<String>>();
+
+    // registers on the left, memory above the controls on the right
+    assert!(lines[1].starts_with("│pc : 0x00000004 |"));
+    assert!(lines[4].starts_with("│x1/ra  : 0x0000000"));
+    assert!(lines[1].contains("│--------   00 01 02 03"));
+    assert!(lines[2].contains("│00000000   93 00 70 00"));
+    assert!(
+        lines[15].contains("└──"),
+        "memory block ends above the controls"
+    );
+    // pc has run onto zeroed memory, which shows as the word it is rather than a nop
+    assert!(lines[17].contains("│illegal 0x00000000"));
+    assert!(lines[19].contains("│||"));
+    // the watch panel sits under the registers and the frame fills the whole area
+    assert!(lines[21].starts_with("┌watch"));
+    assert!(lines[22].starts_with("│x1: 0x00000007"));
+    assert!(lines[23].starts_with("└") && lines[23].ends_with("┘"));
+}
+
+#[test]
+fn test_initial_executor_command() {
+    let (_sender, receiver) = executor_channel(false);
+    assert_eq!(receiver.try_recv(), Ok(ExecutorCommand::Resume));
+    assert!(receiver.try_recv().is_err());
+
+    let (_sender, receiver) = executor_channel(true);
+    assert_eq!(receiver.try_recv(), Ok(ExecutorCommand::Pause));
+}
+
+#[test]
+fn test_view_limit_clamps_scroll() {
+    let state = ArchState::with_mem(2_usize.pow(16));
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    let mut draw = |gui_state: &mut GUIState| {
+        terminal
+            .draw(|frame| GUI::draw(frame, true, &state, gui_state, &Inputs::default()))
+            .unwrap();
+    };
+
+    // the memory table is 16 lines tall, 13 rows once the borders and header are taken out
+    let mut gui_state = GUIState {
+        mem_scroll_pos: usize::MAX,
+        view_limit: Some(0x200),
+        ..Default::default()
+    };
+    draw(&mut gui_state);
+    assert_eq!(gui_state.mem_scroll_pos, 0x200 / 16 - 13);
+
+    // without a limit the whole memory is reachable
+    gui_state.view_limit = None;
+    gui_state.mem_scroll_pos = usize::MAX;
+    draw(&mut gui_state);
+    assert_eq!(gui_state.mem_scroll_pos, 2_usize.pow(16) / 16 - 13);
+
+    // a limit past the end of memory is no different
+    gui_state.view_limit = Some(2_usize.pow(20));
+    draw(&mut gui_state);
+    assert_eq!(gui_state.mem_scroll_pos, 2_usize.pow(16) / 16 - 13);
+}
+
+#[test]
+fn test_parse_goto() {
+    assert_eq!(parse_goto("0x1000"), Ok(GotoTarget::Addr(0x1000)));
+    assert_eq!(parse_goto("ffff0"), Ok(GotoTarget::Addr(0xffff0)));
+    assert_eq!(parse_goto(" pc "), Ok(GotoTarget::Pc));
+    assert_eq!(parse_goto("sp"), Ok(GotoTarget::Register(2)));
+    assert_eq!(parse_goto("x31"), Ok(GotoTarget::Register(31)));
+    // a0 is also valid hex, the register wins
+    assert_eq!(parse_goto("a0"), Ok(GotoTarget::Register(10)));
+    assert!(parse_goto("0x").is_err());
+    assert!(parse_goto("x32").is_err());
+    assert!(parse_goto("0x100000000").is_err());
+}
+
+#[test]
+fn test_parse_pattern() {
+    assert_eq!(parse_pattern("de ad"), Ok(vec![0xde, 0xad]));
+    // words are matched the way a load reads them
+    assert_eq!(
+        parse_pattern("0xdeadbeef"),
+        Ok(vec![0xef, 0xbe, 0xad, 0xde])
+    );
+    assert_eq!(
+        parse_pattern("0x1 0x2 ff"),
+        Ok(vec![1, 0, 0, 0, 2, 0, 0, 0, 0xff])
+    );
+    assert!(parse_pattern("  ").is_err());
+    assert!(parse_pattern("abc").is_err());
+    assert!(parse_pattern("0x123456789").is_err());
+    assert!(parse_pattern("zz").is_err());
+}
+
+#[test]
+fn test_code_window() {
+    let mut state = ArchState::with_mem(0x20);
+    // addi x1, x1, 1 over the first four words, the rest stays zero
+    state.load(0x00108093_u32.to_le_bytes().repeat(4), 0);
+
+    let window = code_window(&state, 8, 5);
+    assert_eq!(
+        window.iter().map(|(addr, _)| *addr).collect::<Vec<u32>>(),
+        [0, 4, 8, 12, 16],
+        "pc sits in the middle row"
+    );
+    assert!(window[..4].iter().all(|(_, inst)| inst.is_some()));
+    assert_eq!(
+        window[4].1,
+        Some(Instruction::ILLEGAL { raw: 0 }),
+        "zeroed memory doesn't decode"
+    );
+
+    // near address 0 the window starts at pc's alignment instead of wrapping
+    let window = code_window(&state, 2, 5);
+    assert_eq!(window[0].0, 2);
+
+    // past the end of memory there is nothing to fetch
+    let window = code_window(&state, 0x1c, 5);
+    assert_eq!(
+        window.iter().map(|(addr, _)| *addr).collect::<Vec<u32>>(),
+        [0x14, 0x18, 0x1c, 0x20, 0x24]
+    );
+    assert!(window[3..].iter().all(|(_, inst)| inst.is_none()));
+}
+
+#[test]
+fn test_parse_register_value() {
+    assert_eq!(parse_register_value(5, "0x1f"), Ok(0x1f));
+    assert_eq!(parse_register_value(5, "DEADBEEF"), Ok(0xdeadbeef));
+    assert!(parse_register_value(5, "").is_err());
+    assert!(parse_register_value(5, "0x123456789").is_err());
+    assert_eq!(
+        parse_register_value(0, "1"),
+        Err("x0 is always zero".to_string())
+    );
+}
+
+#[test]
+fn test_register_prompt() {
+    let key = |code| Event::Key(KeyEvent::from(code));
+    let mut prompt = Some((Prompt::Register(2), String::new()));
+    for c in "0x80".chars() {
+        let inputs = GUI::handle_input(key(KeyCode::Char(c)), &mut prompt);
+        assert!(
+            !inputs.exit && inputs.set_register.is_none(),
+            "keys are typed, not run"
+        );
+    }
+    let inputs = GUI::handle_input(key(KeyCode::Enter), &mut prompt);
+    assert_eq!(inputs.set_register, Some(Ok((2, 0x80))));
+    assert_eq!(prompt, None);
+
+    // with the prompt closed q quits again
+    assert!(GUI::handle_input(key(KeyCode::Char('q')), &mut prompt).exit);
+}
+
+#[test]
+fn test_stats_rows() {
+    let stats = InstructionStats {
+        arithmetic: 4,
+        loads: 8,
+        jumps: 2,
+        ..Default::default()
+    };
+    let rows = stats_rows(&stats, 36);
+    assert_eq!(rows.len(), 7);
+    assert_eq!(rows[0], "       8 loads              ########");
+    assert_eq!(rows[1], "       4 arithmetic         ####");
+    assert_eq!(rows[2], "       2 jumps              ##");
+    assert_eq!(rows[3], "       0 stores");
+
+    // a narrow pane keeps the counts
+    assert_eq!(stats_rows(&stats, 12)[0], "       8 lo…");
+}
+
+#[test]
+fn test_fault_shown_in_red() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(0xffffffff_u32.to_le_bytes().to_vec(), 0);
+    let trail = Mutex::new(PcTrail::new(TRAIL_LIMIT));
+    execute_step(&mut state, &trail);
+    assert!(state.paused, "a fault pauses the executor");
+
+    let mut gui_state = GUIState::default();
+    assert!(gui_state.take_fault(&mut state));
+    assert_eq!(
+        gui_state.last_error,
+        Some(VmError::IllegalInstruction(0xffffffff))
+    );
+    assert!(!gui_state.take_fault(&mut state), "taken only once");
+
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    terminal
+        .draw(|frame| GUI::draw(frame, true, &state, &mut gui_state, &Inputs::default()))
+        .unwrap();
+    let buffer = terminal.backend().buffer();
+    let (x, y) = (0..buffer.area.height)
+        .find_map(|y| {
+            let line: String = (0..buffer.area.width)
+                .map(|x| buffer[(x, y)].symbol())
+                .collect();
+            line.find("│error: illegal instruction 0xffffffff")
+                .map(|at| (line[..at].chars().count() as u16 + 1, y))
+        })
+        .expect("error line is drawn");
+    assert_eq!(buffer[(x, y)].fg, Color::Red);
+}
+
+#[test]
+fn test_every_watched_write_shown() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let program = [
+        0x08100023_u32, // sb x1, 0x80(x0)
+        0x08100023,     // sb x1, 0x80(x0)
+        0x08100023,     // sb x1, 0x80(x0)
+    ];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+    state.add_watchpoint(0x80);
+    for _ in 0..3 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.watch_events.len(), 3);
+
+    let mut gui_state = GUIState::default();
+    assert!(gui_state.take_watch_events(&mut state));
+    assert_eq!(gui_state.watch_hit, Some(0x80));
+    assert_eq!(
+        gui_state.status.as_deref(),
+        Some(
+            "0x00000000 wrote 0x00 over 0x00 at 0x00000080; \
+             0x00000004 wrote 0x00 over 0x00 at 0x00000080; \
+             0x00000008 wrote 0x00 over 0x00 at 0x00000080"
+        )
+    );
+    assert!(!gui_state.take_watch_events(&mut state), "drained");
+}
+
+#[test]
+fn test_execute_batch() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // addi x1, x1, 1 over and over
+    state.load(0x00108093_u32.to_le_bytes().repeat(16), 0);
+    let trail = Mutex::new(PcTrail::new(TRAIL_LIMIT));
+    assert_eq!(execute_batch(&mut state, &trail, 3), 3);
+    assert_eq!(state.get_register(1), 3);
+
+    // a breakpoint ends the batch early
+    state.add_breakpoint(0x14);
+    assert_eq!(execute_batch(&mut state, &trail, 100), 2);
+    assert_eq!(state.pc, 0x14);
+    assert_eq!(execute_batch(&mut state, &trail, 100), 0, "paused");
+}
+
+#[test]
+fn test_free_run_to_exit() {
+    const LOOPS: u32 = 200_000;
+    // addi x1, x1, 1; bne x1, x2, -4; then addi a7, x0, 93; addi a0, x0, 0; ecall
+    let program = [
+        0x00108093_u32,
+        0xfe209ee3,
+        0x05d00893,
+        0x00000513,
+        0x00000073,
+    ];
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+    state.set_register(2, LOOPS);
+    state.set_syscall_handler(Box::new(HostSyscalls));
+    let state = Mutex::new(state);
+    let trail = Mutex::new(PcTrail::new(TRAIL_LIMIT));
+    let speed = AtomicU64::new(1);
+
+    let (commands, command_rx) = executor_channel(true);
+    let (_step, step_rx) = channel();
+    let (_quit, quit_rx) = channel();
+    commands.send(ExecutorCommand::FreeRun).unwrap();
+    // the executor gives up once the UI hangs up and it's paused on the exit
+    drop(commands);
+    let ran = thread::scope(|scope| {
+        scope
+            .spawn(|| run_executor(&state, &trail, &speed, command_rx, step_rx, quit_rx))
+            .join()
+            .unwrap()
+    });
+
+    let state = state.into_inner().unwrap();
+    assert_eq!(state.exit_status, Some(0));
+    assert_eq!(state.get_register(1), LOOPS);
+    // the extra one is the tick that found the machine halted
+    assert_eq!(ran, 2 * LOOPS as u64 + 3 + 1);
+}
+
+#[test]
+fn test_number_format() {
+    let cases = [
+        (0, "0x00000000", "         0", "          0"),
+        (7, "0x00000007", "         7", "          7"),
+        (0x7fffffff, "0x7FFFFFFF", "2147483647", " 2147483647"),
+        (0x80000000, "0x80000000", "2147483648", "-2147483648"),
+        (u32::MAX, "0xFFFFFFFF", "4294967295", "         -1"),
+    ];
+    for (val, hex, unsigned, signed) in cases {
+        assert_eq!(NumberFormat::Hex.format(val), hex);
+        assert_eq!(NumberFormat::Unsigned.format(val), unsigned);
+        assert_eq!(NumberFormat::Signed.format(val), signed);
+    }
+    assert_eq!(NumberFormat::Signed.next(), NumberFormat::Hex);
+}
+
+#[test]
+fn test_group_bytes() {
+    let bytes = [0x93, 0x00, 0x70, 0x00, 0xef, 0xbe, 0xad, 0xde];
+    assert_eq!(group_bytes(&bytes, 1)[..3], ["93", "00", "70"]);
+    assert_eq!(group_bytes(&bytes, 2), ["0093", "0070", "beef", "dead"]);
+    assert_eq!(group_bytes(&bytes, 4), ["00700093", "deadbeef"]);
+    // a trailing partial group is still little-endian
+    assert_eq!(group_bytes(&bytes[..6], 4), ["00700093", "beef"]);
+
+    // the table header and cells follow the grouping
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(bytes.to_vec(), 0);
+    let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+    let mut gui_state = GUIState {
+        grouping: Grouping::Word,
+        ..Default::default()
+    };
+    terminal
+        .draw(|frame| GUI::draw(frame, true, &state, &mut gui_state, &Inputs::default()))
+        .unwrap();
+    let buffer = terminal.backend().buffer();
+    let line = |y| {
+        (0..buffer.area.width)
+            .map(|x| buffer[(x, y)].symbol())
+            .collect::<String>()
+    };
+    // memory is the second pane along, skip the registers
+    let words = |y| {
+        let line = line(y);
+        let pane = line.split('│').nth(2).unwrap().to_string();
+        pane.split_whitespace()
+            .take(3)
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    assert_eq!(words(1), "-------- 00 04");
+    assert_eq!(words(2), "00000000 00700093| deadbeef|");
+}
+
+#[test]
+fn test_device_memory_peeked() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let input = InputDevice::new(b"abc");
+    let queue = input.queue();
+    state.map_mmio(0x10..0x18, Box::new(input));
+    // lbu x1, 0x10(x0)
+    state.load(0x01004083_u32.to_le_bytes().to_vec(), 0);
+
+    let draw = |state: &ArchState| {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal
+            .draw(|frame| {
+                GUI::draw(
+                    frame,
+                    true,
+                    state,
+                    &mut GUIState::default(),
+                    &Inputs::default(),
+                )
+            })
+            .unwrap();
+        terminal.backend().buffer().clone()
+    };
+    let buffer = draw(&state);
+    let line: String = (0..buffer.area.width)
+        .map(|x| buffer[(x, 3)].symbol())
+        .collect();
+    assert!(line.contains("│00000010   61 00"));
+    assert_eq!(
+        state.peek_byte(0x14),
+        Some(3),
+        "the status word counts what's left"
+    );
+    let x = line.find("61 00").unwrap() as u16 - 2;
+    assert_eq!(buffer[(x, 3)].fg, Color::Magenta, "device bytes are marked");
+    assert_eq!(
+        queue.lock().unwrap().len(),
+        3,
+        "drawing doesn't consume input"
+    );
+
+    state.tick().unwrap();
+    assert_eq!(state.get_register(1), u32::from(b'a'));
+    assert_eq!(queue.lock().unwrap().len(), 2, "a load does");
+    let buffer = draw(&state);
+    let line: String = (0..buffer.area.width)
+        .map(|x| buffer[(x, 3)].symbol())
+        .collect();
+    assert!(line.contains("│00000010   62 00"));
+    assert_eq!(state.peek_byte(0x14), Some(2));
+}
@@ -1,28 +1,93 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::{Display, Write},
-    u32,
+    fs, io,
+    ops::Range,
+    path::PathBuf,
 };
 
+mod atomic;
+mod compressed;
+mod csr;
+mod disasm;
+mod elf;
+mod hexfile;
+mod history;
+mod memory;
+mod mmio;
+mod save;
+mod stats;
+mod syscall;
+mod timing;
+mod trace;
+mod trap;
+use atomic::AmoOp;
+pub use compressed::{decode_compressed, expand_compressed, is_compressed};
+use csr::CsrOp;
+pub use csr::{
+    COUNTER_CSRS, CSR_CYCLE, CSR_CYCLEH, CSR_INSTRET, CSR_INSTRETH, CSR_TIME, CSR_TIMEH,
+};
+pub use disasm::{assembly, disassemble};
+pub use elf::{elf_segments, elf_symbols};
+pub use hexfile::{ihex_segments, srec_segments};
+pub use history::DEFAULT_HISTORY_LIMIT;
+use history::History;
+pub use memory::Memory;
+pub use mmio::{ConsoleDevice, INPUT_ADDR, InputDevice, MmioDevice};
+pub use stats::InstructionStats;
+pub use syscall::{HostSyscalls, SYS_EXIT, SYS_READ, SYS_WRITE, SyscallHandler, SyscallOutcome};
+pub use timing::TimingModel;
+pub use trace::{TraceChange, TraceEntry, TraceSink, trace_file};
+pub use trap::{CSR_MCAUSE, CSR_MEPC, CSR_MTVAL, CSR_MTVEC};
+
 #[cfg(test)]
 mod instruction_tests;
 #[cfg(test)]
 mod integration_tests;
 
 type RegisterPointer = u8;
-/** 12 Bit Immediate */
-#[derive(Clone, Copy, Debug)]
+
+const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// Calling convention name of register `index`, e.g. `sp` for x2. `s0` doubles as `fp`
+pub fn register_abi_name(index: usize) -> &'static str {
+    ABI_NAMES.get(index).copied().unwrap_or("?")
+}
+/** 12 Bit Immediate, I format */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 struct SmallImmediate {
     val: u32,
 }
+/** 12 Bit Immediate, S format splits it around rs1 and rs2 */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct StoreImmediate {
+    val: u32,
+}
 /** 20 Bit Immediate */
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 struct BigImmediate {
     val: u32,
 }
 
-impl Into<u32> for SmallImmediate {
-    fn into(self) -> u32 {
-        self.val
+/** 13 Bit Immediate, bit 0 is always zero so it is a byte offset */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BranchImmediate {
+    val: u32,
+}
+
+/** 21 Bit Immediate, bit 0 is always zero so it is a byte offset */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct JumpImmediate {
+    val: u32,
+}
+
+impl From<SmallImmediate> for u32 {
+    fn from(value: SmallImmediate) -> Self {
+        value.val
     }
 }
 
@@ -32,9 +97,21 @@ impl From<u32> for SmallImmediate {
     }
 }
 
-impl Into<u32> for BigImmediate {
-    fn into(self) -> u32 {
-        self.val
+impl From<StoreImmediate> for u32 {
+    fn from(value: StoreImmediate) -> Self {
+        value.val
+    }
+}
+
+impl From<u32> for StoreImmediate {
+    fn from(value: u32) -> Self {
+        Self { val: value }
+    }
+}
+
+impl From<BigImmediate> for u32 {
+    fn from(value: BigImmediate) -> Self {
+        value.val
     }
 }
 
@@ -44,10 +121,113 @@ impl From<u32> for BigImmediate {
     }
 }
 
+impl From<BranchImmediate> for u32 {
+    fn from(value: BranchImmediate) -> Self {
+        value.val
+    }
+}
+
+impl From<u32> for BranchImmediate {
+    fn from(value: u32) -> Self {
+        Self { val: value }
+    }
+}
+
+impl From<JumpImmediate> for u32 {
+    fn from(value: JumpImmediate) -> Self {
+        value.val
+    }
+}
+
+impl From<u32> for JumpImmediate {
+    fn from(value: u32) -> Self {
+        Self { val: value }
+    }
+}
+
 trait SignExtend {
     fn sign_extend(&self) -> i32;
 }
 
+/// Moves an immediate between its value and the bits it occupies in an instruction word
+trait ImmediateLayout {
+    /// Gathers the immediate out of a whole instruction word
+    fn decode(bytes: u32) -> Self;
+    /// Scatters the immediate back into place, every other bit is zero
+    fn encode(&self) -> u32;
+}
+
+impl ImmediateLayout for SmallImmediate {
+    // imm[11:0] is in bits 31:20
+    fn decode(bytes: u32) -> Self {
+        Self::from(bytes >> 20)
+    }
+
+    fn encode(&self) -> u32 {
+        (self.val & 0xFFF) << 20
+    }
+}
+
+impl ImmediateLayout for StoreImmediate {
+    // imm[4:0] is in bits 11:7 and imm[11:5] in bits 31:25
+    fn decode(bytes: u32) -> Self {
+        Self::from(((bytes >> 7) & 0b11111) | ((bytes >> 25) << 5))
+    }
+
+    fn encode(&self) -> u32 {
+        ((self.val & 0b11111) << 7) | (((self.val >> 5) & 0b1111111) << 25)
+    }
+}
+
+impl ImmediateLayout for BranchImmediate {
+    // imm[12|10:5] is in bits 31:25 and imm[4:1|11] in bits 11:7
+    fn decode(bytes: u32) -> Self {
+        Self::from(
+            (((bytes >> 31) & 1) << 12)
+                | (((bytes >> 7) & 1) << 11)
+                | (((bytes >> 25) & 0b111111) << 5)
+                | (((bytes >> 8) & 0b1111) << 1),
+        )
+    }
+
+    fn encode(&self) -> u32 {
+        (((self.val >> 12) & 1) << 31)
+            | (((self.val >> 11) & 1) << 7)
+            | (((self.val >> 5) & 0b111111) << 25)
+            | (((self.val >> 1) & 0b1111) << 8)
+    }
+}
+
+impl ImmediateLayout for JumpImmediate {
+    // imm[20|10:1|11|19:12] is in bits 31|30:21|20|19:12
+    fn decode(bytes: u32) -> Self {
+        Self::from(
+            (((bytes >> 31) & 1) << 20)
+                | (((bytes >> 21) & 0b1111111111) << 1)
+                | (((bytes >> 20) & 1) << 11)
+                | (((bytes >> 12) & 0b11111111) << 12),
+        )
+    }
+
+    fn encode(&self) -> u32 {
+        (((self.val >> 20) & 1) << 31)
+            | (((self.val >> 1) & 0b1111111111) << 21)
+            | (((self.val >> 11) & 1) << 20)
+            | (((self.val >> 12) & 0b11111111) << 12)
+    }
+}
+
+impl ImmediateLayout for BigImmediate {
+    // imm[31:12] is in bits 31:12
+    fn decode(bytes: u32) -> Self {
+        Self::from(bytes >> 12)
+    }
+
+    fn encode(&self) -> u32 {
+        (self.val & 0xFFFFF) << 12
+    }
+}
+
 impl SignExtend for SmallImmediate {
     fn sign_extend(&self) -> i32 {
         let msb = self.val & (1 << 11) != 0;
@@ -55,6 +235,27 @@ impl SignExtend for SmallImmediate {
     }
 }
 
+impl SignExtend for StoreImmediate {
+    fn sign_extend(&self) -> i32 {
+        let msb = self.val & (1 << 11) != 0;
+        transmute_to_signed(if msb { self.val + 0xFFFFF000 } else { self.val })
+    }
+}
+
+impl SignExtend for BranchImmediate {
+    fn sign_extend(&self) -> i32 {
+        let msb = self.val & (1 << 12) != 0;
+        transmute_to_signed(if msb { self.val + 0xFFFFE000 } else { self.val })
+    }
+}
+
+impl SignExtend for JumpImmediate {
+    fn sign_extend(&self) -> i32 {
+        let msb = self.val & (1 << 20) != 0;
+        transmute_to_signed(if msb { self.val + 0xFFE00000 } else { self.val })
+    }
+}
+
 impl SignExtend for BigImmediate {
     fn sign_extend(&self) -> i32 {
         let msb = self.val & (1 << 19) != 0;
@@ -67,104 +268,323 @@ fn test_sign_extension() {
     assert_eq!(1, SmallImmediate::from(1).sign_extend());
     assert_eq!(-1, SmallImmediate::from(2_u32.pow(12) - 1).sign_extend());
 
+    assert_eq!(2, BranchImmediate::from(2).sign_extend());
+    assert_eq!(-2, BranchImmediate::from(2_u32.pow(13) - 2).sign_extend());
+
+    assert_eq!(2, JumpImmediate::from(2).sign_extend());
+    assert_eq!(-2, JumpImmediate::from(2_u32.pow(21) - 2).sign_extend());
+
     assert_eq!(1, BigImmediate::from(1).sign_extend());
     assert_eq!(-1, BigImmediate::from(2_u32.pow(20) - 1).sign_extend());
 }
 
 // Instruction Formats
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct R {
     rd: RegisterPointer,
     rs1: RegisterPointer,
     rs2: RegisterPointer,
 }
 
+impl R {
+    pub fn new(rd: u8, rs1: u8, rs2: u8) -> Self {
+        Self {
+            rd: rd & 0b11111,
+            rs1: rs1 & 0b11111,
+            rs2: rs2 & 0b11111,
+        }
+    }
+
+    /// Register fields in place, every other bit is zero
+    fn encode(&self) -> u32 {
+        ((self.rd as u32) << 7) | ((self.rs1 as u32) << 15) | ((self.rs2 as u32) << 20)
+    }
+}
+
 impl Display for R {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("rd:  x{} | ", self.rd))?;
-        f.write_fmt(format_args!("rs1: x{} | ", self.rs1))?;
-        f.write_fmt(format_args!("rs2: x{}", self.rs2))?;
+        f.write_fmt(format_args!(
+            "rd:  x{}/{} | ",
+            self.rd,
+            register_abi_name(self.rd as usize)
+        ))?;
+        f.write_fmt(format_args!(
+            "rs1: x{}/{} | ",
+            self.rs1,
+            register_abi_name(self.rs1 as usize)
+        ))?;
+        f.write_fmt(format_args!(
+            "rs2: x{}/{}",
+            self.rs2,
+            register_abi_name(self.rs2 as usize)
+        ))?;
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// R format with the acquire and release bits the RV32A instructions keep in funct7
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct A {
+    rd: RegisterPointer,
+    rs1: RegisterPointer,
+    rs2: RegisterPointer,
+    aq: bool,
+    rl: bool,
+}
+
+impl A {
+    pub fn new(rd: u8, rs1: u8, rs2: u8, aq: bool, rl: bool) -> Self {
+        let R { rd, rs1, rs2 } = R::new(rd, rs1, rs2);
+        Self {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        }
+    }
+
+    /// Register and ordering fields in place, every other bit is zero
+    fn encode(&self) -> u32 {
+        ((self.rd as u32) << 7)
+            | ((self.rs1 as u32) << 15)
+            | ((self.rs2 as u32) << 20)
+            | ((self.rl as u32) << 25)
+            | ((self.aq as u32) << 26)
+    }
+}
+
+impl Display for A {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        R {
+            rd: self.rd,
+            rs1: self.rs1,
+            rs2: self.rs2,
+        }
+        .fmt(f)?;
+        if self.aq {
+            f.write_str(" | aq")?;
+        }
+        if self.rl {
+            f.write_str(" | rl")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct I {
     rd: RegisterPointer,
     rs1: RegisterPointer,
     imm: SmallImmediate,
 }
 
+impl I {
+    /// `imm` keeps its low 12 bits, a CSR number or the shift amount and funct7 fit there too
+    pub fn new(rd: u8, rs1: u8, imm: u32) -> Self {
+        Self {
+            rd: rd & 0b11111,
+            rs1: rs1 & 0b11111,
+            imm: SmallImmediate::from(imm & 0xFFF),
+        }
+    }
+
+    /// Register and immediate fields in place, every other bit is zero
+    fn encode(&self) -> u32 {
+        ((self.rd as u32) << 7) | ((self.rs1 as u32) << 15) | self.imm.encode()
+    }
+}
+
 impl Display for I {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("rd:  x{} | ", self.rd))?;
-        f.write_fmt(format_args!("rs1: x{} | ", self.rs1))?;
+        f.write_fmt(format_args!(
+            "rd:  x{}/{} | ",
+            self.rd,
+            register_abi_name(self.rd as usize)
+        ))?;
+        f.write_fmt(format_args!(
+            "rs1: x{}/{} | ",
+            self.rs1,
+            register_abi_name(self.rs1 as usize)
+        ))?;
         f.write_fmt(format_args!("imm: {:#014b}", self.imm.val))?;
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct S {
-    imm: SmallImmediate,
+    imm: StoreImmediate,
     rs1: RegisterPointer,
     rs2: RegisterPointer,
 }
+impl S {
+    /// `imm` keeps its low 12 bits
+    pub fn new(rs1: u8, rs2: u8, imm: u32) -> Self {
+        Self {
+            imm: StoreImmediate::from(imm & 0xFFF),
+            rs1: rs1 & 0b11111,
+            rs2: rs2 & 0b11111,
+        }
+    }
+
+    /// Register and immediate fields in place, every other bit is zero
+    fn encode(&self) -> u32 {
+        ((self.rs1 as u32) << 15) | ((self.rs2 as u32) << 20) | self.imm.encode()
+    }
+}
+
 impl Display for S {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("rs1: x{} | ", self.rs1))?;
-        f.write_fmt(format_args!("rs2: x{} | ", self.rs2))?;
+        f.write_fmt(format_args!(
+            "rs1: x{}/{} | ",
+            self.rs1,
+            register_abi_name(self.rs1 as usize)
+        ))?;
+        f.write_fmt(format_args!(
+            "rs2: x{}/{} | ",
+            self.rs2,
+            register_abi_name(self.rs2 as usize)
+        ))?;
         f.write_fmt(format_args!("imm: {:#014b}", self.imm.val))?;
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct U {
     rd: RegisterPointer,
     imm: BigImmediate,
 }
 
+impl U {
+    /// `imm` is the upper 20 bits of the result, shifted down
+    pub fn new(rd: u8, imm: u32) -> Self {
+        Self {
+            rd: rd & 0b11111,
+            imm: BigImmediate::from(imm & 0xFFFFF),
+        }
+    }
+
+    /// Register and immediate fields in place, every other bit is zero
+    fn encode(&self) -> u32 {
+        ((self.rd as u32) << 7) | self.imm.encode()
+    }
+}
+
 impl Display for U {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("rd:  x{} | ", self.rd))?;
+        f.write_fmt(format_args!(
+            "rd:  x{}/{} | ",
+            self.rd,
+            register_abi_name(self.rd as usize)
+        ))?;
         f.write_fmt(format_args!("imm: {:#022b}", self.imm.val))?;
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 // Immediate mode variants
 pub struct B {
-    imm: SmallImmediate,
+    imm: BranchImmediate,
     rs1: RegisterPointer,
     rs2: RegisterPointer,
 } // Variant of S
 
+impl B {
+    /// `imm` is the byte offset, keeping bits 12:1
+    pub fn new(rs1: u8, rs2: u8, imm: u32) -> Self {
+        Self {
+            imm: BranchImmediate::from(imm & 0x1FFE),
+            rs1: rs1 & 0b11111,
+            rs2: rs2 & 0b11111,
+        }
+    }
+
+    /// Register and immediate fields in place, every other bit is zero
+    fn encode(&self) -> u32 {
+        ((self.rs1 as u32) << 15) | ((self.rs2 as u32) << 20) | self.imm.encode()
+    }
+}
+
 impl Display for B {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("rs1: x{} | ", self.rs1))?;
-        f.write_fmt(format_args!("rs2: x{} | ", self.rs2))?;
-        f.write_fmt(format_args!("imm: {:#014b}", self.imm.val))?;
+        f.write_fmt(format_args!(
+            "rs1: x{}/{} | ",
+            self.rs1,
+            register_abi_name(self.rs1 as usize)
+        ))?;
+        f.write_fmt(format_args!(
+            "rs2: x{}/{} | ",
+            self.rs2,
+            register_abi_name(self.rs2 as usize)
+        ))?;
+        f.write_fmt(format_args!("imm: {:#015b}", self.imm.val))?;
         Ok(())
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct J {
     rd: RegisterPointer,
-    imm: BigImmediate,
+    imm: JumpImmediate,
 } // Variant of U
 
+impl J {
+    /// `imm` is the byte offset, keeping bits 20:1
+    pub fn new(rd: u8, imm: u32) -> Self {
+        Self {
+            rd: rd & 0b11111,
+            imm: JumpImmediate::from(imm & 0x1FFFFE),
+        }
+    }
+
+    /// Register and immediate fields in place, every other bit is zero
+    fn encode(&self) -> u32 {
+        ((self.rd as u32) << 7) | self.imm.encode()
+    }
+}
+
 impl Display for J {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("rd:  x{} | ", self.rd))?;
-        f.write_fmt(format_args!("imm: {:#022b}", self.imm.val))?;
+        f.write_fmt(format_args!(
+            "rd:  x{}/{} | ",
+            self.rd,
+            register_abi_name(self.rd as usize)
+        ))?;
+        f.write_fmt(format_args!("imm: {:#023b}", self.imm.val))?;
         Ok(())
     }
 }
 
-#[derive(Debug)]
+/// Operand fields of any format, for building an [`Instruction`] from its parts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operands {
+    R(R),
+    A(A),
+    I(I),
+    S(S),
+    B(B),
+    U(U),
+    J(J),
+}
+
+impl Operands {
+    fn encode(&self) -> u32 {
+        match self {
+            Operands::R(data) => data.encode(),
+            Operands::A(data) => data.encode(),
+            Operands::I(data) => data.encode(),
+            Operands::S(data) => data.encode(),
+            Operands::B(data) => data.encode(),
+            Operands::U(data) => data.encode(),
+            Operands::J(data) => data.encode(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
     ADD { data: R },
     SUB { data: R },
@@ -177,6 +597,15 @@ pub enum Instruction {
     SLT { data: R },
     SLTU { data: R },
 
+    MUL { data: R },
+    MULH { data: R },
+    MULHSU { data: R },
+    MULHU { data: R },
+    DIV { data: R },
+    DIVU { data: R },
+    REM { data: R },
+    REMU { data: R },
+
     ADDI { data: I },
     XORI { data: I },
     ORI { data: I },
@@ -212,6 +641,30 @@ pub enum Instruction {
 
     ECALL { data: I },
     EBREAK { data: I },
+    MRET { data: I },
+    FENCE { data: I },
+    FENCEI { data: I },
+    // Zicsr, imm holds the CSR number and the immediate forms keep their operand in rs1
+    CSRRW { data: I },
+    CSRRS { data: I },
+    CSRRC { data: I },
+    CSRRWI { data: I },
+    CSRRSI { data: I },
+    CSRRCI { data: I },
+    // RV32A, with one hart every access is already atomic so aq and rl are kept but do nothing
+    LRW { data: A },
+    SCW { data: A },
+    AMOSWAPW { data: A },
+    AMOADDW { data: A },
+    AMOXORW { data: A },
+    AMOANDW { data: A },
+    AMOORW { data: A },
+    AMOMINW { data: A },
+    AMOMAXW { data: A },
+    AMOMINUW { data: A },
+    AMOMAXUW { data: A },
+    // a word the decoder doesn't recognise, kept so it can be shown and faults when run
+    ILLEGAL { raw: u32 },
 }
 
 impl Instruction {
@@ -227,6 +680,14 @@ impl Instruction {
             Instruction::SRA { data } => data.to_string(),
             Instruction::SLT { data } => data.to_string(),
             Instruction::SLTU { data } => data.to_string(),
+            Instruction::MUL { data } => data.to_string(),
+            Instruction::MULH { data } => data.to_string(),
+            Instruction::MULHSU { data } => data.to_string(),
+            Instruction::MULHU { data } => data.to_string(),
+            Instruction::DIV { data } => data.to_string(),
+            Instruction::DIVU { data } => data.to_string(),
+            Instruction::REM { data } => data.to_string(),
+            Instruction::REMU { data } => data.to_string(),
             Instruction::ADDI { data } => data.to_string(),
             Instruction::XORI { data } => data.to_string(),
             Instruction::ORI { data } => data.to_string(),
@@ -256,6 +717,27 @@ impl Instruction {
             Instruction::AUIPC { data } => data.to_string(),
             Instruction::ECALL { data } => data.to_string(),
             Instruction::EBREAK { data } => data.to_string(),
+            Instruction::MRET { data } => data.to_string(),
+            Instruction::FENCE { data } => data.to_string(),
+            Instruction::FENCEI { data } => data.to_string(),
+            Instruction::CSRRW { data } => data.to_string(),
+            Instruction::CSRRS { data } => data.to_string(),
+            Instruction::CSRRC { data } => data.to_string(),
+            Instruction::CSRRWI { data } => data.to_string(),
+            Instruction::CSRRSI { data } => data.to_string(),
+            Instruction::CSRRCI { data } => data.to_string(),
+            Instruction::LRW { data } => data.to_string(),
+            Instruction::SCW { data } => data.to_string(),
+            Instruction::AMOSWAPW { data } => data.to_string(),
+            Instruction::AMOADDW { data } => data.to_string(),
+            Instruction::AMOXORW { data } => data.to_string(),
+            Instruction::AMOANDW { data } => data.to_string(),
+            Instruction::AMOORW { data } => data.to_string(),
+            Instruction::AMOMINW { data } => data.to_string(),
+            Instruction::AMOMAXW { data } => data.to_string(),
+            Instruction::AMOMINUW { data } => data.to_string(),
+            Instruction::AMOMAXUW { data } => data.to_string(),
+            Instruction::ILLEGAL { raw } => format!("0x{:08x}", raw),
         }
     }
 }
@@ -270,42 +752,1183 @@ impl Instruction {
             },
         }
     }
+
+    /// The `info` instruction with `operands`, `None` if they don't make a valid one, like a
+    /// shift amount past 31 or fields of a different format
+    pub fn new(info: &OpcodeInfo, operands: Operands) -> Option<Self> {
+        let inst = try_interpret_bytes(info.encoding() | operands.encode())?;
+        let matches = inst
+            .info()
+            .is_some_and(|found| found.mnemonic == info.mnemonic);
+        let shape = matches!(
+            (info.format, operands, inst.is_atomic()),
+            (Format::R, Operands::R(_), false)
+                | (Format::R, Operands::A(_), true)
+                | (Format::I, Operands::I(_), _)
+                | (Format::S, Operands::S(_), _)
+                | (Format::B, Operands::B(_), _)
+                | (Format::U, Operands::U(_), _)
+                | (Format::J, Operands::J(_), _)
+        );
+        (matches && shape).then_some(inst)
+    }
+
+    /// The instruction word, so `try_interpret_bytes(inst.encode()) == Some(inst)`. The decoder
+    /// keeps every bit it doesn't reject, so decoding then encoding gives back the same word
+    pub fn encode(&self) -> u32 {
+        let operands = match self {
+            Instruction::ILLEGAL { raw } => *raw,
+            Instruction::ADD { data }
+            | Instruction::SUB { data }
+            | Instruction::XOR { data }
+            | Instruction::OR { data }
+            | Instruction::AND { data }
+            | Instruction::SLL { data }
+            | Instruction::SRL { data }
+            | Instruction::SRA { data }
+            | Instruction::SLT { data }
+            | Instruction::SLTU { data }
+            | Instruction::MUL { data }
+            | Instruction::MULH { data }
+            | Instruction::MULHSU { data }
+            | Instruction::MULHU { data }
+            | Instruction::DIV { data }
+            | Instruction::DIVU { data }
+            | Instruction::REM { data }
+            | Instruction::REMU { data } => data.encode(),
+            Instruction::LRW { data }
+            | Instruction::SCW { data }
+            | Instruction::AMOSWAPW { data }
+            | Instruction::AMOADDW { data }
+            | Instruction::AMOXORW { data }
+            | Instruction::AMOANDW { data }
+            | Instruction::AMOORW { data }
+            | Instruction::AMOMINW { data }
+            | Instruction::AMOMAXW { data }
+            | Instruction::AMOMINUW { data }
+            | Instruction::AMOMAXUW { data } => data.encode(),
+            Instruction::ADDI { data }
+            | Instruction::XORI { data }
+            | Instruction::ORI { data }
+            | Instruction::ANDI { data }
+            | Instruction::SLLI { data }
+            | Instruction::SRLI { data }
+            | Instruction::SRAI { data }
+            | Instruction::SLTI { data }
+            | Instruction::SLTUI { data }
+            | Instruction::LB { data }
+            | Instruction::LH { data }
+            | Instruction::LW { data }
+            | Instruction::LBU { data }
+            | Instruction::LHU { data }
+            | Instruction::JALR { data }
+            | Instruction::ECALL { data }
+            | Instruction::EBREAK { data }
+            | Instruction::MRET { data }
+            | Instruction::FENCE { data }
+            | Instruction::FENCEI { data }
+            | Instruction::CSRRW { data }
+            | Instruction::CSRRS { data }
+            | Instruction::CSRRC { data }
+            | Instruction::CSRRWI { data }
+            | Instruction::CSRRSI { data }
+            | Instruction::CSRRCI { data } => data.encode(),
+            Instruction::SB { data } | Instruction::SH { data } | Instruction::SW { data } => {
+                data.encode()
+            }
+            Instruction::BEQ { data }
+            | Instruction::BNE { data }
+            | Instruction::BLT { data }
+            | Instruction::BGE { data }
+            | Instruction::BLTU { data }
+            | Instruction::BGEU { data } => data.encode(),
+            Instruction::LUI { data } | Instruction::AUIPC { data } => data.encode(),
+            Instruction::JAL { data } => data.encode(),
+        };
+        // an illegal word has no table entry and keeps every bit as it was
+        self.info().map_or(0, OpcodeInfo::encoding) | operands
+    }
+
+    /// Entry for this instruction in [`all_opcodes`], `None` for [`Instruction::ILLEGAL`]
+    pub fn info(&self) -> Option<&'static OpcodeInfo> {
+        let index = match self {
+            Instruction::ADD { .. } => 0,
+            Instruction::SUB { .. } => 1,
+            Instruction::XOR { .. } => 2,
+            Instruction::OR { .. } => 3,
+            Instruction::AND { .. } => 4,
+            Instruction::SLL { .. } => 5,
+            Instruction::SRL { .. } => 6,
+            Instruction::SRA { .. } => 7,
+            Instruction::SLT { .. } => 8,
+            Instruction::SLTU { .. } => 9,
+            Instruction::MUL { .. } => 10,
+            Instruction::MULH { .. } => 11,
+            Instruction::MULHSU { .. } => 12,
+            Instruction::MULHU { .. } => 13,
+            Instruction::DIV { .. } => 14,
+            Instruction::DIVU { .. } => 15,
+            Instruction::REM { .. } => 16,
+            Instruction::REMU { .. } => 17,
+            Instruction::ADDI { .. } => 18,
+            Instruction::XORI { .. } => 19,
+            Instruction::ORI { .. } => 20,
+            Instruction::ANDI { .. } => 21,
+            Instruction::SLLI { .. } => 22,
+            Instruction::SRLI { .. } => 23,
+            Instruction::SRAI { .. } => 24,
+            Instruction::SLTI { .. } => 25,
+            Instruction::SLTUI { .. } => 26,
+            Instruction::LB { .. } => 27,
+            Instruction::LH { .. } => 28,
+            Instruction::LW { .. } => 29,
+            Instruction::LBU { .. } => 30,
+            Instruction::LHU { .. } => 31,
+            Instruction::SB { .. } => 32,
+            Instruction::SH { .. } => 33,
+            Instruction::SW { .. } => 34,
+            Instruction::BEQ { .. } => 35,
+            Instruction::BNE { .. } => 36,
+            Instruction::BLT { .. } => 37,
+            Instruction::BGE { .. } => 38,
+            Instruction::BLTU { .. } => 39,
+            Instruction::BGEU { .. } => 40,
+            Instruction::JAL { .. } => 41,
+            Instruction::JALR { .. } => 42,
+            Instruction::LUI { .. } => 43,
+            Instruction::AUIPC { .. } => 44,
+            Instruction::ECALL { .. } => 45,
+            Instruction::EBREAK { .. } => 46,
+            Instruction::MRET { .. } => 47,
+            Instruction::FENCE { .. } => 48,
+            Instruction::FENCEI { .. } => 49,
+            Instruction::CSRRW { .. } => 50,
+            Instruction::CSRRS { .. } => 51,
+            Instruction::CSRRC { .. } => 52,
+            Instruction::CSRRWI { .. } => 53,
+            Instruction::CSRRSI { .. } => 54,
+            Instruction::CSRRCI { .. } => 55,
+            Instruction::LRW { .. } => 56,
+            Instruction::SCW { .. } => 57,
+            Instruction::AMOSWAPW { .. } => 58,
+            Instruction::AMOADDW { .. } => 59,
+            Instruction::AMOXORW { .. } => 60,
+            Instruction::AMOANDW { .. } => 61,
+            Instruction::AMOORW { .. } => 62,
+            Instruction::AMOMINW { .. } => 63,
+            Instruction::AMOMAXW { .. } => 64,
+            Instruction::AMOMINUW { .. } => 65,
+            Instruction::AMOMAXUW { .. } => 66,
+            Instruction::ILLEGAL { .. } => return None,
+        };
+        Some(&OPCODES[index])
+    }
+
+    /// The canonical `addi x0, x0, 0` encoding, other writes to x0 are hints that do nothing too
+    pub fn is_nop(&self) -> bool {
+        matches!(self, Instruction::ADDI { data } if data.rd == 0 && data.rs1 == 0 && data.imm.val == 0)
+    }
+
+    /// One of the RV32A load-reserved, store-conditional or AMO instructions
+    pub fn is_atomic(&self) -> bool {
+        matches!(
+            self,
+            Instruction::LRW { .. }
+                | Instruction::SCW { .. }
+                | Instruction::AMOSWAPW { .. }
+                | Instruction::AMOADDW { .. }
+                | Instruction::AMOXORW { .. }
+                | Instruction::AMOANDW { .. }
+                | Instruction::AMOORW { .. }
+                | Instruction::AMOMINW { .. }
+                | Instruction::AMOMAXW { .. }
+                | Instruction::AMOMINUW { .. }
+                | Instruction::AMOMAXUW { .. }
+        )
+    }
+
+    /// A jump linking through `ra` or `t0`, which the calling convention treats as a call
+    pub fn is_call(&self) -> bool {
+        match self {
+            Instruction::JAL { data } => matches!(data.rd, 1 | 5),
+            Instruction::JALR { data } => matches!(data.rd, 1 | 5),
+            _ => false,
+        }
+    }
+
+    /// `jalr x0` through `ra` or `t0`, a return by the calling convention
+    pub fn is_return(&self) -> bool {
+        matches!(self, Instruction::JALR { data } if data.rd == 0 && matches!(data.rs1, 1 | 5))
+    }
+
+    /// Whether this may leave pc somewhere other than the next instruction
+    pub fn is_branch_or_jump(&self) -> bool {
+        matches!(
+            self,
+            Instruction::BEQ { .. }
+                | Instruction::BNE { .. }
+                | Instruction::BLT { .. }
+                | Instruction::BGE { .. }
+                | Instruction::BLTU { .. }
+                | Instruction::BGEU { .. }
+                | Instruction::JAL { .. }
+                | Instruction::JALR { .. }
+        )
+    }
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(
-            format_args!("{:?}", self)
-                .to_string()
-                .split_whitespace()
-                .next()
-                .unwrap(),
-        )?;
-        f.write_fmt(format_args!(" {}", self.get_payload()))?;
+        if self.is_nop() {
+            return f.write_str("NOP");
+        }
+        // only ILLEGAL has no table entry, its payload is the raw word
+        let name = self.info().map_or("illegal", |info| info.mnemonic);
+        f.write_fmt(format_args!("{} {}", name, self.get_payload()))?;
         Ok(())
     }
 }
 
-#[derive(Clone)]
+/// Faults raised while decoding or executing instructions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VmError {
+    /// The word doesn't decode to any supported instruction
+    IllegalInstruction(u32),
+    /// The instruction decodes but the emulator can't execute it yet
+    Unimplemented(&'static str),
+    /// Fetching reached past the end of memory, at this address. That's pc, or pc + 2 for an
+    /// instruction whose upper half is missing
+    FetchOutOfBounds(u32),
+    /// pc isn't a multiple of two
+    MisalignedPc(u32),
+    /// The machine already halted, see [`ArchState::halted`]
+    Halted,
+    /// The file isn't a loadable 32-bit little-endian RISC-V ELF
+    InvalidElf(&'static str),
+    /// A record of an Intel HEX or S-record file is malformed, `line` counts from 1
+    InvalidHexFile { line: usize, reason: &'static str },
+    /// A CSR instruction tried to write a read-only CSR such as `cycle`
+    ReadOnlyCsr(u16),
+    /// Ran this many instructions without reaching what the caller was waiting for
+    StepLimit(u64),
+    /// A load or store of `width` bytes wasn't naturally aligned, see [`ArchState::strict_alignment`]
+    Misaligned { addr: u32, width: u32 },
+    /// A load or store reached past the end of memory
+    OutOfBounds { addr: u32 },
+    /// `ecall` ran with neither a syscall handler nor a trap vector to take it
+    Ecall,
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmError::IllegalInstruction(raw) => {
+                f.write_fmt(format_args!("illegal instruction 0x{:08x}", raw))
+            }
+            VmError::Unimplemented(mnemonic) => {
+                f.write_fmt(format_args!("{} is not implemented", mnemonic))
+            }
+            VmError::FetchOutOfBounds(pc) => {
+                f.write_fmt(format_args!("fetch from 0x{:08x} is out of bounds", pc))
+            }
+            VmError::MisalignedPc(pc) => f.write_fmt(format_args!("misaligned pc 0x{:08x}", pc)),
+            VmError::Halted => f.write_str("machine is halted"),
+            VmError::InvalidElf(reason) => f.write_fmt(format_args!("invalid ELF: {}", reason)),
+            VmError::InvalidHexFile { line, reason } => {
+                f.write_fmt(format_args!("invalid hex file, line {}: {}", line, reason))
+            }
+            VmError::ReadOnlyCsr(csr) => {
+                f.write_fmt(format_args!("CSR 0x{:03x} is read-only", csr))
+            }
+            VmError::StepLimit(steps) => {
+                f.write_fmt(format_args!("gave up after {} instructions", steps))
+            }
+            VmError::Misaligned { addr, width } => f.write_fmt(format_args!(
+                "misaligned {} byte access at 0x{:08x}",
+                width, addr
+            )),
+            VmError::OutOfBounds { addr } => {
+                f.write_fmt(format_args!("access at 0x{:08x} is out of bounds", addr))
+            }
+            VmError::Ecall => f.write_str("ecall with no handler"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+/// Things worth pointing out about an instruction that still executed normally
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VmEvent {
+    /// The instruction at `pc` tried to write `val` to x0, see [`ArchState::strict_x0`]
+    IgnoredX0Write { pc: u32, val: u32 },
+    /// The instruction at `pc` keeps jumping to itself, see [`ArchState::idle_limit`]
+    IdleLoop { pc: u32 },
+}
+
+impl Display for VmEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VmEvent::IgnoredX0Write { pc, val } => f.write_fmt(format_args!(
+                "0x{:08x} wrote 0x{:08x} to x0, which always reads as zero",
+                pc, val
+            )),
+            VmEvent::IdleLoop { pc } => {
+                f.write_fmt(format_args!("idle loop detected at 0x{:08x}", pc))
+            }
+        }
+    }
+}
+
+/// A store wrote a watched byte, see [`ArchState::add_watchpoint`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// Address of the store instruction
+    pub pc: u32,
+    pub addr: u32,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// How a run ended and the registers it left behind, see [`ArchState::run`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunReport {
+    /// Instructions that retired
+    pub steps: u64,
+    pub pc: u32,
+    /// x0 through x31
+    pub registers: [u32; 32],
+    pub halted: bool,
+    pub exit_status: Option<u32>,
+    pub stop: StopReason,
+}
+
+/// Why [`ArchState::run`] returned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The program exited, through the `exit` syscall or a store to `halt_addr`
+    Exited(u32),
+    /// Halted without an exit status, e.g. `EBREAK` outside the debugger
+    Halted,
+    /// Reached a breakpoint or `EBREAK` in interactive mode
+    Paused,
+    /// Ran the whole instruction budget
+    BudgetExhausted,
+    /// Spun at this pc for [`ArchState::idle_limit`] ticks
+    IdleLoop(u32),
+    Fault(VmError),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Exited(status) => f.write_fmt(format_args!("exited with {}", status)),
+            StopReason::Halted => f.write_str("halted"),
+            StopReason::Paused => f.write_str("paused"),
+            StopReason::BudgetExhausted => f.write_str("instruction budget exhausted"),
+            StopReason::IdleLoop(pc) => {
+                f.write_fmt(format_args!("idle loop detected at 0x{:08x}", pc))
+            }
+            StopReason::Fault(err) => f.write_fmt(format_args!("fault: {}", err)),
+        }
+    }
+}
+
+/// Granularity of [`ArchState::populated_pages`]
+pub const PAGE_SIZE: usize = 4096;
+
+/// Small seeded generator for [`ArchState::register_seed`], not meant to be cryptographic
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Bytes [`ArchState::stack_reserved`] keeps free at the top of memory unless changed
+pub const DEFAULT_STACK_RESERVED: u32 = 16;
+
+/// Hook run after every retired instruction, see [`ArchState::on_retire`]
+pub type RetireCallback = Box<dyn FnMut(&ArchState, &Instruction) + Send>;
+
 pub struct ArchState {
     regs: [u32; 31], // x0 is handled in the getter
     pub pc: i64,     // must be able to be negative so we can jump to 0
-    pub mem: Vec<u8>,
+    pub mem: Memory,
+    /// Where pc starts after a reset, independent of where segments are loaded
+    pub reset_vector: u32,
+    /// A store to this address halts the machine with the stored value as the exit status
+    pub halt_addr: Option<u32>,
+    /// Value stored to `halt_addr` when the machine halted that way
+    pub exit_status: Option<u32>,
+    /// Set once the machine has halted, `tick` refuses to run after this
+    pub halted: bool,
+    /// Running under a debugger, so `EBREAK` pauses instead of halting
+    pub interactive: bool,
+    /// Set by `EBREAK` in interactive mode or on reaching a breakpoint, the debugger clears it when resuming
+    pub paused: bool,
+    /// The error that made the debugger pause, for it to show
+    pub fault: Option<VmError>,
+    /// Flags nonzero writes to x0 in `last_event`, as a hint for code that targets x0 by mistake.
+    /// Jumps linking through x0 (`j`, `ret`) discard the link on purpose and aren't flagged
+    pub strict_x0: bool,
+    /// Set by the last applied instruction, cleared at the start of every `apply`
+    pub last_event: Option<VmEvent>,
+    /// Memory addresses stored to by the last applied instruction, cleared with `last_event`
+    pub last_written: Vec<u32>,
+    /// Faults misaligned halfword and word loads and stores instead of splitting them into bytes
+    pub strict_alignment: bool,
+    /// Fills x1..x31 with pseudo-random values from this seed on reset instead of zeroing them,
+    /// so programs relying on zeroed registers fail visibly but reproducibly
+    pub register_seed: Option<u64>,
+    /// Bytes kept free above the stack at the top of memory. On construction and every reset
+    /// `sp` starts at the top of memory less this, rounded down to the 16 bytes the calling
+    /// convention aligns the stack to. `None` leaves `sp` zeroed like the other registers
+    pub stack_reserved: Option<u32>,
+    /// Pause once pc and every register have stayed the same for this many ticks in a row
+    /// without a store, which catches spins like `j .`. Off when `None`
+    pub idle_limit: Option<u32>,
+    /// Function and object names by address, used to label addresses. Kept across resets
+    pub symbols: BTreeMap<u32, String>,
+    breakpoints: HashSet<u32>,
+    /// Ticks in a row that changed nothing but the counters, see [`ArchState::idle_limit`]
+    idle_ticks: u32,
+    /// Word reserved by the last `lr.w`, cleared by `sc.w` and on reset
+    reservation: Option<u32>,
+    /// Breakpoint removed the first time it is hit, see [`ArchState::run_to`]
+    temporary_breakpoint: Option<u32>,
+    watchpoints: HashSet<u32>,
+    /// Watched bytes written by stores, oldest first. Whoever pauses on them drains the queue
+    pub watch_events: VecDeque<WatchEvent>,
+    /// Written CSRs, the counters are computed from `retired` instead, see [`ArchState::read_csr`]
+    csrs: HashMap<u16, u32>,
+    /// Handler given to [`ArchState::set_trap_vector`], put back in `mtvec` on reset
+    host_trap_vector: Option<u32>,
+    /// Instructions retired by `tick` since the last reset
+    retired: u64,
+    /// Cycles those instructions took under `timing`
+    cycles: u64,
+    timing: Option<TimingModel>,
+    stats: InstructionStats,
+    on_retire: Option<RetireCallback>,
+    trace: Option<TraceSink>,
+    syscall_handler: Option<Box<dyn SyscallHandler>>,
+    mmio: Vec<(Range<u32>, Box<dyn MmioDevice>)>,
+    history: History,
+    /// Decoded instructions and their lengths by address, see [`ArchState::clear_decode_cache`]
+    decode_cache: HashMap<u32, (Instruction, u32)>,
+    decode_cache_hits: u64,
+}
+
+/// Produces the bytes of a segment, called again every time the program is reloaded
+pub type SegmentSource = Box<dyn Fn() -> io::Result<Vec<u8>> + Send>;
+
+/// A piece of the program and the address it is loaded at
+pub struct Segment {
+    pub source: SegmentSource,
+    pub offset: usize,
 }
 
-fn transmute_to_signed(unsigned: u32) -> i32 {
-    unsafe { std::mem::transmute(unsigned) }
+impl Segment {
+    pub fn from_file(path: PathBuf, offset: usize) -> Self {
+        Self {
+            source: Box::new(move || fs::read(&path)),
+            offset,
+        }
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>, offset: usize) -> Self {
+        Self {
+            source: Box::new(move || Ok(bytes.clone())),
+            offset,
+        }
+    }
+}
+
+/// Encoding format of an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    R,
+    I,
+    S,
+    B,
+    U,
+    J,
+}
+
+/// Fixed encoding fields and a short description of a supported instruction
+#[derive(Clone, Copy, Debug)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    pub format: Format,
+    pub opcode: u32,
+    pub funct3: Option<u32>,
+    /// Bits 31:25, also used by the shift immediates
+    pub funct7: Option<u32>,
+    /// Bits 31:20, only used by the system instructions
+    pub funct12: Option<u32>,
+    pub description: &'static str,
+}
+
+impl OpcodeInfo {
+    /// The instruction word with every operand field zeroed
+    pub fn encoding(&self) -> u32 {
+        self.opcode
+            | (self.funct3.unwrap_or(0) << 12)
+            | (self.funct7.unwrap_or(0) << 25)
+            | (self.funct12.unwrap_or(0) << 20)
+    }
+}
+
+const OPCODES: [OpcodeInfo; 67] = [
+    OpcodeInfo {
+        mnemonic: "ADD",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b000),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = rs1 + rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "SUB",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b000),
+        funct7: Some(0b0100000),
+        funct12: None,
+        description: "rd = rs1 - rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "XOR",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b100),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = rs1 ^ rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "OR",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b110),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = rs1 | rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "AND",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b111),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = rs1 & rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "SLL",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b001),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = rs1 << rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "SRL",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b101),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = rs1 >> rs2, zero filled",
+    },
+    OpcodeInfo {
+        mnemonic: "SRA",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b101),
+        funct7: Some(0b0100000),
+        funct12: None,
+        description: "rd = rs1 >> rs2, sign filled",
+    },
+    OpcodeInfo {
+        mnemonic: "SLT",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b010),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = 1 if rs1 < rs2, signed",
+    },
+    OpcodeInfo {
+        mnemonic: "SLTU",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b011),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = 1 if rs1 < rs2, unsigned",
+    },
+    OpcodeInfo {
+        mnemonic: "MUL",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b000),
+        funct7: Some(0b0000001),
+        funct12: None,
+        description: "rd = low 32 bits of rs1 * rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "MULH",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b001),
+        funct7: Some(0b0000001),
+        funct12: None,
+        description: "rd = high 32 bits of rs1 * rs2, signed",
+    },
+    OpcodeInfo {
+        mnemonic: "MULHSU",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b010),
+        funct7: Some(0b0000001),
+        funct12: None,
+        description: "rd = high 32 bits of rs1 * rs2, signed by unsigned",
+    },
+    OpcodeInfo {
+        mnemonic: "MULHU",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b011),
+        funct7: Some(0b0000001),
+        funct12: None,
+        description: "rd = high 32 bits of rs1 * rs2, unsigned",
+    },
+    OpcodeInfo {
+        mnemonic: "DIV",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b100),
+        funct7: Some(0b0000001),
+        funct12: None,
+        description: "rd = rs1 / rs2, signed",
+    },
+    OpcodeInfo {
+        mnemonic: "DIVU",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b101),
+        funct7: Some(0b0000001),
+        funct12: None,
+        description: "rd = rs1 / rs2, unsigned",
+    },
+    OpcodeInfo {
+        mnemonic: "REM",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b110),
+        funct7: Some(0b0000001),
+        funct12: None,
+        description: "rd = rs1 % rs2, signed",
+    },
+    OpcodeInfo {
+        mnemonic: "REMU",
+        format: Format::R,
+        opcode: 0b0110011,
+        funct3: Some(0b111),
+        funct7: Some(0b0000001),
+        funct12: None,
+        description: "rd = rs1 % rs2, unsigned",
+    },
+    OpcodeInfo {
+        mnemonic: "ADDI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: None,
+        description: "rd = rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "XORI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b100),
+        funct7: None,
+        funct12: None,
+        description: "rd = rs1 ^ imm",
+    },
+    OpcodeInfo {
+        mnemonic: "ORI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b110),
+        funct7: None,
+        funct12: None,
+        description: "rd = rs1 | imm",
+    },
+    OpcodeInfo {
+        mnemonic: "ANDI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b111),
+        funct7: None,
+        funct12: None,
+        description: "rd = rs1 & imm",
+    },
+    OpcodeInfo {
+        mnemonic: "SLLI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b001),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = rs1 << shamt",
+    },
+    OpcodeInfo {
+        mnemonic: "SRLI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b101),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = rs1 >> shamt, zero filled",
+    },
+    OpcodeInfo {
+        mnemonic: "SRAI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b101),
+        funct7: Some(0b0100000),
+        funct12: None,
+        description: "rd = rs1 >> shamt, sign filled",
+    },
+    OpcodeInfo {
+        mnemonic: "SLTI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b010),
+        funct7: None,
+        funct12: None,
+        description: "rd = 1 if rs1 < imm, signed",
+    },
+    OpcodeInfo {
+        mnemonic: "SLTUI",
+        format: Format::I,
+        opcode: 0b0010011,
+        funct3: Some(0b011),
+        funct7: None,
+        funct12: None,
+        description: "rd = 1 if rs1 < imm, unsigned",
+    },
+    OpcodeInfo {
+        mnemonic: "LB",
+        format: Format::I,
+        opcode: 0b0000011,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: None,
+        description: "rd = sign extended byte at rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "LH",
+        format: Format::I,
+        opcode: 0b0000011,
+        funct3: Some(0b001),
+        funct7: None,
+        funct12: None,
+        description: "rd = sign extended half at rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "LW",
+        format: Format::I,
+        opcode: 0b0000011,
+        funct3: Some(0b010),
+        funct7: None,
+        funct12: None,
+        description: "rd = word at rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "LBU",
+        format: Format::I,
+        opcode: 0b0000011,
+        funct3: Some(0b100),
+        funct7: None,
+        funct12: None,
+        description: "rd = zero extended byte at rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "LHU",
+        format: Format::I,
+        opcode: 0b0000011,
+        funct3: Some(0b101),
+        funct7: None,
+        funct12: None,
+        description: "rd = zero extended half at rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "SB",
+        format: Format::S,
+        opcode: 0b0100011,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: None,
+        description: "store low byte of rs2 at rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "SH",
+        format: Format::S,
+        opcode: 0b0100011,
+        funct3: Some(0b001),
+        funct7: None,
+        funct12: None,
+        description: "store low half of rs2 at rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "SW",
+        format: Format::S,
+        opcode: 0b0100011,
+        funct3: Some(0b010),
+        funct7: None,
+        funct12: None,
+        description: "store rs2 at rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "BEQ",
+        format: Format::B,
+        opcode: 0b1100011,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: None,
+        description: "branch if rs1 == rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "BNE",
+        format: Format::B,
+        opcode: 0b1100011,
+        funct3: Some(0b001),
+        funct7: None,
+        funct12: None,
+        description: "branch if rs1 != rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "BLT",
+        format: Format::B,
+        opcode: 0b1100011,
+        funct3: Some(0b100),
+        funct7: None,
+        funct12: None,
+        description: "branch if rs1 < rs2, signed",
+    },
+    OpcodeInfo {
+        mnemonic: "BGE",
+        format: Format::B,
+        opcode: 0b1100011,
+        funct3: Some(0b101),
+        funct7: None,
+        funct12: None,
+        description: "branch if rs1 >= rs2, signed",
+    },
+    OpcodeInfo {
+        mnemonic: "BLTU",
+        format: Format::B,
+        opcode: 0b1100011,
+        funct3: Some(0b110),
+        funct7: None,
+        funct12: None,
+        description: "branch if rs1 < rs2, unsigned",
+    },
+    OpcodeInfo {
+        mnemonic: "BGEU",
+        format: Format::B,
+        opcode: 0b1100011,
+        funct3: Some(0b111),
+        funct7: None,
+        funct12: None,
+        description: "branch if rs1 >= rs2, unsigned",
+    },
+    OpcodeInfo {
+        mnemonic: "JAL",
+        format: Format::J,
+        opcode: 0b1101111,
+        funct3: None,
+        funct7: None,
+        funct12: None,
+        description: "rd = pc + 4, jump to pc + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "JALR",
+        format: Format::I,
+        opcode: 0b1100111,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: None,
+        description: "rd = pc + 4, jump to rs1 + imm",
+    },
+    OpcodeInfo {
+        mnemonic: "LUI",
+        format: Format::U,
+        opcode: 0b0110111,
+        funct3: None,
+        funct7: None,
+        funct12: None,
+        description: "rd = imm << 12",
+    },
+    OpcodeInfo {
+        mnemonic: "AUIPC",
+        format: Format::U,
+        opcode: 0b0010111,
+        funct3: None,
+        funct7: None,
+        funct12: None,
+        description: "rd = pc + (imm << 12)",
+    },
+    OpcodeInfo {
+        mnemonic: "ECALL",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: Some(0),
+        description: "call into the execution environment",
+    },
+    OpcodeInfo {
+        mnemonic: "EBREAK",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: Some(1),
+        description: "break into the debugger",
+    },
+    OpcodeInfo {
+        mnemonic: "MRET",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: Some(0x302),
+        description: "return from a trap handler, pc = mepc",
+    },
+    OpcodeInfo {
+        mnemonic: "FENCE",
+        format: Format::I,
+        opcode: 0b0001111,
+        funct3: Some(0b000),
+        funct7: None,
+        funct12: None,
+        description: "order memory accesses, a no-op on this single hart",
+    },
+    OpcodeInfo {
+        mnemonic: "FENCEI",
+        format: Format::I,
+        opcode: 0b0001111,
+        funct3: Some(0b001),
+        funct7: None,
+        funct12: None,
+        description: "make earlier stores visible to instruction fetch",
+    },
+    OpcodeInfo {
+        mnemonic: "CSRRW",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b001),
+        funct7: None,
+        funct12: None,
+        description: "rd = csr, csr = rs1",
+    },
+    OpcodeInfo {
+        mnemonic: "CSRRS",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b010),
+        funct7: None,
+        funct12: None,
+        description: "rd = csr, csr |= rs1",
+    },
+    OpcodeInfo {
+        mnemonic: "CSRRC",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b011),
+        funct7: None,
+        funct12: None,
+        description: "rd = csr, csr &= ~rs1",
+    },
+    OpcodeInfo {
+        mnemonic: "CSRRWI",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b101),
+        funct7: None,
+        funct12: None,
+        description: "rd = csr, csr = uimm",
+    },
+    OpcodeInfo {
+        mnemonic: "CSRRSI",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b110),
+        funct7: None,
+        funct12: None,
+        description: "rd = csr, csr |= uimm",
+    },
+    OpcodeInfo {
+        mnemonic: "CSRRCI",
+        format: Format::I,
+        opcode: 0b1110011,
+        funct3: Some(0b111),
+        funct7: None,
+        funct12: None,
+        description: "rd = csr, csr &= ~uimm",
+    },
+    OpcodeInfo {
+        mnemonic: "LRW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b0001000),
+        funct12: None,
+        description: "rd = mem[rs1], reserves rs1",
+    },
+    OpcodeInfo {
+        mnemonic: "SCW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b0001100),
+        funct12: None,
+        description: "mem[rs1] = rs2 and rd = 0 if rs1 is reserved, else rd = 1",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOSWAPW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b0000100),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] = rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOADDW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b0000000),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] += rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOXORW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b0010000),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] ^= rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOANDW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b0110000),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] &= rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOORW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b0100000),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] |= rs2",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOMINW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b1000000),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] = min(mem[rs1], rs2)",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOMAXW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b1010000),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] = max(mem[rs1], rs2)",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOMINUW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b1100000),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] = min(mem[rs1], rs2) (unsigned)",
+    },
+    OpcodeInfo {
+        mnemonic: "AMOMAXUW",
+        format: Format::R,
+        opcode: 0b0101111,
+        funct3: Some(0b010),
+        funct7: Some(0b1110000),
+        funct12: None,
+        description: "rd = mem[rs1], mem[rs1] = max(mem[rs1], rs2) (unsigned)",
+    },
+];
+
+/// Every instruction the decoder supports
+pub fn all_opcodes() -> &'static [OpcodeInfo] {
+    &OPCODES
+}
+
+pub(crate) fn transmute_to_signed(unsigned: u32) -> i32 {
+    unsigned as i32
 }
 
 fn transmute_to_unsigned(signed: i32) -> u32 {
-    unsafe { std::mem::transmute(signed) }
+    signed as u32
 }
 
+/// Two's complement `a < b`, compared directly since `a - b` can overflow
+fn less_than_signed(a: u32, b: u32) -> bool {
+    transmute_to_signed(a) < transmute_to_signed(b)
+}
+
+/// Decodes a word, anything unrecognised comes back as [`Instruction::ILLEGAL`]
 pub fn interpret_bytes(bytes: u32) -> Instruction {
+    try_interpret_bytes(bytes).unwrap_or(Instruction::ILLEGAL { raw: bytes })
+}
+
+/// Decodes each whole little-endian word of `mem[start..end]` with its address, unknown words
+/// as [`Instruction::ILLEGAL`] like [`interpret_bytes`]. `end` is clamped to `mem`, and a trailing partial word is skipped
+pub fn decode_range(mem: &[u8], start: u32, end: u32) -> impl Iterator<Item = (u32, Instruction)> {
+    let end = (end as usize).min(mem.len());
+    (start as usize..end)
+        .step_by(4)
+        .filter(move |&addr| addr + 4 <= end)
+        .map(|addr| {
+            let word = u32::from_le_bytes([mem[addr], mem[addr + 1], mem[addr + 2], mem[addr + 3]]);
+            (addr as u32, interpret_bytes(word))
+        })
+}
+
+/// Decodes a word, returning `None` for opcode/funct combinations the decoder doesn't know
+pub fn try_interpret_bytes(bytes: u32) -> Option<Instruction> {
     let opcode = bytes & 0b1111111;
     let func3 = (bytes & (0b111 << 12)) >> 12;
 
-    match opcode {
+    Some(match opcode {
         0b0110011 => {
             // integer register to register
             let data = R {
@@ -313,19 +1936,28 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
                 rs1: (bytes >> 15) as u8 & 0b11111,
                 rs2: (bytes >> 20) as u8 & 0b11111,
             };
-            // check func3 and 30 bit for function
-            match func3 + (bytes >> 27) {
-                0b0000 => Instruction::ADD { data },
-                0b1000 => Instruction::SUB { data },
-                0b0001 | 0b1001 => Instruction::SLL { data },
-                0b0010 | 0b1010 => Instruction::SLT { data },
-                0b0011 | 0b1011 => Instruction::SLTU { data },
-                0b0100 | 0b1100 => Instruction::XOR { data },
-                0b0101 => Instruction::SRL { data },
-                0b1101 => Instruction::SRA { data },
-                0b0110 | 0b1110 => Instruction::OR { data },
-                0b0111 | 0b1111 => Instruction::AND { data },
-                _ => Instruction::nop(),
+            // funct7 picks the base op, SUB and SRA set bit 30 and RV32M is funct7 = 1.
+            // Every other funct7 is reserved
+            match (bytes >> 25, func3) {
+                (0b0000000, 0b000) => Instruction::ADD { data },
+                (0b0100000, 0b000) => Instruction::SUB { data },
+                (0b0000000, 0b001) => Instruction::SLL { data },
+                (0b0000000, 0b010) => Instruction::SLT { data },
+                (0b0000000, 0b011) => Instruction::SLTU { data },
+                (0b0000000, 0b100) => Instruction::XOR { data },
+                (0b0000000, 0b101) => Instruction::SRL { data },
+                (0b0100000, 0b101) => Instruction::SRA { data },
+                (0b0000000, 0b110) => Instruction::OR { data },
+                (0b0000000, 0b111) => Instruction::AND { data },
+                (0b0000001, 0b000) => Instruction::MUL { data },
+                (0b0000001, 0b001) => Instruction::MULH { data },
+                (0b0000001, 0b010) => Instruction::MULHSU { data },
+                (0b0000001, 0b011) => Instruction::MULHU { data },
+                (0b0000001, 0b100) => Instruction::DIV { data },
+                (0b0000001, 0b101) => Instruction::DIVU { data },
+                (0b0000001, 0b110) => Instruction::REM { data },
+                (0b0000001, 0b111) => Instruction::REMU { data },
+                _ => return None,
             }
         }
         0b0010011 => {
@@ -333,7 +1965,7 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
             let data = I {
                 rd: (bytes >> 7) as u8 & 0b11111,
                 rs1: (bytes >> 15) as u8 & 0b11111,
-                imm: SmallImmediate::from(bytes >> 20),
+                imm: SmallImmediate::decode(bytes),
             };
             match func3 {
                 0b000 => Instruction::ADDI { data },
@@ -342,16 +1974,11 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
                 0b100 => Instruction::XORI { data },
                 0b110 => Instruction::ORI { data },
                 0b111 => Instruction::ANDI { data },
-                0b001 => Instruction::SLLI { data },
-                // Check f7
-                0b101 => {
-                    if bytes & 2_u32.pow(30) == 0 {
-                        Instruction::SRLI { data }
-                    } else {
-                        Instruction::SRAI { data }
-                    }
-                }
-                _ => Instruction::nop(),
+                // imm[11:5] is funct7 for the shifts, shamt[5] is reserved on RV32
+                0b001 if bytes >> 25 == 0b0000000 => Instruction::SLLI { data },
+                0b101 if bytes >> 25 == 0b0000000 => Instruction::SRLI { data },
+                0b101 if bytes >> 25 == 0b0100000 => Instruction::SRAI { data },
+                _ => return None,
             }
         }
         0b0100011 => {
@@ -359,13 +1986,13 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
             let data = S {
                 rs1: (bytes >> 15) as u8 & 0b11111,
                 rs2: (bytes >> 20) as u8 & 0b11111,
-                imm: SmallImmediate::from((bytes >> 7) & 0b11111 + (bytes >> 24)),
+                imm: StoreImmediate::decode(bytes),
             };
             match func3 {
                 0b000 => Instruction::SB { data },
                 0b001 => Instruction::SH { data },
                 0b010 => Instruction::SW { data },
-                _ => Instruction::nop(),
+                _ => return None,
             }
         }
         0b0000011 => {
@@ -373,7 +2000,7 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
             let data = I {
                 rd: (bytes >> 7) as u8 & 0b11111,
                 rs1: (bytes >> 15) as u8 & 0b11111,
-                imm: SmallImmediate::from(bytes >> 20),
+                imm: SmallImmediate::decode(bytes),
             };
             match func3 {
                 0b000 => Instruction::LB { data },
@@ -381,16 +2008,19 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
                 0b010 => Instruction::LW { data },
                 0b100 => Instruction::LBU { data },
                 0b101 => Instruction::LHU { data },
-                _ => Instruction::nop(),
+                _ => return None,
             }
         }
         0b1100111 => {
-            // JALR
+            // JALR, the only func3 is 0
+            if func3 != 0 {
+                return None;
+            }
             Instruction::JALR {
                 data: I {
-                    rd: (bytes >> 7) as u8,
-                    rs1: (bytes >> 15) as u8,
-                    imm: SmallImmediate::from(bytes >> 20),
+                    rd: (bytes >> 7) as u8 & 0b11111,
+                    rs1: (bytes >> 15) as u8 & 0b11111,
+                    imm: SmallImmediate::decode(bytes),
                 },
             }
         }
@@ -399,13 +2029,7 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
             let data = B {
                 rs1: (bytes >> 15) as u8 & 0b11111,
                 rs2: (bytes >> 20) as u8 & 0b11111,
-                imm: SmallImmediate::from(
-                    (((bytes >> 7) & 0b11111 +
-                    (bytes >> 24)) & 0b111111111100) +
-                    // lower order bits are moved to higher order for branches
-                    ((bytes & 128) << (11 - 7)) +
-                    ((bytes & 2_u32.pow(31) >> (31 - 12))),
-                ),
+                imm: BranchImmediate::decode(bytes),
             };
             match func3 {
                 0b000 => Instruction::BEQ { data },
@@ -414,7 +2038,7 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
                 0b101 => Instruction::BGE { data },
                 0b110 => Instruction::BLTU { data },
                 0b111 => Instruction::BGEU { data },
-                _ => Instruction::nop(),
+                _ => return None,
             }
         }
         0b1101111 => {
@@ -422,12 +2046,7 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
             Instruction::JAL {
                 data: J {
                     rd: (bytes >> 7) as u8 & 0b11111,
-                    imm: BigImmediate::from(
-                        ((bytes >> 20) & 0b1111111111)
-                            + (((bytes >> 20) & 1) << 10)
-                            + (((bytes >> 12) & 0b11111111) << 11)
-                            + (((bytes >> 30) & 1) << 19),
-                    ),
+                    imm: JumpImmediate::decode(bytes),
                 },
             }
         }
@@ -436,7 +2055,7 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
             Instruction::LUI {
                 data: U {
                     rd: (bytes >> 7) as u8 & 0b11111,
-                    imm: BigImmediate::from(bytes >> 12),
+                    imm: BigImmediate::decode(bytes),
                 },
             }
         }
@@ -445,12 +2064,77 @@ pub fn interpret_bytes(bytes: u32) -> Instruction {
             Instruction::AUIPC {
                 data: U {
                     rd: (bytes >> 7) as u8 & 0b11111,
-                    imm: BigImmediate::from(bytes >> 12),
+                    imm: BigImmediate::decode(bytes),
                 },
             }
         }
-        // unknown instruction so no-op
-        _ => Instruction::nop(),
+        0b0001111 => {
+            // Fences
+            let data = I {
+                rd: (bytes >> 7) as u8 & 0b11111,
+                rs1: (bytes >> 15) as u8 & 0b11111,
+                imm: SmallImmediate::decode(bytes),
+            };
+            match func3 {
+                0b000 => Instruction::FENCE { data },
+                0b001 => Instruction::FENCEI { data },
+                _ => return None,
+            }
+        }
+        0b0101111 => {
+            // RV32A, bits 25 and 26 are rl and aq
+            let data = A {
+                rd: (bytes >> 7) as u8 & 0b11111,
+                rs1: (bytes >> 15) as u8 & 0b11111,
+                rs2: (bytes >> 20) as u8 & 0b11111,
+                aq: bytes & (1 << 26) != 0,
+                rl: bytes & (1 << 25) != 0,
+            };
+            if func3 != 0b010 {
+                return None;
+            }
+            match bytes >> 27 {
+                0b00010 if data.rs2 == 0 => Instruction::LRW { data },
+                0b00011 => Instruction::SCW { data },
+                0b00001 => Instruction::AMOSWAPW { data },
+                0b00000 => Instruction::AMOADDW { data },
+                0b00100 => Instruction::AMOXORW { data },
+                0b01100 => Instruction::AMOANDW { data },
+                0b01000 => Instruction::AMOORW { data },
+                0b10000 => Instruction::AMOMINW { data },
+                0b10100 => Instruction::AMOMAXW { data },
+                0b11000 => Instruction::AMOMINUW { data },
+                0b11100 => Instruction::AMOMAXUW { data },
+                _ => return None,
+            }
+        }
+        0b1110011 => {
+            // System
+            let data = I {
+                rd: (bytes >> 7) as u8 & 0b11111,
+                rs1: (bytes >> 15) as u8 & 0b11111,
+                imm: SmallImmediate::decode(bytes),
+            };
+            match (func3, data.imm.val, data.rd, data.rs1) {
+                (0b000, 0, 0, 0) => Instruction::ECALL { data },
+                (0b000, 1, 0, 0) => Instruction::EBREAK { data },
+                (0b000, 0x302, 0, 0) => Instruction::MRET { data },
+                (0b001, ..) => Instruction::CSRRW { data },
+                (0b010, ..) => Instruction::CSRRS { data },
+                (0b011, ..) => Instruction::CSRRC { data },
+                (0b101, ..) => Instruction::CSRRWI { data },
+                (0b110, ..) => Instruction::CSRRSI { data },
+                (0b111, ..) => Instruction::CSRRCI { data },
+                _ => return None,
+            }
+        }
+        _ => return None,
+    })
+}
+
+impl Default for ArchState {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -460,13 +2144,156 @@ impl ArchState {
     }
 
     pub fn with_mem(cap: usize) -> Self {
-        Self {
+        let mut state = Self {
             regs: [0; 31],
             pc: 0,
-            mem: vec![0; cap],
+            mem: Memory::new(cap),
+            reset_vector: 0,
+            halt_addr: None,
+            exit_status: None,
+            halted: false,
+            interactive: false,
+            paused: false,
+            fault: None,
+            strict_x0: false,
+            last_event: None,
+            last_written: vec![],
+            strict_alignment: false,
+            register_seed: None,
+            stack_reserved: Some(DEFAULT_STACK_RESERVED),
+            symbols: BTreeMap::new(),
+            breakpoints: HashSet::new(),
+            reservation: None,
+            temporary_breakpoint: None,
+            watchpoints: HashSet::new(),
+            watch_events: VecDeque::new(),
+            csrs: HashMap::new(),
+            host_trap_vector: None,
+            retired: 0,
+            cycles: 0,
+            timing: None,
+            stats: InstructionStats::default(),
+            on_retire: None,
+            trace: None,
+            syscall_handler: None,
+            mmio: vec![],
+            history: History::new(DEFAULT_HISTORY_LIMIT),
+            decode_cache: HashMap::new(),
+            decode_cache_hits: 0,
+            idle_limit: None,
+            idle_ticks: 0,
+        };
+        state.init_sp();
+        state
+    }
+
+    /// Points `sp` at the top of the stack, see [`ArchState::stack_reserved`]
+    fn init_sp(&mut self) {
+        if let Some(reserved) = self.stack_reserved {
+            let top = (self.mem.len() as u64).saturating_sub(reserved as u64);
+            self.regs[1] = top.min(u32::MAX as u64) as u32 & !0xf;
+        }
+    }
+
+    /// Starts execution at `pc` now and after every reset or reload, see [`ArchState::reset_vector`]
+    pub fn set_entry(&mut self, pc: u32) {
+        self.reset_vector = pc;
+        self.pc = pc as i64;
+    }
+
+    /// Clears registers and memory and moves pc to the reset vector.
+    /// Keeps the memory size, reset vector, any registered hooks, and the trap vector
+    /// set by the host
+    pub fn reset(&mut self) {
+        self.regs = match self.register_seed {
+            Some(seed) => {
+                let mut state = seed;
+                std::array::from_fn(|_| splitmix64(&mut state) as u32)
+            }
+            None => [0; 31],
+        };
+        self.init_sp();
+        self.pc = self.reset_vector as i64;
+        self.exit_status = None;
+        self.halted = false;
+        self.paused = false;
+        self.fault = None;
+        self.last_event = None;
+        self.last_written.clear();
+        self.watch_events.clear();
+        self.csrs.clear();
+        self.set_trap_vector(self.host_trap_vector);
+        self.retired = 0;
+        self.cycles = 0;
+        self.stats = InstructionStats::default();
+        self.reservation = None;
+        self.temporary_breakpoint = None;
+        self.idle_ticks = 0;
+        self.history.clear();
+        self.decode_cache.clear();
+        self.mem.clear();
+    }
+
+    /// Registers a callback invoked with the post-execution state and the instruction
+    /// after each successful `apply`. Replaces any previously registered callback.
+    pub fn on_retire(&mut self, f: RetireCallback) {
+        self.on_retire = Some(f);
+    }
+
+    /// Pauses `tick` once pc reaches `addr`, before the instruction there runs
+    pub fn add_breakpoint(&mut self, addr: u32) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Returns whether there was a breakpoint at `addr`
+    pub fn remove_breakpoint(&mut self, addr: u32) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> &HashSet<u32> {
+        &self.breakpoints
+    }
+
+    /// Pauses `tick` the next time pc reaches `addr`, then forgets it. Replaces any earlier target
+    pub fn run_to(&mut self, addr: u32) {
+        self.temporary_breakpoint = Some(addr);
+    }
+
+    /// Address a call at pc returns to, the instruction after it. `None` if the instruction
+    /// at pc isn't a call, see [`Instruction::is_call`]
+    pub fn call_return_addr(&self) -> Option<u32> {
+        match self.decode_next() {
+            Ok((inst, len)) if inst.is_call() => Some((self.pc as u32).wrapping_add(len)),
+            _ => None,
         }
     }
 
+    /// Where `run_to` is still waiting to pause
+    pub fn temporary_breakpoint(&self) -> Option<u32> {
+        self.temporary_breakpoint
+    }
+
+    /// Pauses once a store writes the byte at `addr`, queueing a [`WatchEvent`] even if
+    /// the value stays the same
+    pub fn add_watchpoint(&mut self, addr: u32) {
+        self.watchpoints.insert(addr);
+    }
+
+    /// Returns whether there was a watchpoint at `addr`
+    pub fn remove_watchpoint(&mut self, addr: u32) -> bool {
+        self.watchpoints.remove(&addr)
+    }
+
+    pub fn watchpoints(&self) -> &HashSet<u32> {
+        &self.watchpoints
+    }
+
+    /// Registers the handler `ECALL` is routed to, replacing any previous one.
+    /// Without a handler `ECALL` traps, or fails with [`VmError::Ecall`] if there's no trap vector
+    pub fn set_syscall_handler(&mut self, handler: Box<dyn SyscallHandler>) {
+        self.syscall_handler = Some(handler);
+    }
+
     pub fn get_register(&self, reg: usize) -> u32 {
         if reg == 0 {
             return 0;
@@ -474,8 +2301,15 @@ impl ArchState {
         self.regs[reg - 1]
     }
 
-    fn set_register(&mut self, index: usize, val: u32) {
+    /// Writes to x0 are ignored
+    pub fn set_register(&mut self, index: usize, val: u32) {
         if index == 0 {
+            if self.strict_x0 && val != 0 {
+                self.last_event = Some(VmEvent::IgnoredX0Write {
+                    pc: self.pc as u32,
+                    val,
+                });
+            }
             return;
         }
         if let Some(reg) = self.regs.get_mut(index - 1) {
@@ -484,19 +2318,194 @@ impl ArchState {
     }
 
     pub fn load(&mut self, program: Vec<u8>, offset: usize) {
-        (offset..offset + program.len()).for_each(|i| self.mem[i] = program[i - offset]);
+        self.decode_cache.clear();
+        self.mem.write(offset, &program);
+    }
+
+    /// Resets the state and loads every segment from its source. All sources are read and
+    /// checked to fit in memory before anything is reset so a failure leaves the state untouched
+    pub fn load_segments(&mut self, segments: &[Segment]) -> io::Result<()> {
+        let data = segments
+            .iter()
+            .map(|segment| (segment.source)().map(|bytes| (bytes, segment.offset)))
+            .collect::<io::Result<Vec<(Vec<u8>, usize)>>>()?;
+        if let Some((bytes, offset)) = data
+            .iter()
+            .find(|(bytes, offset)| offset + bytes.len() > self.mem.len())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "segment 0x{:x}..0x{:x} doesn't fit in 0x{:x} bytes of memory",
+                    offset,
+                    offset + bytes.len(),
+                    self.mem.len()
+                ),
+            ));
+        }
+        self.reset();
+        for (bytes, offset) in data {
+            self.load(bytes, offset);
+        }
+        Ok(())
+    }
+
+    /// Ticks up to `max` instructions, stopping early on an exit, fault, or breakpoint
+    pub fn run(&mut self, max: u64) -> RunReport {
+        self.paused = false;
+        let mut steps = 0;
+        let stop = loop {
+            if self.halted {
+                break match self.exit_status {
+                    Some(status) => StopReason::Exited(status),
+                    None => StopReason::Halted,
+                };
+            }
+            if steps == max {
+                break StopReason::BudgetExhausted;
+            }
+            if let Err(err) = self.tick() {
+                break StopReason::Fault(err);
+            }
+            steps += 1;
+            if self.paused {
+                break match self.last_event {
+                    Some(VmEvent::IdleLoop { pc }) => StopReason::IdleLoop(pc),
+                    _ => StopReason::Paused,
+                };
+            }
+        };
+        RunReport {
+            steps,
+            pc: self.pc as u32,
+            registers: std::array::from_fn(|i| self.get_register(i)),
+            halted: self.halted,
+            exit_status: self.exit_status,
+            stop,
+        }
+    }
+
+    /// Loads `bytes` at `offset` into a fresh machine, starts there, and runs up to `max` instructions
+    pub fn execute_program(bytes: Vec<u8>, offset: usize, max: u64) -> RunReport {
+        let mut state = Self::new();
+        state.reset_vector = offset as u32;
+        state.reset();
+        state.load(bytes, offset);
+        state.run(max)
+    }
+
+    /// Register, pc, and memory overview for printing after a headless run
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        match (self.halted, self.exit_status) {
+            (_, Some(status)) => {
+                let _ = writeln!(out, "halted with exit status {}", status);
+            }
+            (true, None) => {
+                let _ = writeln!(out, "halted");
+            }
+            _ => {}
+        }
+        let _ = writeln!(out, "pc : 0x{0:0>8X} | {0:0>10}", self.pc as u32);
+        for i in 0..32 {
+            let _ = writeln!(
+                out,
+                "x{: <2}: 0x{1:0>8X} | {1:0>10}",
+                i,
+                self.get_register(i)
+            );
+        }
+        // scanning all of memory is too slow, so only show the rows under pc and sp
+        for (name, addr) in [("pc", self.pc as u32), ("sp", self.get_register(2))] {
+            // offsets from an aligned start, so the top row ends at 0xFFFFFFFF without overflowing
+            let start = addr - addr % 16;
+            let bytes = (0..16)
+                .map(|i| format!("{:02x}", self.mem.get((start + i) as usize).unwrap_or(&0)))
+                .collect::<Vec<String>>();
+            let _ = writeln!(out, "{}: {:08x} {}", name, start, bytes.join(" "));
+        }
+        out
     }
 
-    pub fn apply(&mut self, inst: &Instruction) {
+    /// Addresses where `pattern` starts in memory, lowest first. Matches may overlap
+    pub fn find_bytes(&self, pattern: &[u8]) -> Vec<u32> {
+        self.mem.find(pattern)
+    }
+
+    /// Copies out `len` bytes starting at `start`. Bytes past the end of memory, or past the top
+    /// of the address space, read as zero so the result is always `len` long
+    pub fn dump(&self, start: u32, len: usize) -> Vec<u8> {
+        let start = start as usize;
+        // only the part inside memory is read, the rest is left zeroed
+        let end = start.saturating_add(len).min(self.mem.len()).max(start);
+        let mut bytes = vec![0; len];
+        for (byte, addr) in bytes.iter_mut().zip(start..end) {
+            *byte = self.mem[addr];
+        }
+        bytes
+    }
+
+    /// Page-aligned chunks of memory holding at least one nonzero byte, with their base address
+    pub fn populated_pages(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        self.mem.populated_pages()
+    }
+
+    /// Formats memory as a Verilog `$readmemh` image for an 8 bit wide memory.
+    /// Lines of 16 zero bytes are skipped, with `@` address markers resuming after each gap
+    pub fn export_mem_hex(&self) -> String {
+        let mut out = String::new();
+        // address the next line would be written at without a marker
+        let mut next = None;
+        let lines = self.mem.populated_pages().flat_map(|(base, page)| {
+            page.chunks(16)
+                .enumerate()
+                .map(move |(line, chunk)| (base as usize + line * 16, chunk))
+        });
+        for (addr, chunk) in lines {
+            if chunk.iter().all(|byte| *byte == 0) {
+                continue;
+            }
+            if next != Some(addr) {
+                let _ = writeln!(out, "@{:08x}", addr);
+            }
+            next = Some(addr + 16);
+            let bytes = chunk
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<String>>();
+            let _ = writeln!(out, "{}", bytes.join(" "));
+        }
+        out
+    }
+
+    /// Executes `inst` at the current pc. Memory is little-endian like RISC-V,
+    /// multi-byte loads and stores put the least significant byte at the lowest address
+    /// Leaves the state untouched if the instruction can't be executed, unless a
+    /// [`ArchState::trap_vector`] is set, then the fault jumps there instead
+    pub fn apply(&mut self, inst: &Instruction) -> Result<(), VmError> {
+        self.execute(inst, 4)
+            .or_else(|err| self.trap(err, Some(inst)))
+    }
+
+    /// Runs `inst` as if it were `len` bytes long, which is 2 for expanded compressed instructions
+    fn execute(&mut self, inst: &Instruction, len: u32) -> Result<(), VmError> {
+        // address of the instruction being applied, before any arm moves pc. Arms that read
+        // their own address use this rather than `self.pc`
+        let inst_pc = self.pc;
+        self.last_event = None;
+        self.last_written.clear();
+        let pre_trace = self.trace.is_some().then(|| self.trace_before(inst));
         match inst {
             // Register Arithmetic
             Instruction::ADD { data } => self.set_register(
                 data.rd as usize,
-                self.get_register(data.rs1 as usize) + self.get_register(data.rs2 as usize),
+                self.get_register(data.rs1 as usize)
+                    .wrapping_add(self.get_register(data.rs2 as usize)),
             ),
             Instruction::SUB { data } => self.set_register(
                 data.rd as usize,
-                self.get_register(data.rs1 as usize) - self.get_register(data.rs2 as usize),
+                self.get_register(data.rs1 as usize)
+                    .wrapping_sub(self.get_register(data.rs2 as usize)),
             ),
             Instruction::XOR { data } => self.set_register(
                 data.rd as usize,
@@ -513,25 +2522,28 @@ impl ArchState {
             // Shifts
             Instruction::SLL { data } => self.set_register(
                 data.rd as usize,
-                self.get_register(data.rs1 as usize) << self.get_register(data.rs2 as usize),
+                self.get_register(data.rs1 as usize)
+                    << (self.get_register(data.rs2 as usize) & 0x1f),
             ),
             Instruction::SRL { data } => self.set_register(
                 data.rd as usize,
-                self.get_register(data.rs1 as usize) >> self.get_register(data.rs2 as usize),
+                self.get_register(data.rs1 as usize)
+                    >> (self.get_register(data.rs2 as usize) & 0x1f),
             ),
             Instruction::SRA { data } => self.set_register(
                 data.rd as usize,
                 transmute_to_unsigned(
                     transmute_to_signed(self.get_register(data.rs1 as usize))
-                        >> self.get_register(data.rs2 as usize),
+                        >> (self.get_register(data.rs2 as usize) & 0x1f),
                 ),
             ),
             // Register Comparisons
             Instruction::SLT { data } => self.set_register(
                 data.rd as usize,
-                if transmute_to_signed(self.get_register(data.rs1 as usize))
-                    < transmute_to_signed(self.get_register(data.rs2 as usize))
-                {
+                if less_than_signed(
+                    self.get_register(data.rs1 as usize),
+                    self.get_register(data.rs2 as usize),
+                ) {
                     1
                 } else {
                     0
@@ -545,13 +2557,76 @@ impl ArchState {
                     0
                 },
             ),
+            // Multiply and Divide
+            Instruction::MUL { data } => self.set_register(
+                data.rd as usize,
+                self.get_register(data.rs1 as usize)
+                    .wrapping_mul(self.get_register(data.rs2 as usize)),
+            ),
+            Instruction::MULH { data } => self.set_register(
+                data.rd as usize,
+                ((transmute_to_signed(self.get_register(data.rs1 as usize)) as i64
+                    * transmute_to_signed(self.get_register(data.rs2 as usize)) as i64)
+                    >> 32) as u32,
+            ),
+            Instruction::MULHSU { data } => self.set_register(
+                data.rd as usize,
+                ((transmute_to_signed(self.get_register(data.rs1 as usize)) as i64
+                    * self.get_register(data.rs2 as usize) as i64)
+                    >> 32) as u32,
+            ),
+            Instruction::MULHU { data } => self.set_register(
+                data.rd as usize,
+                ((self.get_register(data.rs1 as usize) as u64
+                    * self.get_register(data.rs2 as usize) as u64)
+                    >> 32) as u32,
+            ),
+            // division never traps, by zero gives all ones and INT_MIN / -1 wraps back to INT_MIN
+            Instruction::DIV { data } => {
+                let dividend = transmute_to_signed(self.get_register(data.rs1 as usize));
+                let divisor = transmute_to_signed(self.get_register(data.rs2 as usize));
+                self.set_register(
+                    data.rd as usize,
+                    if divisor == 0 {
+                        u32::MAX
+                    } else {
+                        transmute_to_unsigned(dividend.wrapping_div(divisor))
+                    },
+                )
+            }
+            Instruction::DIVU { data } => self.set_register(
+                data.rd as usize,
+                self.get_register(data.rs1 as usize)
+                    .checked_div(self.get_register(data.rs2 as usize))
+                    .unwrap_or(u32::MAX),
+            ),
+            // remainder by zero gives back the dividend and INT_MIN % -1 is 0
+            Instruction::REM { data } => {
+                let dividend = transmute_to_signed(self.get_register(data.rs1 as usize));
+                let divisor = transmute_to_signed(self.get_register(data.rs2 as usize));
+                self.set_register(
+                    data.rd as usize,
+                    if divisor == 0 {
+                        transmute_to_unsigned(dividend)
+                    } else {
+                        transmute_to_unsigned(dividend.wrapping_rem(divisor))
+                    },
+                )
+            }
+            Instruction::REMU { data } => {
+                let dividend = self.get_register(data.rs1 as usize);
+                self.set_register(
+                    data.rd as usize,
+                    dividend
+                        .checked_rem(self.get_register(data.rs2 as usize))
+                        .unwrap_or(dividend),
+                )
+            }
             // Immediate Arithmetic
             Instruction::ADDI { data } => self.set_register(
                 data.rd as usize,
-                transmute_to_unsigned(
-                    transmute_to_signed(self.get_register(data.rs1 as usize))
-                        + data.imm.sign_extend(),
-                ),
+                self.get_register(data.rs1 as usize)
+                    .wrapping_add_signed(data.imm.sign_extend()),
             ),
             Instruction::XORI { data } => self.set_register(
                 data.rd as usize,
@@ -577,30 +2652,27 @@ impl ArchState {
             // Immediate Shifts
             Instruction::SLLI { data } => self.set_register(
                 data.rd as usize,
-                self.get_register(data.rs1 as usize) << data.imm.val,
+                self.get_register(data.rs1 as usize) << (data.imm.val & 0b11111),
             ),
             Instruction::SRLI { data } => self.set_register(
                 data.rd as usize,
-                self.get_register(data.rs1 as usize)
-                // Skip first few bits because arithmetic vs logical shift is encoded in them
-                    >> data.imm.val
-                    & 0b11111,
+                // decode already picked logical vs arithmetic, only shamt = imm[4:0] is left
+                self.get_register(data.rs1 as usize) >> (data.imm.val & 0b11111),
             ),
             Instruction::SRAI { data } => self.set_register(
                 data.rd as usize,
                 transmute_to_unsigned(
                     transmute_to_signed(self.get_register(data.rs1 as usize))
-                    // Skip first few bits because arithmetic vs logical shift is encoded in them
-                        >> data.imm.val
-                        & 0b11111,
+                        >> (data.imm.val & 0b11111),
                 ),
             ),
             // Immediate Comparisons
             Instruction::SLTI { data } => self.set_register(
                 data.rd as usize,
-                if transmute_to_signed(self.get_register(data.rs1 as usize))
-                    < data.imm.sign_extend()
-                {
+                if less_than_signed(
+                    self.get_register(data.rs1 as usize),
+                    transmute_to_unsigned(data.imm.sign_extend()),
+                ) {
                     1
                 } else {
                     0
@@ -617,110 +2689,50 @@ impl ArchState {
                 },
             ),
             // Loads
-            Instruction::LBU { data } => self.set_register(
-                data.rd as usize,
-                *self
-                    .mem
-                    .get(
-                        (self.get_register(data.rs1 as usize) as usize)
-                            .wrapping_add_signed(data.imm.sign_extend() as isize),
-                    )
-                    .unwrap() as u32,
-            ),
+            Instruction::LBU { data } => {
+                let val = self.load_mem(self.load_addr(data), 1)?;
+                self.set_register(data.rd as usize, val)
+            }
             Instruction::LHU { data } => {
-                let index = (self.get_register(data.rs1 as usize) as usize)
-                    .wrapping_add_signed(data.imm.sign_extend() as isize);
-                self.set_register(
-                    data.rd as usize,
-                    (0..2)
-                        .map(|offset| {
-                            (*self.mem.get(index + offset).unwrap() as u32) << 8 * (1 - offset)
-                        })
-                        .sum::<u32>(),
-                )
+                let val = self.load_mem(self.load_addr(data), 2)?;
+                self.set_register(data.rd as usize, val)
             }
             Instruction::LB { data } => {
-                let val = *self
-                    .mem
-                    .get(
-                        (self.get_register(data.rs1 as usize) as usize)
-                            .wrapping_add_signed(data.imm.sign_extend() as isize),
-                    )
-                    .unwrap() as u32;
-                self.set_register(
-                    data.rd as usize,
-                    // sign extension magic
-                    // check if most significant defined bit is 1
-                    // if so, set remaining significant bits to 1 with magic number
-                    val + if val & 0b10000000 == 128 {
-                        0xFFFFFF00
-                    } else {
-                        0
-                    },
-                );
+                let val = self.load_mem(self.load_addr(data), 1)?;
+                self.set_register(data.rd as usize, val as i8 as i32 as u32)
             }
             Instruction::LH { data } => {
-                let index = (self.get_register(data.rs1 as usize) as usize)
-                    .wrapping_add_signed(data.imm.sign_extend() as isize);
-                let val = (0..2)
-                    .map(|offset| {
-                        (*self.mem.get(index + offset).unwrap() as u32) << 8 * (1 - offset)
-                    })
-                    .sum::<u32>();
-                self.set_register(
-                    data.rd as usize,
-                    // sign extension magic
-                    // check if most significant defined bit is 1
-                    // if so, set remaining significant bits to 1 with magic number
-                    val + if val & (1 << 15) == 1 << 15 {
-                        0xFFFF0000
-                    } else {
-                        0
-                    },
-                )
+                let val = self.load_mem(self.load_addr(data), 2)?;
+                self.set_register(data.rd as usize, val as i16 as i32 as u32)
             }
             Instruction::LW { data } => {
-                let index = (self.get_register(data.rs1 as usize) as usize)
-                    .wrapping_add_signed(data.imm.sign_extend() as isize);
-                self.set_register(
-                    data.rd as usize,
-                    (0..4)
-                        .map(|offset| {
-                            (*self.mem.get(index + offset).unwrap() as u32) << 8 * (3 - offset)
-                        })
-                        .sum::<u32>(),
-                )
+                let val = self.load_mem(self.load_addr(data), 4)?;
+                self.set_register(data.rd as usize, val)
             }
             Instruction::SB { data } => {
                 let index = self
                     .get_register(data.rs1 as usize)
-                    .wrapping_add_signed(data.imm.sign_extend() as i32);
-                self.mem[index as usize] = self.get_register(data.rs2 as usize) as u8;
+                    .wrapping_add_signed(data.imm.sign_extend());
+                self.store_mem(index, 1, self.get_register(data.rs2 as usize))?;
             }
             Instruction::SH { data } => {
                 let index = self
                     .get_register(data.rs1 as usize)
-                    .wrapping_add_signed(data.imm.sign_extend() as i32);
-                (0..2).for_each(|offset| {
-                    self.mem[index as usize + offset] =
-                        (self.get_register(data.rs2 as usize) >> 8 * (1 - offset)) as u8
-                });
+                    .wrapping_add_signed(data.imm.sign_extend());
+                self.store_mem(index, 2, self.get_register(data.rs2 as usize))?;
             }
             Instruction::SW { data } => {
                 let index = self
                     .get_register(data.rs1 as usize)
-                    .wrapping_add_signed(data.imm.sign_extend() as i32);
-                (0..4).for_each(|offset| {
-                    self.mem[index as usize + offset] =
-                        (self.get_register(data.rs2 as usize) >> 8 * (3 - offset)) as u8
-                });
+                    .wrapping_add_signed(data.imm.sign_extend());
+                self.store_mem(index, 4, self.get_register(data.rs2 as usize))?;
             }
             Instruction::BEQ { data } => {
                 self.pc += if self.get_register(data.rs1 as usize)
                     == self.get_register(data.rs2 as usize)
                 {
                     // decrement because we will increment later
-                    data.imm.sign_extend() * 2 - 4
+                    data.imm.sign_extend() - len as i32
                 } else {
                     0
                 } as i64
@@ -730,17 +2742,18 @@ impl ArchState {
                     != self.get_register(data.rs2 as usize)
                 {
                     // decrement because we will increment later
-                    data.imm.sign_extend() * 2 - 4
+                    data.imm.sign_extend() - len as i32
                 } else {
                     0
                 } as i64
             }
             Instruction::BLT { data } => {
-                self.pc += if transmute_to_signed(self.get_register(data.rs1 as usize))
-                    < transmute_to_signed(self.get_register(data.rs2 as usize))
-                {
+                self.pc += if less_than_signed(
+                    self.get_register(data.rs1 as usize),
+                    self.get_register(data.rs2 as usize),
+                ) {
                     // decrement because we will increment later
-                    data.imm.sign_extend() * 2 - 4
+                    data.imm.sign_extend() - len as i32
                 } else {
                     0
                 } as i64
@@ -749,17 +2762,18 @@ impl ArchState {
                 self.pc +=
                     if self.get_register(data.rs1 as usize) < self.get_register(data.rs2 as usize) {
                         // decrement because we will increment later
-                        data.imm.sign_extend() * 2 - 4
+                        data.imm.sign_extend() - len as i32
                     } else {
                         0
                     } as i64
             }
             Instruction::BGE { data } => {
-                self.pc += if transmute_to_signed(self.get_register(data.rs1 as usize))
-                    >= transmute_to_signed(self.get_register(data.rs2 as usize))
-                {
+                self.pc += if !less_than_signed(
+                    self.get_register(data.rs1 as usize),
+                    self.get_register(data.rs2 as usize),
+                ) {
                     // decrement because we will increment later
-                    data.imm.sign_extend() * 2 - 4
+                    data.imm.sign_extend() - len as i32
                 } else {
                     0
                 } as i64
@@ -769,55 +2783,392 @@ impl ArchState {
                     >= self.get_register(data.rs2 as usize)
                 {
                     // decrement because we will increment later
-                    data.imm.sign_extend() * 2 - 4
+                    data.imm.sign_extend() - len as i32
                 } else {
                     0
                 } as i64
             }
             Instruction::JAL { data } => {
-                self.set_register(data.rd as usize, self.pc as u32 + 4);
-                self.pc += data.imm.sign_extend() as i64 * 2 - 4;
+                self.pc += data.imm.sign_extend() as i64 - len as i64;
+                // link to the instruction after the jump, not wherever pc ended up
+                if data.rd != 0 {
+                    self.set_register(data.rd as usize, (inst_pc as u32).wrapping_add(len));
+                }
             }
             Instruction::JALR { data } => {
-                self.set_register(data.rd as usize, self.pc as u32 + 4);
-                self.pc = (self
+                // read the base before linking in case rd and rs1 are the same register
+                let target = self
                     .get_register(data.rs1 as usize)
-                    .saturating_add_signed(data.imm.sign_extend())
-                    as i64
-                    & 0xFFFE)
-                    - 4;
+                    .wrapping_add_signed(data.imm.sign_extend())
+                    & !1;
+                self.pc = target as i64 - len as i64;
+                if data.rd != 0 {
+                    self.set_register(data.rd as usize, (inst_pc as u32).wrapping_add(len));
+                }
             }
             Instruction::LUI { data } => {
                 self.set_register(data.rd as usize, data.imm.val << 12);
             }
             Instruction::AUIPC { data } => {
-                self.set_register(data.rd as usize, self.pc as u32 + (data.imm.val << 12));
+                self.set_register(
+                    data.rd as usize,
+                    (inst_pc as u32).wrapping_add(data.imm.val << 12),
+                );
             }
-            _ => {
-                panic!("Instruction Not Implemented!!")
+            // there is one hart and no caches, so memory is always ordered
+            Instruction::FENCE { data: _ } => {}
+            Instruction::FENCEI { data: _ } => self.decode_cache.clear(),
+            Instruction::CSRRW { data } => self.apply_csr(
+                CsrOp::Write,
+                data.imm.val as u16,
+                data.rd as usize,
+                self.get_register(data.rs1 as usize),
+                true,
+            )?,
+            Instruction::CSRRS { data } => self.apply_csr(
+                CsrOp::Set,
+                data.imm.val as u16,
+                data.rd as usize,
+                self.get_register(data.rs1 as usize),
+                data.rs1 != 0,
+            )?,
+            Instruction::CSRRC { data } => self.apply_csr(
+                CsrOp::Clear,
+                data.imm.val as u16,
+                data.rd as usize,
+                self.get_register(data.rs1 as usize),
+                data.rs1 != 0,
+            )?,
+            Instruction::CSRRWI { data } => self.apply_csr(
+                CsrOp::Write,
+                data.imm.val as u16,
+                data.rd as usize,
+                data.rs1 as u32,
+                true,
+            )?,
+            Instruction::CSRRSI { data } => self.apply_csr(
+                CsrOp::Set,
+                data.imm.val as u16,
+                data.rd as usize,
+                data.rs1 as u32,
+                data.rs1 != 0,
+            )?,
+            Instruction::CSRRCI { data } => self.apply_csr(
+                CsrOp::Clear,
+                data.imm.val as u16,
+                data.rd as usize,
+                data.rs1 as u32,
+                data.rs1 != 0,
+            )?,
+            Instruction::EBREAK { data: _ } => {
+                // a debugger resumes at the next instruction, batch runs just stop
+                if self.interactive {
+                    self.paused = true;
+                } else {
+                    self.halted = true;
+                }
             }
+            Instruction::ILLEGAL { raw } => return Err(VmError::IllegalInstruction(*raw)),
+            Instruction::MRET { data: _ } => {
+                self.pc = self.read_csr(CSR_MEPC) as i64 - len as i64;
+            }
+            Instruction::LRW { data } => self.apply_lr(data)?,
+            Instruction::SCW { data } => self.apply_sc(data)?,
+            Instruction::AMOSWAPW { data } => self.apply_amo(AmoOp::Swap, data)?,
+            Instruction::AMOADDW { data } => self.apply_amo(AmoOp::Add, data)?,
+            Instruction::AMOXORW { data } => self.apply_amo(AmoOp::Xor, data)?,
+            Instruction::AMOANDW { data } => self.apply_amo(AmoOp::And, data)?,
+            Instruction::AMOORW { data } => self.apply_amo(AmoOp::Or, data)?,
+            Instruction::AMOMINW { data } => self.apply_amo(AmoOp::Min, data)?,
+            Instruction::AMOMAXW { data } => self.apply_amo(AmoOp::Max, data)?,
+            Instruction::AMOMINUW { data } => self.apply_amo(AmoOp::MinUnsigned, data)?,
+            Instruction::AMOMAXUW { data } => self.apply_amo(AmoOp::MaxUnsigned, data)?,
+            Instruction::ECALL { data: _ } => {
+                // take the handler out so it can borrow the rest of the state
+                let Some(mut handler) = self.syscall_handler.take() else {
+                    return Err(VmError::Ecall);
+                };
+                let outcome = handler.handle(self);
+                self.syscall_handler = Some(handler);
+                // the handler may have written anywhere, e.g. a read into the program
+                self.decode_cache.clear();
+                if let SyscallOutcome::Exit(code) = outcome {
+                    self.exit_status = Some(code);
+                    self.halted = true;
+                }
+            }
+        }
+        self.pc += len as i64;
+        self.stats.count(inst, self.pc != inst_pc + len as i64);
+
+        // take the callback out so it can borrow the rest of the state
+        if let Some(mut callback) = self.on_retire.take() {
+            callback(self, inst);
+            self.on_retire = Some(callback);
+        }
+        if let Some(pre_trace) = pre_trace {
+            self.trace_after(pre_trace, inst);
+        }
+        Ok(())
+    }
+
+    /// Decodes the instruction at pc without running it, unknown words show as [`Instruction::ILLEGAL`]
+    pub fn get_instruction(&self) -> Result<Instruction, VmError> {
+        match self.decode_next() {
+            Ok((inst, _)) => Ok(inst),
+            Err(VmError::IllegalInstruction(raw)) => Ok(Instruction::ILLEGAL { raw }),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Decodes the instruction at pc along with its length in bytes, 2 if it is compressed
+    fn decode_next(&self) -> Result<(Instruction, u32), VmError> {
+        let pc = self.pc as usize;
+        let low = self
+            .mem
+            .read(pc, 2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .ok_or(VmError::FetchOutOfBounds(pc as u32))?;
+        if is_compressed(low) {
+            let inst = decode_compressed(low).ok_or(VmError::IllegalInstruction(low as u32))?;
+            return Ok((inst, 2));
         }
-        self.pc += 4;
+        // the low half was there, so the missing upper half is what faults
+        let bytes = self
+            .fetch(pc)
+            .ok_or(VmError::FetchOutOfBounds(pc as u32 + 2))?;
+        let inst = try_interpret_bytes(bytes).ok_or(VmError::IllegalInstruction(bytes))?;
+        Ok((inst, 4))
     }
 
-    pub fn get_instruction(&self) -> Option<Instruction> {
-        if self.pc as usize + 4 >= self.mem.len() {
+    /// Reads the little-endian instruction word at `addr`, `None` if it runs past the end of memory
+    pub fn fetch(&self, addr: usize) -> Option<u32> {
+        if addr + 4 > self.mem.len() {
             return None;
         }
-        Some(interpret_bytes(u32::from_be_bytes([
-            self.mem[self.pc as usize],
-            self.mem[self.pc as usize + 1],
-            self.mem[self.pc as usize + 2],
-            self.mem[self.pc as usize + 3],
-        ])))
+        Some(u32::from_le_bytes([
+            self.mem[addr],
+            self.mem[addr + 1],
+            self.mem[addr + 2],
+            self.mem[addr + 3],
+        ]))
+    }
+
+    /// Decodes a raw word and applies it at the current pc, returning what it decoded to
+    pub fn apply_raw(&mut self, bytes: u32) -> Result<Instruction, VmError> {
+        let inst = try_interpret_bytes(bytes).ok_or(VmError::IllegalInstruction(bytes))?;
+        self.apply(&inst)?;
+        Ok(inst)
+    }
+
+    /// Forgets every decoded instruction. Stores and loads keep the cache up to date,
+    /// but anything writing `mem` directly has to call this before the next `tick`
+    pub fn clear_decode_cache(&mut self) {
+        self.decode_cache.clear();
+    }
+
+    /// Instructions `tick` has retired since the last reset
+    pub fn instret(&self) -> u64 {
+        self.retired
+    }
+
+    /// Cycles `tick` has counted since the last reset, the same as `instret` without a timing model
+    pub fn cycle_count(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Charges each instruction `tick` retires by its class, `None` goes back to one cycle each.
+    /// Cycles already counted are kept
+    pub fn set_timing(&mut self, timing: Option<TimingModel>) {
+        self.timing = timing;
+    }
+
+    /// Applied instructions by kind since the last reset
+    pub fn stats(&self) -> &InstructionStats {
+        &self.stats
+    }
+
+    /// How many ticks reused an already decoded instruction
+    pub fn decode_cache_hits(&self) -> u64 {
+        self.decode_cache_hits
+    }
+
+    /// Drops cached decodes of every instruction overlapping the `len` bytes at `addr`.
+    /// Instructions start on any halfword, so one starting a halfword early may overlap too
+    fn invalidate_decoded(&mut self, addr: u32, len: u32) {
+        if self.decode_cache.is_empty() {
+            return;
+        }
+        let last = addr.wrapping_add(len - 1) & !1;
+        let mut half = (addr & !1).wrapping_sub(2);
+        loop {
+            self.decode_cache.remove(&half);
+            if half == last {
+                break;
+            }
+            half = half.wrapping_add(2);
+        }
+    }
+
+    fn load_addr(&self, data: &I) -> u32 {
+        self.get_register(data.rs1 as usize)
+            .wrapping_add_signed(data.imm.sign_extend())
+    }
+
+    /// Fails unless all `width` bytes at `addr` are inside memory
+    fn check_bounds(&self, addr: u32, width: u32) -> Result<(), VmError> {
+        if addr as usize + width as usize > self.mem.len() {
+            return Err(VmError::OutOfBounds { addr });
+        }
+        Ok(())
+    }
+
+    /// Fails in strict alignment mode unless `addr` is a multiple of `width`
+    fn check_alignment(&self, addr: u32, width: u32) -> Result<(), VmError> {
+        if self.strict_alignment && !addr.is_multiple_of(width) {
+            return Err(VmError::Misaligned { addr, width });
+        }
+        Ok(())
     }
 
-    pub fn tick(&mut self) -> Result<(), ()> {
-        let inst = match self.get_instruction() {
-            Some(inst) => inst,
-            None => return Err(()),
+    /// Reads `width` little-endian bytes, from a mapped device if one covers `addr`
+    fn load_mem(&mut self, addr: u32, width: u32) -> Result<u32, VmError> {
+        self.check_alignment(addr, width)?;
+        if let Some(val) = self.mmio_read(addr, width) {
+            return Ok(val);
+        }
+        self.check_bounds(addr, width)?;
+        Ok((0..width)
+            .map(|offset| (self.mem[addr as usize + offset as usize] as u32) << (8 * offset))
+            .sum())
+    }
+
+    /// Writes the low `width` bytes of `val` little-endian, to a mapped device if one covers `addr`.
+    /// A store to the halt address halts even if there is no memory behind it
+    fn store_mem(&mut self, addr: u32, width: u32, val: u32) -> Result<(), VmError> {
+        self.check_alignment(addr, width)?;
+        let halting = self.check_halt(addr, val & (u32::MAX >> (32 - 8 * width)));
+        if self.mmio_write(addr, width, val) {
+            return Ok(());
+        }
+        if halting && self.check_bounds(addr, width).is_err() {
+            return Ok(());
+        }
+        self.check_bounds(addr, width)?;
+        for offset in 0..width {
+            let byte_addr = addr + offset;
+            let new = (val >> (8 * offset)) as u8;
+            let old = std::mem::replace(&mut self.mem[byte_addr as usize], new);
+            self.last_written.push(byte_addr);
+            if self.watchpoints.contains(&byte_addr) {
+                self.watch_events.push_back(WatchEvent {
+                    pc: self.pc as u32,
+                    addr: byte_addr,
+                    old,
+                    new,
+                });
+                self.paused = true;
+            }
+        }
+        self.invalidate_decoded(addr, width);
+        Ok(())
+    }
+
+    /// Address and width `inst` stores to with the current registers, `None` if it isn't a store
+    fn store_target(&self, inst: &Instruction) -> Option<(u32, u32)> {
+        let (data, width) = match inst {
+            Instruction::SB { data } => (data, 1),
+            Instruction::SH { data } => (data, 2),
+            Instruction::SW { data } => (data, 4),
+            // atomics take their address from rs1 alone
+            Instruction::SCW { data }
+            | Instruction::AMOSWAPW { data }
+            | Instruction::AMOADDW { data }
+            | Instruction::AMOXORW { data }
+            | Instruction::AMOANDW { data }
+            | Instruction::AMOORW { data }
+            | Instruction::AMOMINW { data }
+            | Instruction::AMOMAXW { data }
+            | Instruction::AMOMINUW { data }
+            | Instruction::AMOMAXUW { data } => {
+                return Some((self.get_register(data.rs1 as usize), 4));
+            }
+            _ => return None,
         };
-        self.apply(&inst);
+        let addr = self
+            .get_register(data.rs1 as usize)
+            .wrapping_add_signed(data.imm.sign_extend());
+        Some((addr, width))
+    }
+
+    /// Halts if a store of `val` to `addr` hit the configured halt address, returning whether it did
+    fn check_halt(&mut self, addr: u32, val: u32) -> bool {
+        if self.halt_addr != Some(addr) {
+            return false;
+        }
+        self.exit_status = Some(val);
+        self.halted = true;
+        true
+    }
+
+    /// Fetches, decodes, and applies the instruction at pc, recording it for `step_back`.
+    /// Faults jump to the [`ArchState::trap_vector`] if one is set, and stepping back undoes the trap
+    pub fn tick(&mut self) -> Result<(), VmError> {
+        if self.halted {
+            return Err(VmError::Halted);
+        }
+        let pc = self.pc as u32;
+        // compressed instructions only need halfword alignment
+        if !pc.is_multiple_of(2) {
+            let snapshot = self.bare_snapshot();
+            return self.trap_and_record(VmError::MisalignedPc(pc), None, snapshot);
+        }
+        let (inst, len) = match self.decode_cache.get(&pc) {
+            Some(decoded) => {
+                self.decode_cache_hits += 1;
+                *decoded
+            }
+            None => match self.decode_next() {
+                Ok(decoded) => {
+                    self.decode_cache.insert(pc, decoded);
+                    decoded
+                }
+                Err(err) => {
+                    let snapshot = self.bare_snapshot();
+                    return self.trap_and_record(err, None, snapshot);
+                }
+            },
+        };
+        let snapshot = self.snapshot(&inst);
+        let regs = self.idle_limit.map(|_| self.regs);
+        if let Err(err) = self.execute(&inst, len) {
+            return self.trap_and_record(err, Some(&inst), snapshot);
+        }
+        self.retired += 1;
+        self.cycles += match &self.timing {
+            Some(timing) => timing.cost(&inst, self.pc != pc as i64 + len as i64),
+            None => 1,
+        };
+        self.record(snapshot);
+        if let (Some(limit), Some(regs)) = (self.idle_limit, regs) {
+            if self.pc == pc as i64 && self.regs == regs && self.last_written.is_empty() {
+                self.idle_ticks += 1;
+            } else {
+                self.idle_ticks = 0;
+            }
+            if self.idle_ticks >= limit {
+                self.idle_ticks = 0;
+                self.last_event = Some(VmEvent::IdleLoop { pc });
+                self.paused = true;
+            }
+        }
+        if self.breakpoints.contains(&(self.pc as u32)) {
+            self.paused = true;
+        }
+        if self.temporary_breakpoint == Some(self.pc as u32) {
+            self.temporary_breakpoint = None;
+            self.paused = true;
+        }
         Ok(())
     }
 }
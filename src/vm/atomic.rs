@@ -0,0 +1,78 @@
+use super::{A, ArchState, VmError};
+
+#[cfg(test)]
+mod atomic_tests;
+
+/// How an AMO combines the word in memory with rs2
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum AmoOp {
+    Swap,
+    Add,
+    Xor,
+    And,
+    Or,
+    Min,
+    Max,
+    MinUnsigned,
+    MaxUnsigned,
+}
+
+impl AmoOp {
+    fn combine(self, old: u32, operand: u32) -> u32 {
+        match self {
+            AmoOp::Swap => operand,
+            AmoOp::Add => old.wrapping_add(operand),
+            AmoOp::Xor => old ^ operand,
+            AmoOp::And => old & operand,
+            AmoOp::Or => old | operand,
+            AmoOp::Min => (old as i32).min(operand as i32) as u32,
+            AmoOp::Max => (old as i32).max(operand as i32) as u32,
+            AmoOp::MinUnsigned => old.min(operand),
+            AmoOp::MaxUnsigned => old.max(operand),
+        }
+    }
+}
+
+impl ArchState {
+    /// The word an atomic accesses, which has to be aligned even without `strict_alignment`
+    fn atomic_addr(&self, data: &A) -> Result<u32, VmError> {
+        let addr = self.get_register(data.rs1 as usize);
+        if !addr.is_multiple_of(4) {
+            return Err(VmError::Misaligned { addr, width: 4 });
+        }
+        Ok(addr)
+    }
+
+    /// `lr.w`, loads the word at rs1 and reserves it for the next `sc.w`
+    pub(super) fn apply_lr(&mut self, data: &A) -> Result<(), VmError> {
+        let addr = self.atomic_addr(data)?;
+        let val = self.load_mem(addr, 4)?;
+        self.set_register(data.rd as usize, val);
+        self.reservation = Some(addr);
+        Ok(())
+    }
+
+    /// `sc.w`, stores rs2 only if rs1 is still reserved and writes 0 to rd on success, 1 on failure.
+    /// The reservation is used up either way
+    pub(super) fn apply_sc(&mut self, data: &A) -> Result<(), VmError> {
+        let addr = self.atomic_addr(data)?;
+        let reserved = self.reservation.take() == Some(addr);
+        if reserved {
+            let val = self.get_register(data.rs2 as usize);
+            self.store_mem(addr, 4, val)?;
+        }
+        self.set_register(data.rd as usize, !reserved as u32);
+        Ok(())
+    }
+
+    /// Loads the word at rs1 into rd and stores it back combined with rs2
+    pub(super) fn apply_amo(&mut self, op: AmoOp, data: &A) -> Result<(), VmError> {
+        let addr = self.atomic_addr(data)?;
+        let old = self.load_mem(addr, 4)?;
+        // rs2 is read before rd is written in case they are the same register
+        let new = op.combine(old, self.get_register(data.rs2 as usize));
+        self.store_mem(addr, 4, new)?;
+        self.set_register(data.rd as usize, old);
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+use crate::{
+    asm::assemble,
+    vm::{ArchState, VmError, assembly, try_interpret_bytes},
+};
+
+fn run(source: &str, steps: u64) -> ArchState {
+    let mut state = ArchState::with_mem(2_usize.pow(12));
+    state.load(assemble(source).unwrap(), 0);
+    state.set_register(5, 0x800);
+    assert_eq!(state.run(steps).steps, steps);
+    state
+}
+
+#[test]
+fn test_amo() {
+    let state = run(
+        "li x6, 40
+        sw x6, 0(x5)
+        li x7, 2
+        amoadd.w x8, x7, (x5)
+        li x7, -1
+        amomaxu.w x9, x7, (x5)
+        amomin.w x10, x0, (x5)
+        amoswap.w x7, x7, (x5)",
+        8,
+    );
+    assert_eq!(state.get_register(8), 40);
+    assert_eq!(state.get_register(9), 42);
+    assert_eq!(state.get_register(10), u32::MAX);
+    // rd and rs2 can be the same register, the old value still lands in rd
+    assert_eq!(state.get_register(7), u32::MAX);
+    assert_eq!(state.fetch(0x800), Some(u32::MAX));
+}
+
+#[test]
+fn test_lr_sc() {
+    let state = run(
+        "li x6, 7
+        lr.w x7, (x5)
+        sc.w x8, x6, (x5)
+        sc.w x9, x6, (x5)",
+        4,
+    );
+    assert_eq!(state.get_register(7), 0);
+    // the first sc.w uses up the reservation, the second fails
+    assert_eq!(state.get_register(8), 0);
+    assert_eq!(state.get_register(9), 1);
+    assert_eq!(state.fetch(0x800), Some(7));
+
+    // a reservation on another word doesn't count
+    let mut state = run("lr.w x7, (x5)\nsc.w x8, x6, (x6)", 1);
+    state.set_register(6, 0x804);
+    state.tick().unwrap();
+    assert_eq!(state.get_register(8), 1);
+    assert_eq!(state.fetch(0x804), Some(0));
+}
+
+#[test]
+fn test_atomic_alignment_and_text() {
+    let mut state = ArchState::with_mem(2_usize.pow(12));
+    state.load(assemble("amoor.w x1, x2, (x3)").unwrap(), 0);
+    state.set_register(3, 0x802);
+    assert_eq!(
+        state.tick(),
+        Err(VmError::Misaligned {
+            addr: 0x802,
+            width: 4
+        })
+    );
+
+    for text in [
+        "lr.w x1, (x2)",
+        "sc.w x1, x3, (x2)",
+        "amomaxu.w x4, x5, (x6)",
+    ] {
+        let word = u32::from_le_bytes(assemble(text).unwrap().try_into().unwrap());
+        assert_eq!(assembly(&try_interpret_bytes(word).unwrap(), 0), text);
+    }
+    assert!(assemble("lr.w x1, 4(x2)").is_err());
+}
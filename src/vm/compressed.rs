@@ -0,0 +1,77 @@
+use super::{
+    ImmediateLayout, Instruction, JumpImmediate, SmallImmediate, StoreImmediate,
+    try_interpret_bytes,
+};
+
+#[cfg(test)]
+mod compressed_tests;
+
+/// Whether the parcel starting with `low_half` is a 16 bit instruction,
+/// 32 bit instructions always have `11` in their two lowest bits
+pub fn is_compressed(low_half: u16) -> bool {
+    low_half & 0b11 != 0b11
+}
+
+/// Decodes a 16 bit RV32C instruction into the 32 bit instruction it stands for
+pub fn decode_compressed(half: u16) -> Option<Instruction> {
+    expand_compressed(half).and_then(try_interpret_bytes)
+}
+
+/// Rewrites a 16 bit RV32C instruction as its 32 bit equivalent.
+///
+/// Supports C.ADDI (and C.NOP), C.LI, C.LW, C.SW, C.J, C.JR, and C.MV,
+/// anything else including the all zero halfword comes back `None`
+pub fn expand_compressed(half: u16) -> Option<u32> {
+    let half = half as u32;
+    let bits = |hi: u32, lo: u32| (half >> lo) & ((1 << (hi - lo + 1)) - 1);
+    let funct3 = bits(15, 13);
+    // the 3 bit register fields name x8..x15
+    let rd_prime = bits(4, 2) + 8;
+    let rs1_prime = bits(9, 7) + 8;
+    let rd = bits(11, 7);
+    let rs2 = bits(6, 2);
+    // sign extended imm[5|4:0] shared by C.ADDI and C.LI
+    let imm6 = (((bits(12, 12) << 5) | bits(6, 2)) as i32) << 26 >> 26;
+    // zero extended uimm[5:3|2|6] shared by C.LW and C.SW, always a multiple of 4
+    let word_offset = (bits(12, 10) << 3) | (bits(6, 6) << 2) | (bits(5, 5) << 6);
+
+    Some(match (bits(1, 0), funct3) {
+        (0b00, 0b010) => encode_i(word_offset, rs1_prime, 0b010, rd_prime, 0b0000011),
+        (0b00, 0b110) => {
+            StoreImmediate::from(word_offset).encode()
+                | (rd_prime << 20)
+                | (rs1_prime << 15)
+                | (0b010 << 12)
+                | 0b0100011
+        }
+        (0b01, 0b000) => encode_i(imm6 as u32, rd, 0b000, rd, 0b0010011),
+        (0b01, 0b010) if rd != 0 => encode_i(imm6 as u32, 0, 0b000, rd, 0b0010011),
+        (0b01, 0b101) => {
+            // offset[11|4|9:8|10|6|7|3:1|5] is in bits 12:2
+            let offset = (bits(12, 12) << 11)
+                | (bits(11, 11) << 4)
+                | (bits(10, 9) << 8)
+                | (bits(8, 8) << 10)
+                | (bits(7, 7) << 6)
+                | (bits(6, 6) << 7)
+                | (bits(5, 3) << 1)
+                | (bits(2, 2) << 5);
+            let offset = ((offset as i32) << 20 >> 20) as u32;
+            JumpImmediate::from(offset).encode() | 0b1101111
+        }
+        (0b10, 0b100) if bits(12, 12) == 0 && rd != 0 => {
+            if rs2 == 0 {
+                // C.JR
+                encode_i(0, rd, 0b000, 0, 0b1100111)
+            } else {
+                // C.MV is add rd, x0, rs2
+                (rs2 << 20) | (rd << 7) | 0b0110011
+            }
+        }
+        _ => return None,
+    })
+}
+
+fn encode_i(imm: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    SmallImmediate::from(imm).encode() | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
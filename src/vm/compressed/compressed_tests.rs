@@ -0,0 +1,55 @@
+use crate::vm::{ArchState, try_interpret_bytes};
+
+use super::{decode_compressed, expand_compressed, is_compressed};
+
+#[test]
+fn test_expand_compressed() {
+    // each compressed encoding next to the 32 bit instruction it stands for
+    let pairs = [
+        (0x0505_u16, 0x00150513_u32), // c.addi a0, 1 / addi a0, a0, 1
+        (0x1141, 0xff010113),         // c.addi sp, -16 / addi sp, sp, -16
+        (0x0001, 0x00000013),         // c.nop / addi x0, x0, 0
+        (0x557d, 0xfff00513),         // c.li a0, -1 / addi a0, x0, -1
+        (0x4188, 0x0005a503),         // c.lw a0, 0(a1) / lw a0, 0(a1)
+        (0x5e7c, 0x07c62783),         // c.lw a5, 124(a2) / lw a5, 124(a2)
+        (0xc1c8, 0x00a5a223),         // c.sw a0, 4(a1) / sw a0, 4(a1)
+        (0xbff5, 0xffdff06f),         // c.j -4 / jal x0, -4
+        (0xaffd, 0x7fe0006f),         // c.j 2046 / jal x0, 2046
+        (0xb001, 0x801ff06f),         // c.j -2048 / jal x0, -2048
+        (0x8082, 0x00008067),         // c.jr ra / jalr x0, 0(ra)
+        (0x852e, 0x00b00533),         // c.mv a0, a1 / add a0, x0, a1
+    ];
+    for (half, word) in pairs {
+        assert!(is_compressed(half));
+        assert_eq!(expand_compressed(half), Some(word), "0x{:04x}", half);
+        assert_eq!(
+            format!("{:?}", decode_compressed(half)),
+            format!("{:?}", try_interpret_bytes(word))
+        );
+    }
+    // the low half of addi a0, a0, 1
+    assert!(!is_compressed(0x0513));
+    assert_eq!(expand_compressed(0), None);
+    // c.li x0, 1 is a hint this decoder doesn't take
+    assert_eq!(expand_compressed(0x4005), None);
+}
+
+#[test]
+fn test_tick_compressed() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let mut program = vec![];
+    program.extend(0x4515_u16.to_le_bytes()); // c.li a0, 5
+    program.extend(0x00150593_u32.to_le_bytes()); // addi a1, a0, 1
+    program.extend(0x862e_u16.to_le_bytes()); // c.mv a2, a1
+    program.extend(0xbfe5_u16.to_le_bytes()); // c.j -8
+    state.load(program, 0);
+
+    state.tick().unwrap();
+    assert_eq!((state.pc, state.get_register(10)), (2, 5));
+    state.tick().unwrap();
+    assert_eq!((state.pc, state.get_register(11)), (6, 6));
+    state.tick().unwrap();
+    assert_eq!((state.pc, state.get_register(12)), (8, 6));
+    state.tick().unwrap();
+    assert_eq!(state.pc, 0);
+}
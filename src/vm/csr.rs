@@ -0,0 +1,71 @@
+use super::{ArchState, VmError};
+
+#[cfg(test)]
+mod csr_tests;
+
+/// Unprivileged counter CSRs, the `h` variants hold the upper 32 bits
+pub const CSR_CYCLE: u16 = 0xC00;
+pub const CSR_TIME: u16 = 0xC01;
+pub const CSR_INSTRET: u16 = 0xC02;
+pub const CSR_CYCLEH: u16 = 0xC80;
+pub const CSR_TIMEH: u16 = 0xC81;
+pub const CSR_INSTRETH: u16 = 0xC82;
+
+/// Counter names as the `rdcycle` family of pseudo-instructions spell them, without the `rd`
+pub const COUNTER_CSRS: [(&str, u16); 6] = [
+    ("cycle", CSR_CYCLE),
+    ("time", CSR_TIME),
+    ("instret", CSR_INSTRET),
+    ("cycleh", CSR_CYCLEH),
+    ("timeh", CSR_TIMEH),
+    ("instreth", CSR_INSTRETH),
+];
+
+/// How a CSR instruction combines its operand with the old value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum CsrOp {
+    Write,
+    Set,
+    Clear,
+}
+
+impl ArchState {
+    /// `cycle` reads [`ArchState::cycle_count`], every instruction takes one tick of `time`,
+    /// so it reads the same as `instret`
+    pub fn read_csr(&self, csr: u16) -> u32 {
+        match csr {
+            CSR_CYCLE => self.cycles as u32,
+            CSR_CYCLEH => (self.cycles >> 32) as u32,
+            CSR_TIME | CSR_INSTRET => self.retired as u32,
+            CSR_TIMEH | CSR_INSTRETH => (self.retired >> 32) as u32,
+            _ => self.csrs.get(&csr).copied().unwrap_or(0),
+        }
+    }
+
+    /// Runs one Zicsr instruction, writing the old value of `csr` to `rd`.
+    /// Set and clear with a zero operand register or immediate read without writing
+    pub(super) fn apply_csr(
+        &mut self,
+        op: CsrOp,
+        csr: u16,
+        rd: usize,
+        operand: u32,
+        writes: bool,
+    ) -> Result<(), VmError> {
+        // csr[11:10] == 0b11 marks the read-only range, counters included
+        if writes && csr >> 10 == 0b11 {
+            return Err(VmError::ReadOnlyCsr(csr));
+        }
+        let old = self.read_csr(csr);
+        if writes {
+            let new = match op {
+                CsrOp::Write => operand,
+                CsrOp::Set => old | operand,
+                CsrOp::Clear => old & !operand,
+            };
+            self.csrs.insert(csr, new);
+        }
+        self.set_register(rd, old);
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{
+    asm,
+    vm::{self, ArchState, CSR_CYCLE, CSR_CYCLEH, CSR_INSTRET, VmError},
+};
+
+#[test]
+fn test_read_cycle() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let program = [
+        0x00000013_u32, // nop
+        0x00000013,     // nop
+        0x00000013,     // nop
+        0xc00020f3,     // csrrs x1, cycle, x0
+        0xc0202173,     // csrrs x2, instret, x0
+        0xc00011f3,     // csrrw x3, cycle, x0
+    ];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+
+    assert_eq!(state.run(5).steps, 5);
+    // read before the csrrs itself retires
+    assert_eq!(state.get_register(1), 3);
+    assert_eq!(state.get_register(2), 4);
+    assert_eq!(state.read_csr(CSR_CYCLE), 5);
+    assert_eq!(state.read_csr(CSR_INSTRET), 5);
+    assert_eq!(state.read_csr(CSR_CYCLEH), 0);
+
+    assert_eq!(state.tick(), Err(VmError::ReadOnlyCsr(CSR_CYCLE)));
+    assert_eq!(state.get_register(3), 0);
+}
+
+#[test]
+fn test_csr_read_write() {
+    const MSCRATCH: u16 = 0x340;
+    let mut state = ArchState::new();
+    state.set_register(1, 0b1100);
+    // csrrw x2, mscratch, x1
+    state.apply_raw(0x34009173).unwrap();
+    assert_eq!(state.get_register(2), 0);
+    assert_eq!(state.read_csr(MSCRATCH), 0b1100);
+
+    // csrrsi x2, mscratch, 3 then csrrc x2, mscratch, x1
+    state.apply_raw(0x3401e173).unwrap();
+    assert_eq!(state.get_register(2), 0b1100);
+    state.apply_raw(0x3400b173).unwrap();
+    assert_eq!(state.get_register(2), 0b1111);
+    assert_eq!(state.read_csr(MSCRATCH), 0b0011);
+
+    // csrrs x2, cycle, x0 only reads, so the read-only counter doesn't fault
+    state.apply_raw(0xc0002173).unwrap();
+    // csrrsi x0, cycle, 1 writes
+    assert_eq!(
+        state.apply_raw(0xc000e073).unwrap_err(),
+        VmError::ReadOnlyCsr(CSR_CYCLE)
+    );
+}
+
+#[test]
+fn test_rdinstret_around_loop() {
+    const LOOPS: u32 = 25;
+    let source = format!(
+        "   rdinstret a0
+            li t0, {}
+        loop:
+            addi t0, t0, -1
+            bne t0, x0, loop
+            rdinstret a1
+            rdcycleh a2",
+        LOOPS
+    );
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(asm::assemble(&source).unwrap(), 0);
+    assert_eq!(state.run(4 + 2 * LOOPS as u64).steps, 4 + 2 * LOOPS as u64);
+    // each read counts what retired before it, so the first read itself, li, and the loop
+    assert_eq!(
+        state.get_register(11) - state.get_register(10),
+        2 + 2 * LOOPS
+    );
+    assert_eq!(state.get_register(12), 0);
+
+    let listing = vm::disassemble(&asm::assemble("rdtime x5\nrdinstreth x6").unwrap(), 0);
+    assert_eq!(listing[0].2, "rdtime x5");
+    assert_eq!(listing[1].2, "rdinstreth x6");
+}
@@ -0,0 +1,222 @@
+use super::{COUNTER_CSRS, Instruction, SignExtend, try_interpret_bytes};
+
+#[cfg(test)]
+mod disasm_tests;
+
+/// Decodes `bytes` a little-endian word at a time, with the first word at `base_addr`.
+///
+/// Returns each word's address, decode, and assembly text. Words that don't decode
+/// come back as [`Instruction::ILLEGAL`] written `.word 0x...` so the text still assembles,
+/// and a trailing partial word is skipped.
+pub fn disassemble(bytes: &[u8], base_addr: u32) -> Vec<(u32, Instruction, String)> {
+    bytes
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let addr = base_addr.wrapping_add(i as u32 * 4);
+            let word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            match try_interpret_bytes(word) {
+                Some(inst) => {
+                    let text = assembly(&inst, addr);
+                    (addr, inst, text)
+                }
+                None => (
+                    addr,
+                    Instruction::ILLEGAL { raw: word },
+                    format!(".word 0x{:08x}", word),
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Assembly text for `inst` sitting at `addr`, branch and jump targets are absolute
+pub fn assembly(inst: &Instruction, addr: u32) -> String {
+    if inst.is_nop() {
+        return "nop".to_string();
+    }
+    let name = mnemonic(inst);
+    match inst {
+        Instruction::ADD { data }
+        | Instruction::SUB { data }
+        | Instruction::XOR { data }
+        | Instruction::OR { data }
+        | Instruction::AND { data }
+        | Instruction::SLL { data }
+        | Instruction::SRL { data }
+        | Instruction::SRA { data }
+        | Instruction::SLT { data }
+        | Instruction::SLTU { data }
+        | Instruction::MUL { data }
+        | Instruction::MULH { data }
+        | Instruction::MULHSU { data }
+        | Instruction::MULHU { data }
+        | Instruction::DIV { data }
+        | Instruction::DIVU { data }
+        | Instruction::REM { data }
+        | Instruction::REMU { data } => {
+            format!("{} x{}, x{}, x{}", name, data.rd, data.rs1, data.rs2)
+        }
+        Instruction::SLLI { data } | Instruction::SRLI { data } | Instruction::SRAI { data } => {
+            format!(
+                "{} x{}, x{}, {}",
+                name,
+                data.rd,
+                data.rs1,
+                data.imm.val & 0b11111
+            )
+        }
+        Instruction::ADDI { data }
+        | Instruction::XORI { data }
+        | Instruction::ORI { data }
+        | Instruction::ANDI { data }
+        | Instruction::SLTI { data }
+        | Instruction::SLTUI { data } => format!(
+            "{} x{}, x{}, {}",
+            name,
+            data.rd,
+            data.rs1,
+            data.imm.sign_extend()
+        ),
+        Instruction::LB { data }
+        | Instruction::LH { data }
+        | Instruction::LW { data }
+        | Instruction::LBU { data }
+        | Instruction::LHU { data }
+        | Instruction::JALR { data } => format!(
+            "{} x{}, {}(x{})",
+            name,
+            data.rd,
+            data.imm.sign_extend(),
+            data.rs1
+        ),
+        Instruction::SB { data } | Instruction::SH { data } | Instruction::SW { data } => {
+            format!(
+                "{} x{}, {}(x{})",
+                name,
+                data.rs2,
+                data.imm.sign_extend(),
+                data.rs1
+            )
+        }
+        Instruction::BEQ { data }
+        | Instruction::BNE { data }
+        | Instruction::BLT { data }
+        | Instruction::BGE { data }
+        | Instruction::BLTU { data }
+        | Instruction::BGEU { data } => format!(
+            "{} x{}, x{}, 0x{:08x}",
+            name,
+            data.rs1,
+            data.rs2,
+            addr.wrapping_add_signed(data.imm.sign_extend())
+        ),
+        Instruction::JAL { data } => format!(
+            "{} x{}, 0x{:08x}",
+            name,
+            data.rd,
+            addr.wrapping_add_signed(data.imm.sign_extend())
+        ),
+        Instruction::LUI { data } | Instruction::AUIPC { data } => {
+            format!("{} x{}, 0x{:x}", name, data.rd, data.imm.val)
+        }
+        Instruction::FENCE { data } => {
+            // pred is imm[7:4] and succ is imm[3:0], each a set of i, o, r, w
+            let (pred, succ) = (data.imm.val >> 4 & 0b1111, data.imm.val & 0b1111);
+            if pred == 0b1111 && succ == 0b1111 {
+                name
+            } else {
+                format!("{} {}, {}", name, fence_set(pred), fence_set(succ))
+            }
+        }
+        // reading a counter is spelled with its pseudo-instruction, e.g. `rdcycle x1`
+        Instruction::CSRRS { data } if data.rs1 == 0 && counter_name(data.imm.val).is_some() => {
+            format!("rd{} x{}", counter_name(data.imm.val).unwrap(), data.rd)
+        }
+        Instruction::CSRRW { data } | Instruction::CSRRS { data } | Instruction::CSRRC { data } => {
+            format!(
+                "{} x{}, 0x{:03x}, x{}",
+                name, data.rd, data.imm.val, data.rs1
+            )
+        }
+        Instruction::CSRRWI { data }
+        | Instruction::CSRRSI { data }
+        | Instruction::CSRRCI { data } => {
+            format!(
+                "{} x{}, 0x{:03x}, {}",
+                name, data.rd, data.imm.val, data.rs1
+            )
+        }
+        Instruction::LRW { data } => format!(
+            "{}{} x{}, (x{})",
+            name,
+            ordering(data.aq, data.rl),
+            data.rd,
+            data.rs1
+        ),
+        Instruction::SCW { data }
+        | Instruction::AMOSWAPW { data }
+        | Instruction::AMOADDW { data }
+        | Instruction::AMOXORW { data }
+        | Instruction::AMOANDW { data }
+        | Instruction::AMOORW { data }
+        | Instruction::AMOMINW { data }
+        | Instruction::AMOMAXW { data }
+        | Instruction::AMOMINUW { data }
+        | Instruction::AMOMAXUW { data } => format!(
+            "{}{} x{}, x{}, (x{})",
+            name,
+            ordering(data.aq, data.rl),
+            data.rd,
+            data.rs2,
+            data.rs1
+        ),
+        Instruction::ECALL { .. }
+        | Instruction::EBREAK { .. }
+        | Instruction::MRET { .. }
+        | Instruction::FENCEI { .. } => name,
+        Instruction::ILLEGAL { .. } => inst.to_string(),
+    }
+}
+
+/// Suffix for an atomic with the acquire or release bits set, like `.aqrl`
+fn ordering(aq: bool, rl: bool) -> &'static str {
+    match (aq, rl) {
+        (false, false) => "",
+        (true, false) => ".aq",
+        (false, true) => ".rl",
+        (true, true) => ".aqrl",
+    }
+}
+
+/// Name of the counter CSR `csr`, if it is one
+fn counter_name(csr: u32) -> Option<&'static str> {
+    COUNTER_CSRS
+        .iter()
+        .find(|(_, counter)| *counter as u32 == csr)
+        .map(|(name, _)| *name)
+}
+
+/// Assembler spelling of a fence predecessor or successor set
+fn fence_set(bits: u32) -> String {
+    "iorw"
+        .chars()
+        .enumerate()
+        .filter(|(i, _)| bits & (0b1000 >> i) != 0)
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Lower case assembler mnemonic, which spells `SLTUI`, `FENCEI` and the atomics the standard way
+fn mnemonic(inst: &Instruction) -> String {
+    let name = inst
+        .info()
+        .map_or("illegal", |info| info.mnemonic)
+        .to_lowercase();
+    match inst {
+        Instruction::SLTUI { .. } => "sltiu".to_string(),
+        Instruction::FENCEI { .. } => "fence.i".to_string(),
+        _ if inst.is_atomic() => format!("{}.w", &name[..name.len() - 1]),
+        _ => name,
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{asm::assemble, headless, vm::Instruction};
+
+use super::disassemble;
+
+#[test]
+fn test_disassemble() {
+    let program = &headless::demo_program()[0];
+    let listing = disassemble(&(program.source)().unwrap(), program.offset as u32);
+    assert_eq!(listing.len(), 9);
+    assert_eq!(listing[0].0, 0);
+    assert!(matches!(listing[0].1, Instruction::ADDI { .. }));
+    assert_eq!(listing[0].2, "addi x1, x0, 1000");
+    assert_eq!(listing[2].2, "addi x3, x2, -1000");
+    assert_eq!(listing[5].2, "auipc x6, 0x10");
+    assert_eq!(listing[8].0, 0x20);
+    assert_eq!(listing[8].2, "lw x7, 0(x6)");
+}
+
+#[test]
+fn test_disassemble_targets() {
+    // bge x5, x6, -8; jal x0, -8; sw x2, 4(x1); srai x1, x2, 4; a word that doesn't decode;
+    // fence rw, w; fence; fence.i
+    let words = [
+        0xfe62dce3_u32,
+        0xff9ff06f,
+        0x0020a223,
+        0x40415093,
+        0,
+        0x0310000f,
+        0x0ff0000f,
+        0x0000100f,
+    ];
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect::<Vec<u8>>();
+    let listing = disassemble(&bytes, 0x1000)
+        .into_iter()
+        .map(|(addr, _, text)| (addr, text))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        listing,
+        [
+            (0x1000, "bge x5, x6, 0x00000ff8".to_string()),
+            (0x1004, "jal x0, 0x00000ffc".to_string()),
+            (0x1008, "sw x2, 4(x1)".to_string()),
+            (0x100c, "srai x1, x2, 4".to_string()),
+            (0x1010, ".word 0x00000000".to_string()),
+            (0x1014, "fence rw, w".to_string()),
+            (0x1018, "fence".to_string()),
+            (0x101c, "fence.i".to_string()),
+        ]
+    );
+    // words that don't decode still assemble back to themselves
+    assert_eq!(assemble(&listing[4].1).unwrap(), [0; 4]);
+}
+
+#[test]
+fn test_disassemble_counter_reads() {
+    // csrrs x5, cycle, x0; csrrs x5, cycle, x1
+    let bytes = [0xc00022f3_u32, 0xc000a2f3]
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect::<Vec<u8>>();
+    let listing = disassemble(&bytes, 0);
+    assert_eq!(listing[0].2, "rdcycle x5");
+    // only a read with rs1 = x0 is the pseudo-instruction
+    assert_eq!(listing[1].2, "csrrs x5, 0xc00, x1");
+}
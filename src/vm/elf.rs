@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use super::{ArchState, Segment, VmError};
+
+#[cfg(test)]
+mod elf_tests;
+
+const EM_RISCV: u16 = 0xF3;
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16, VmError> {
+    data.get(at..at + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(VmError::InvalidElf("truncated header"))
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32, VmError> {
+    data.get(at..at + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or(VmError::InvalidElf("truncated header"))
+}
+
+fn check_header(data: &[u8]) -> Result<(), VmError> {
+    if !data.starts_with(b"\x7fELF") {
+        return Err(VmError::InvalidElf("missing ELF magic"));
+    }
+    if data.get(4) != Some(&1) {
+        return Err(VmError::InvalidElf("not a 32-bit ELF"));
+    }
+    if data.get(5) != Some(&1) {
+        return Err(VmError::InvalidElf("not a little-endian ELF"));
+    }
+    if read_u16(data, 18)? != EM_RISCV {
+        return Err(VmError::InvalidElf("not a RISC-V ELF"));
+    }
+    Ok(())
+}
+
+/// Parses a 32-bit little-endian RISC-V ELF into its entry point and one segment per `PT_LOAD`.
+/// Each segment is padded with zeros out to its memory size so `.bss` is cleared on reload.
+/// Segments that wouldn't fit in `mem_size` bytes are rejected before anything is allocated
+pub fn elf_segments(data: &[u8], mem_size: usize) -> Result<(u32, Vec<Segment>), VmError> {
+    check_header(data)?;
+    let entry = read_u32(data, 24)?;
+    let phoff = read_u32(data, 28)? as usize;
+    let phentsize = read_u16(data, 42)? as usize;
+    let phnum = read_u16(data, 44)? as usize;
+
+    let mut segments = vec![];
+    for i in 0..phnum {
+        let header = phoff + i * phentsize;
+        if read_u32(data, header)? != PT_LOAD {
+            continue;
+        }
+        let offset = read_u32(data, header + 4)? as usize;
+        let vaddr = read_u32(data, header + 8)?;
+        let filesz = read_u32(data, header + 16)? as usize;
+        let memsz = read_u32(data, header + 20)? as usize;
+        if vaddr as usize + memsz.max(filesz) > mem_size {
+            return Err(VmError::InvalidElf("segment doesn't fit in memory"));
+        }
+        let mut bytes = data
+            .get(offset..offset + filesz)
+            .ok_or(VmError::InvalidElf("segment runs past the end of the file"))?
+            .to_vec();
+        bytes.resize(memsz.max(filesz), 0);
+        segments.push(Segment::from_bytes(bytes, vaddr as usize));
+    }
+    Ok((entry, segments))
+}
+
+/// Named functions, objects, and labels from the ELF symbol table by address, empty for a
+/// stripped file. When several names share an address the first one in the table wins
+pub fn elf_symbols(data: &[u8]) -> Result<BTreeMap<u32, String>, VmError> {
+    check_header(data)?;
+    let shoff = read_u32(data, 32)? as usize;
+    let shentsize = read_u16(data, 46)? as usize;
+    let shnum = read_u16(data, 48)? as usize;
+    let section = |index: usize| shoff + index * shentsize;
+
+    let mut symbols = BTreeMap::new();
+    for i in 0..shnum {
+        if read_u32(data, section(i) + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let offset = read_u32(data, section(i) + 16)? as usize;
+        let size = read_u32(data, section(i) + 20)? as usize;
+        let strtab = section(read_u32(data, section(i) + 24)? as usize);
+        let strtab_offset = read_u32(data, strtab + 16)? as usize;
+        let entsize = (read_u32(data, section(i) + 36)? as usize).max(16);
+        for entry in (offset..offset + size).step_by(entsize) {
+            let info = *data
+                .get(entry + 12)
+                .ok_or(VmError::InvalidElf("truncated symbol table"))?;
+            // undefined symbols have no address to label
+            if !matches!(info & 0xf, STT_NOTYPE | STT_OBJECT | STT_FUNC)
+                || read_u16(data, entry + 14)? == 0
+            {
+                continue;
+            }
+            let name_start = strtab_offset + read_u32(data, entry)? as usize;
+            let name = data
+                .get(name_start..)
+                .and_then(|rest| rest.split(|&byte| byte == 0).next())
+                .ok_or(VmError::InvalidElf("symbol name outside the string table"))?;
+            // `$x` and `$d` mark code and data for disassemblers, they aren't names
+            if !name.is_empty() && !name.starts_with(b"$") {
+                symbols
+                    .entry(read_u32(data, entry + 4)?)
+                    .or_insert_with(|| String::from_utf8_lossy(name).into_owned());
+            }
+        }
+    }
+    Ok(symbols)
+}
+
+impl ArchState {
+    /// The symbol at or below `addr` and how far past it `addr` is, e.g. `("main", 0x10)`
+    pub fn nearest_symbol(&self, addr: u32) -> Option<(&str, u32)> {
+        self.symbols
+            .range(..=addr)
+            .next_back()
+            .map(|(start, name)| (name.as_str(), addr - start))
+    }
+
+    /// Copies each `PT_LOAD` segment of a RISC-V ELF to its virtual address and makes the
+    /// entry the reset vector and pc, returning it. Memory is left alone on error
+    pub fn load_elf(&mut self, data: &[u8]) -> Result<u32, VmError> {
+        let (entry, segments) = elf_segments(data, self.mem.len())?;
+        self.symbols = elf_symbols(data)?;
+        for segment in segments {
+            // from_bytes sources can't fail
+            self.load((segment.source)().unwrap_or_default(), segment.offset);
+        }
+        self.set_entry(entry);
+        Ok(entry)
+    }
+}
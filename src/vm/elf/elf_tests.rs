@@ -0,0 +1,167 @@
+use crate::vm::{ArchState, VmError, elf_segments};
+
+/// A minimal ELF with one PT_LOAD segment at `vaddr` holding `code` followed by `bss` zeroed bytes
+fn fixture(vaddr: u32, entry: u32, code: &[u32], bss: u32) -> Vec<u8> {
+    const EHSIZE: u32 = 52;
+    const PHENTSIZE: u32 = 32;
+    let code = code
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect::<Vec<u8>>();
+
+    let mut elf = b"\x7fELF".to_vec();
+    elf.extend([1, 1, 1, 0]); // 32-bit, little endian, version 1, System V
+    elf.extend([0; 8]);
+    elf.extend(2_u16.to_le_bytes()); // executable
+    elf.extend(0xF3_u16.to_le_bytes()); // RISC-V
+    elf.extend(1_u32.to_le_bytes());
+    elf.extend(entry.to_le_bytes());
+    elf.extend(EHSIZE.to_le_bytes()); // e_phoff
+    elf.extend(0_u32.to_le_bytes()); // e_shoff
+    elf.extend(0_u32.to_le_bytes()); // e_flags
+    elf.extend((EHSIZE as u16).to_le_bytes());
+    elf.extend((PHENTSIZE as u16).to_le_bytes());
+    elf.extend(1_u16.to_le_bytes()); // e_phnum
+    elf.extend([0; 6]); // no section headers
+    assert_eq!(elf.len(), EHSIZE as usize);
+
+    let filesz = code.len() as u32;
+    for field in [
+        1,                  // PT_LOAD
+        EHSIZE + PHENTSIZE, // p_offset
+        vaddr,
+        vaddr,
+        filesz,
+        filesz + bss,
+        0b101, // read and execute
+        4,
+    ] {
+        elf.extend(field.to_le_bytes());
+    }
+    elf.extend(code);
+    elf
+}
+
+#[test]
+fn test_load_elf() {
+    let mut state = ArchState::with_mem(2_usize.pow(12));
+    state.mem[0x808] = 0xff;
+    // addi x1, x0, 7; addi x2, x1, 1
+    let elf = fixture(0x800, 0x804, &[0x00700093, 0x00108113], 4);
+
+    assert_eq!(state.load_elf(&elf), Ok(0x804));
+    assert_eq!(state.pc, 0x804);
+    assert_eq!(
+        state.mem.read(0x800, 4).unwrap(),
+        0x00700093_u32.to_le_bytes()
+    );
+    assert_eq!(state.mem[0x808], 0, "bss is cleared");
+
+    state.tick().unwrap();
+    assert_eq!(state.get_register(2), 1);
+
+    // the entry is also where a reset starts
+    state.reset();
+    assert_eq!(state.pc, 0x804);
+}
+
+#[test]
+fn test_load_elf_rejects() {
+    let mut state = ArchState::with_mem(2_usize.pow(12));
+    let elf = fixture(0x800, 0x800, &[0x00700093], 0);
+
+    let mut wide = elf.clone();
+    wide[4] = 2;
+    assert_eq!(
+        state.load_elf(&wide),
+        Err(VmError::InvalidElf("not a 32-bit ELF"))
+    );
+
+    let mut x86 = elf.clone();
+    x86[18] = 0x3E;
+    assert_eq!(
+        state.load_elf(&x86),
+        Err(VmError::InvalidElf("not a RISC-V ELF"))
+    );
+
+    assert_eq!(
+        state.load_elf(&elf[..40]),
+        Err(VmError::InvalidElf("truncated header"))
+    );
+    assert_eq!(
+        state.load_elf(&[0x93, 0x00, 0x70, 0x00]),
+        Err(VmError::InvalidElf("missing ELF magic"))
+    );
+
+    let far = fixture(0x1000, 0x1000, &[0x00700093], 0);
+    assert_eq!(
+        state.load_elf(&far),
+        Err(VmError::InvalidElf("segment doesn't fit in memory"))
+    );
+    assert_eq!(state.pc, 0, "nothing was loaded");
+
+    // a huge .bss is turned down before it is allocated
+    let huge = fixture(0x800, 0x800, &[0x00700093], 0xfff0_0000);
+    assert_eq!(
+        elf_segments(&huge, state.mem.len()).map(|_| ()),
+        Err(VmError::InvalidElf("segment doesn't fit in memory"))
+    );
+    assert_eq!(
+        state.load_elf(&huge),
+        Err(VmError::InvalidElf("segment doesn't fit in memory"))
+    );
+}
+
+/// Appends a symbol table naming each `(name, value, info)` and points the header at it
+fn with_symbols(mut elf: Vec<u8>, symbols: &[(&str, u32, u8)]) -> Vec<u8> {
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; 16]; // the null symbol
+    for (name, value, info) in symbols {
+        symtab.extend((strtab.len() as u32).to_le_bytes());
+        symtab.extend(value.to_le_bytes());
+        symtab.extend(0_u32.to_le_bytes()); // st_size
+        symtab.extend([*info, 0]);
+        symtab.extend(1_u16.to_le_bytes()); // defined in section 1
+        strtab.extend(name.bytes());
+        strtab.push(0);
+    }
+    let symtab_offset = elf.len() as u32;
+    elf.extend(&symtab);
+    let strtab_offset = elf.len() as u32;
+    elf.extend(&strtab);
+
+    let shoff = elf.len() as u32;
+    elf.extend([0; 40]); // null section
+    // sh_name, type, flags, addr, offset, size, link, info, addralign, entsize
+    for field in [0, 2, 0, 0, symtab_offset, symtab.len() as u32, 2, 0, 4, 16] {
+        elf.extend(field.to_le_bytes());
+    }
+    for field in [0, 3, 0, 0, strtab_offset, strtab.len() as u32, 0, 0, 1, 0] {
+        elf.extend(field.to_le_bytes());
+    }
+    elf[32..36].copy_from_slice(&shoff.to_le_bytes());
+    elf[46..48].copy_from_slice(&40_u16.to_le_bytes());
+    elf[48..50].copy_from_slice(&3_u16.to_le_bytes());
+    elf
+}
+
+#[test]
+fn test_nearest_symbol() {
+    let mut state = ArchState::with_mem(2_usize.pow(12));
+    let elf = with_symbols(
+        fixture(0x800, 0x800, &[0x00700093; 8], 0),
+        &[
+            ("_start", 0x800, 0x10), // global label
+            ("main", 0x810, 0x12),   // global function
+            ("$x", 0x810, 0x00),     // mapping symbol
+            ("file.c", 0, 0x04),     // file
+        ],
+    );
+    state.load_elf(&elf).unwrap();
+
+    assert_eq!(state.symbols.len(), 2);
+    assert_eq!(state.nearest_symbol(0x800), Some(("_start", 0)));
+    assert_eq!(state.nearest_symbol(0x80c), Some(("_start", 0xc)));
+    assert_eq!(state.nearest_symbol(0x81c), Some(("main", 0xc)));
+    assert_eq!(state.nearest_symbol(0x7fc), None);
+}
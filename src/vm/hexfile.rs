@@ -0,0 +1,172 @@
+use super::{ArchState, Segment, VmError};
+
+#[cfg(test)]
+mod hexfile_tests;
+
+/// Data records in file order, contiguous runs merged, with the line each run starts on
+type Chunks = Vec<(usize, u32, Vec<u8>)>;
+
+fn invalid(line: usize, reason: &'static str) -> VmError {
+    VmError::InvalidHexFile { line, reason }
+}
+
+/// Decodes pairs of hex digits, `line` is only for the error
+fn hex_bytes(line: usize, digits: &str) -> Result<Vec<u8>, VmError> {
+    if !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+        return Err(invalid(line, "expected pairs of hex digits"));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(&digits[at..at + 2], 16))
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid(line, "expected pairs of hex digits"))
+}
+
+fn push_data(chunks: &mut Chunks, line: usize, addr: u32, data: &[u8]) {
+    match chunks.last_mut() {
+        Some((_, start, bytes)) if start.wrapping_add(bytes.len() as u32) == addr => {
+            bytes.extend(data)
+        }
+        _ => chunks.push((line, addr, data.to_vec())),
+    }
+}
+
+fn into_segments(chunks: Chunks) -> Vec<Segment> {
+    chunks
+        .into_iter()
+        .map(|(_, addr, bytes)| Segment::from_bytes(bytes, addr as usize))
+        .collect()
+}
+
+fn parse_ihex(text: &str) -> Result<(Option<u32>, Chunks), VmError> {
+    let mut chunks = vec![];
+    let mut start = None;
+    // upper address bits from the last extended segment or linear address record
+    let mut base = 0_u32;
+    for (index, record) in text.lines().enumerate() {
+        let line = index + 1;
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let digits = record
+            .strip_prefix(':')
+            .ok_or(invalid(line, "record doesn't start with ':'"))?;
+        let bytes = hex_bytes(line, digits)?;
+        if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+            return Err(invalid(line, "length doesn't match the record"));
+        }
+        if bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(invalid(line, "checksum mismatch"));
+        }
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        let be = |len: usize| -> Result<u32, VmError> {
+            if data.len() != len {
+                return Err(invalid(line, "wrong length for the record type"));
+            }
+            Ok(data.iter().fold(0, |val, byte| val << 8 | *byte as u32))
+        };
+        match bytes[3] {
+            0x00 => push_data(&mut chunks, line, base.wrapping_add(offset), data),
+            0x01 => break,
+            0x02 => base = be(2)? << 4,
+            0x03 => {
+                let cs_ip = be(4)?;
+                start = Some(((cs_ip >> 16) << 4) + (cs_ip & 0xffff));
+            }
+            0x04 => base = be(2)? << 16,
+            0x05 => start = Some(be(4)?),
+            _ => return Err(invalid(line, "unknown record type")),
+        }
+    }
+    Ok((start, chunks))
+}
+
+fn parse_srec(text: &str) -> Result<(Option<u32>, Chunks), VmError> {
+    let mut chunks = vec![];
+    let mut start = None;
+    for (index, record) in text.lines().enumerate() {
+        let line = index + 1;
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let (kind, digits) = record
+            .strip_prefix('S')
+            .and_then(|rest| Some((rest.get(..1)?, rest.get(1..)?)))
+            .ok_or(invalid(line, "record doesn't start with 'S' and a type"))?;
+        let bytes = hex_bytes(line, digits)?;
+        if bytes.is_empty() || bytes.len() != 1 + bytes[0] as usize {
+            return Err(invalid(line, "length doesn't match the record"));
+        }
+        if bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)) != 0xff {
+            return Err(invalid(line, "checksum mismatch"));
+        }
+        let addr_len = match kind {
+            "0" | "1" | "5" | "9" => 2,
+            "2" | "6" | "8" => 3,
+            "3" | "7" => 4,
+            _ => return Err(invalid(line, "unknown record type")),
+        };
+        if bytes.len() < 2 + addr_len {
+            return Err(invalid(line, "record is too short for its address"));
+        }
+        let addr = bytes[1..1 + addr_len]
+            .iter()
+            .fold(0, |val, byte| val << 8 | *byte as u32);
+        let data = &bytes[1 + addr_len..bytes.len() - 1];
+        match kind {
+            "1" | "2" | "3" => push_data(&mut chunks, line, addr, data),
+            "7" | "8" | "9" => start = Some(addr),
+            // header and record counts
+            _ => {}
+        }
+    }
+    Ok((start, chunks))
+}
+
+/// Parses an Intel HEX file into its start address, if it has one, and its data as segments.
+/// Extended segment and linear address records are applied and checksums are verified
+pub fn ihex_segments(text: &str) -> Result<(Option<u32>, Vec<Segment>), VmError> {
+    let (start, chunks) = parse_ihex(text)?;
+    Ok((start, into_segments(chunks)))
+}
+
+/// Parses a Motorola S-record file into its start address, if it has one, and its data as
+/// segments. Checksums are verified, header and count records are ignored
+pub fn srec_segments(text: &str) -> Result<(Option<u32>, Vec<Segment>), VmError> {
+    let (start, chunks) = parse_srec(text)?;
+    Ok((start, into_segments(chunks)))
+}
+
+impl ArchState {
+    /// Copies each data record of an Intel HEX file to its address and points pc at the
+    /// start address if the file has one, which is also returned. Memory is left alone on error
+    pub fn load_ihex(&mut self, text: &str) -> Result<Option<u32>, VmError> {
+        let (start, chunks) = parse_ihex(text)?;
+        self.load_chunks(start, chunks)
+    }
+
+    /// Same as [`ArchState::load_ihex`] for a Motorola S-record file
+    pub fn load_srec(&mut self, text: &str) -> Result<Option<u32>, VmError> {
+        let (start, chunks) = parse_srec(text)?;
+        self.load_chunks(start, chunks)
+    }
+
+    fn load_chunks(&mut self, start: Option<u32>, chunks: Chunks) -> Result<Option<u32>, VmError> {
+        if let Some((line, _, _)) = chunks
+            .iter()
+            .find(|(_, addr, bytes)| *addr as usize + bytes.len() > self.mem.len())
+        {
+            return Err(invalid(*line, "data doesn't fit in memory"));
+        }
+        for (_, addr, bytes) in chunks {
+            self.load(bytes, addr as usize);
+        }
+        if let Some(start) = start {
+            self.pc = start as i64;
+        }
+        Ok(start)
+    }
+}
@@ -0,0 +1,90 @@
+use crate::vm::{ArchState, VmError, ihex_segments, srec_segments};
+
+/// addi x1, x0, 7; addi x2, x1, 1 at 0x10800 behind an extended linear address record
+const IHEX: &str = "\
+:020000040001F9
+:0408000093007000F1
+:04080400138110004C
+:0400000500010800EE
+:00000001FF
+";
+
+/// The same program at 0x800 with a header, a 32-bit address record, and a count
+const SREC: &str = "\
+S0060000686472BB
+S10B0800930070001381100045
+S30900010000EFBEADDEBD
+S5030002FA
+S9030800F4
+";
+
+#[test]
+fn test_load_ihex() {
+    let mut state = ArchState::with_mem(2_usize.pow(17));
+    assert_eq!(state.load_ihex(IHEX), Ok(Some(0x10800)));
+    assert_eq!(state.pc, 0x10800);
+    state.tick().unwrap();
+    state.tick().unwrap();
+    assert_eq!(state.get_register(2), 8);
+
+    // neighbouring records are merged into one segment
+    let (start, segments) = ihex_segments(IHEX).unwrap();
+    assert_eq!(start, Some(0x10800));
+    assert_eq!(segments.len(), 1);
+    assert_eq!((segments[0].source)().unwrap().len(), 8);
+}
+
+#[test]
+fn test_load_srec() {
+    let mut state = ArchState::with_mem(2_usize.pow(17));
+    assert_eq!(state.load_srec(SREC), Ok(Some(0x800)));
+    assert_eq!(
+        state.mem.read(0x10000, 4).unwrap(),
+        [0xef, 0xbe, 0xad, 0xde]
+    );
+    state.tick().unwrap();
+    state.tick().unwrap();
+    assert_eq!(state.get_register(2), 8);
+    assert_eq!(srec_segments(SREC).unwrap().1.len(), 2);
+}
+
+#[test]
+fn test_hex_file_rejects() {
+    let mut state = ArchState::with_mem(2_usize.pow(17));
+    let bad_sum = IHEX.replace(":04080400138110004C", ":04080400138110004D");
+    assert_eq!(
+        state.load_ihex(&bad_sum),
+        Err(VmError::InvalidHexFile {
+            line: 3,
+            reason: "checksum mismatch"
+        })
+    );
+    assert_eq!(
+        state.mem.allocated_pages(),
+        0,
+        "nothing is loaded from a bad file"
+    );
+
+    let bad_sum = SREC.replace("S9030800F4", "S9030800F5");
+    assert_eq!(
+        state.load_srec(&bad_sum),
+        Err(VmError::InvalidHexFile {
+            line: 5,
+            reason: "checksum mismatch"
+        })
+    );
+    assert_eq!(
+        state.load_ihex(":0408"),
+        Err(VmError::InvalidHexFile {
+            line: 1,
+            reason: "length doesn't match the record"
+        })
+    );
+
+    // too big for the memory
+    let mut small = ArchState::with_mem(0x1000);
+    assert_eq!(
+        small.load_ihex(IHEX).unwrap_err().to_string(),
+        "invalid hex file, line 2: data doesn't fit in memory"
+    );
+}
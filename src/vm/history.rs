@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+
+use super::{
+    ArchState, Instruction, InstructionStats, SYS_READ, VmError,
+    trap::{CSR_MCAUSE, CSR_MEPC, CSR_MTVAL},
+};
+
+#[cfg(test)]
+mod history_tests;
+
+/// Snapshots kept for [`ArchState::step_back`] unless changed with `set_history_limit`
+pub const DEFAULT_HISTORY_LIMIT: usize = 1024;
+
+/// State from just before one `tick`. Memory only keeps the bytes the instruction was
+/// about to overwrite rather than a copy of every page
+pub(super) struct Snapshot {
+    regs: [u32; 31],
+    pc: i64,
+    exit_status: Option<u32>,
+    halted: bool,
+    retired: u64,
+    cycles: u64,
+    stats: InstructionStats,
+    reservation: Option<u32>,
+    /// CSRs the instruction or a trap may write and their entries before, `None` if one
+    /// was never written
+    csrs: Vec<(u16, Option<u32>)>,
+    /// Start address and the old contents of the range written
+    mem: Option<(usize, Vec<u8>)>,
+}
+
+/// Bounded ring of snapshots, the oldest are dropped once full
+pub(super) struct History {
+    snapshots: VecDeque<Snapshot>,
+    limit: usize,
+}
+
+impl History {
+    pub(super) fn new(limit: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            limit,
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    fn push(&mut self, snapshot: Snapshot) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.limit {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+}
+
+impl ArchState {
+    /// Undoes the last `tick`, returning false once there is nothing left to undo.
+    /// Writes made by a custom syscall handler outside of `read`'s buffer aren't restored
+    pub fn step_back(&mut self) -> bool {
+        let Some(snapshot) = self.history.snapshots.pop_back() else {
+            return false;
+        };
+        self.regs = snapshot.regs;
+        self.pc = snapshot.pc;
+        self.exit_status = snapshot.exit_status;
+        self.halted = snapshot.halted;
+        self.retired = snapshot.retired;
+        self.cycles = snapshot.cycles;
+        self.stats = snapshot.stats;
+        self.reservation = snapshot.reservation;
+        // the first entry for a CSR is the oldest, so it goes back last
+        for (csr, val) in snapshot.csrs.into_iter().rev() {
+            match val {
+                Some(val) => self.csrs.insert(csr, val),
+                None => self.csrs.remove(&csr),
+            };
+        }
+        if let Some((addr, bytes)) = snapshot.mem {
+            self.mem.write(addr, &bytes);
+            self.clear_decode_cache();
+        }
+        true
+    }
+
+    /// How many ticks `step_back` can undo, 0 turns recording off
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.limit = limit;
+        while self.history.snapshots.len() > limit {
+            self.history.snapshots.pop_front();
+        }
+    }
+
+    /// Captures everything `inst` may change before it runs at the current pc
+    pub(super) fn snapshot(&self, inst: &Instruction) -> Snapshot {
+        let written = match inst {
+            Instruction::ECALL { .. }
+                if self.syscall_handler.is_some() && self.get_register(17) == SYS_READ =>
+            {
+                // the guest picks the length, only the part inside memory can be written
+                let addr = self.get_register(11) as usize;
+                let len = self.get_register(12) as usize;
+                Some((addr, len.min(self.mem.len().saturating_sub(addr))))
+            }
+            _ => self
+                .store_target(inst)
+                .map(|(addr, width)| (addr as usize, width as usize)),
+        };
+        let mut snapshot = self.bare_snapshot();
+        match inst {
+            Instruction::CSRRW { data }
+            | Instruction::CSRRS { data }
+            | Instruction::CSRRC { data }
+            | Instruction::CSRRWI { data }
+            | Instruction::CSRRSI { data }
+            | Instruction::CSRRCI { data } => {
+                let csr = data.imm.val as u16;
+                snapshot.csrs.push((csr, self.csrs.get(&csr).copied()));
+            }
+            _ => {}
+        }
+        snapshot.mem = written.and_then(|(addr, len)| Some((addr, self.mem.read(addr, len)?)));
+        snapshot
+    }
+
+    /// Captures the registers and counters but no CSRs or memory, enough to undo a fault
+    /// raised before the instruction decoded once [`ArchState::trap_and_record`] adds the
+    /// trap CSRs
+    pub(super) fn bare_snapshot(&self) -> Snapshot {
+        Snapshot {
+            regs: self.regs,
+            pc: self.pc,
+            exit_status: self.exit_status,
+            halted: self.halted,
+            retired: self.retired,
+            cycles: self.cycles,
+            stats: self.stats,
+            reservation: self.reservation,
+            csrs: Vec::new(),
+            mem: None,
+        }
+    }
+
+    pub(super) fn record(&mut self, snapshot: Snapshot) {
+        self.history.push(snapshot);
+    }
+
+    /// Vectors `err` through [`ArchState::trap`], recording `snapshot` with the trap CSRs
+    /// added so `step_back` returns to the faulting instruction
+    pub(super) fn trap_and_record(
+        &mut self,
+        err: VmError,
+        inst: Option<&Instruction>,
+        mut snapshot: Snapshot,
+    ) -> Result<(), VmError> {
+        snapshot.csrs.extend(
+            [CSR_MEPC, CSR_MCAUSE, CSR_MTVAL].map(|csr| (csr, self.csrs.get(&csr).copied())),
+        );
+        self.trap(err, inst)?;
+        self.record(snapshot);
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    test_fixtures::accumulator,
+    vm::{ArchState, SYS_READ, SyscallHandler, SyscallOutcome},
+};
+
+#[test]
+fn test_step_back() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // addi x1, x0, 0x55; addi x2, x0, 0x80; sb x1, 0(x2); addi x1, x1, 1
+    state.load(
+        [0x05500093_u32, 0x08000113, 0x00110023, 0x00108093]
+            .iter()
+            .flat_map(|op| op.to_le_bytes())
+            .collect(),
+        0,
+    );
+    state.mem[0x80] = 0xaa;
+    for _ in 0..4 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.get_register(1), 0x56);
+    assert_eq!(state.mem[0x80], 0x55);
+
+    assert!(state.step_back());
+    assert_eq!(state.pc, 12);
+    assert_eq!(state.get_register(1), 0x55);
+    assert_eq!(state.mem[0x80], 0x55);
+
+    assert!(state.step_back());
+    assert_eq!(state.pc, 8);
+    assert_eq!(state.mem[0x80], 0xaa, "the store is undone");
+
+    assert!(state.step_back());
+    assert!(state.step_back());
+    assert_eq!(state.pc, 0);
+    assert_eq!(state.get_register(1), 0);
+    assert_eq!(
+        state.get_register(2),
+        0xf0,
+        "back to the initial stack pointer"
+    );
+    assert!(!state.step_back());
+
+    // stepping forward again replays the same program
+    state.tick().unwrap();
+    assert_eq!(state.get_register(1), 0x55);
+}
+
+#[test]
+fn test_history_limit() {
+    let mut state = accumulator();
+    state.set_history_limit(2);
+    for _ in 0..5 {
+        state.tick().unwrap();
+    }
+    assert!(state.step_back());
+    assert!(state.step_back());
+    assert!(!state.step_back(), "older snapshots were dropped");
+    assert_eq!(state.get_register(1), 3);
+}
+
+/// Fills the whole `read` buffer that fits in memory with 0xee, whatever length was asked for
+struct Fill;
+
+impl SyscallHandler for Fill {
+    fn handle(&mut self, state: &mut ArchState) -> SyscallOutcome {
+        let addr = state.get_register(11) as usize;
+        for byte in addr..state.mem.len() {
+            state.mem[byte] = 0xee;
+        }
+        SyscallOutcome::Continue
+    }
+}
+
+#[test]
+fn test_step_back_read() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(0x00000073_u32.to_le_bytes().to_vec(), 0); // ecall
+    state.set_syscall_handler(Box::new(Fill));
+    state.set_register(17, SYS_READ);
+    state.set_register(11, 0xf0);
+    // a length far past the end of memory only snapshots what is there
+    state.set_register(12, u32::MAX);
+    state.tick().unwrap();
+    assert_eq!(state.mem[0xff], 0xee);
+
+    assert!(state.step_back());
+    assert_eq!(state.mem.read(0xf0, 0x10).unwrap(), [0; 0x10]);
+}
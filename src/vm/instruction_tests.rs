@@ -1,6 +1,10 @@
-use crate::vm::{ArchState, B, BigImmediate, J, S, U, transmute_to_signed};
+use crate::vm::{
+    ArchState, B, BigImmediate, BranchImmediate, ImmediateLayout, J, JumpImmediate, S, SignExtend,
+    StoreImmediate, U, VmError, VmEvent, all_opcodes, assembly, interpret_bytes, register_abi_name,
+    transmute_to_signed, try_interpret_bytes,
+};
 
-use super::{I, Instruction, R, SmallImmediate};
+use super::{A, I, Instruction, Operands, R, SmallImmediate};
 
 #[test]
 fn test_arithmetic() {
@@ -9,25 +13,147 @@ fn test_arithmetic() {
         rs1: 2,
         rs2: 3,
     };
-    for (inst, expected) in vec![
-        (Instruction::ADD { data: data.clone() }, 2),
-        (Instruction::SUB { data: data.clone() }, 0),
-        (Instruction::XOR { data: data.clone() }, 0),
-        (Instruction::OR { data: data.clone() }, 1),
-        (Instruction::AND { data: data.clone() }, 1),
-        (Instruction::SLL { data: data.clone() }, 2),
-        (Instruction::SRL { data: data.clone() }, 0),
-        (Instruction::SRA { data: data.clone() }, 0),
+    for (inst, expected) in [
+        (Instruction::ADD { data }, 2),
+        (Instruction::SUB { data }, 0),
+        (Instruction::XOR { data }, 0),
+        (Instruction::OR { data }, 1),
+        (Instruction::AND { data }, 1),
+        (Instruction::SLL { data }, 2),
+        (Instruction::SRL { data }, 0),
+        (Instruction::SRA { data }, 0),
     ] {
         let mut state = ArchState::new();
         state.set_register(2, 1);
         state.set_register(3, 1);
-        state.apply(&inst);
+        state.apply(&inst).unwrap();
         println!("Test {:?}", &inst);
         assert_eq!(expected, state.get_register(1));
     }
 }
 
+#[test]
+fn test_wrapping_arithmetic() {
+    let mut state = ArchState::new();
+    state.set_register(2, 0xFFFFFFFF);
+    state.set_register(3, 1);
+    let data = R {
+        rd: 1,
+        rs1: 2,
+        rs2: 3,
+    };
+    state.apply(&Instruction::ADD { data }).unwrap();
+    assert_eq!(state.get_register(1), 0);
+
+    let data = R {
+        rd: 1,
+        rs1: 0,
+        rs2: 3,
+    };
+    state.apply(&Instruction::SUB { data }).unwrap();
+    assert_eq!(state.get_register(1), 0xFFFFFFFF);
+
+    let data = I {
+        rd: 1,
+        rs1: 2,
+        imm: SmallImmediate::from(1),
+    };
+    state.apply(&Instruction::ADDI { data }).unwrap();
+    assert_eq!(state.get_register(1), 0);
+}
+
+/// Applies `inst` to x1 = `a`, x2 = `b` and returns x3
+fn apply_r(inst: fn(R) -> Instruction, a: u32, b: u32) -> u32 {
+    let mut state = ArchState::with_mem(4);
+    state.set_register(1, a);
+    state.set_register(2, b);
+    state
+        .apply(&inst(R {
+            rd: 3,
+            rs1: 1,
+            rs2: 2,
+        }))
+        .unwrap();
+    state.get_register(3)
+}
+
+#[test]
+fn test_multiply() {
+    let mul = |data| Instruction::MUL { data };
+    let mulh = |data| Instruction::MULH { data };
+    let mulhsu = |data| Instruction::MULHSU { data };
+    let mulhu = |data| Instruction::MULHU { data };
+
+    assert_eq!(apply_r(mul, 6, 7), 42);
+    assert_eq!(apply_r(mul, -3_i32 as u32, 5), -15_i32 as u32);
+    assert_eq!(apply_r(mul, 0x80000000, 2), 0, "only the low bits are kept");
+
+    assert_eq!(apply_r(mulh, -1_i32 as u32, -1_i32 as u32), 0);
+    assert_eq!(apply_r(mulh, 0x80000000, 0x80000000), 0x40000000);
+    assert_eq!(apply_r(mulh, -2_i32 as u32, 3), 0xFFFFFFFF);
+
+    // rs1 signed, rs2 unsigned
+    assert_eq!(apply_r(mulhsu, -1_i32 as u32, 0xFFFFFFFF), 0xFFFFFFFF);
+    assert_eq!(apply_r(mulhsu, 2, 0xFFFFFFFF), 1);
+
+    assert_eq!(apply_r(mulhu, 0xFFFFFFFF, 0xFFFFFFFF), 0xFFFFFFFE);
+    assert_eq!(apply_r(mulhu, 0x80000000, 2), 1);
+}
+
+#[test]
+fn test_divide() {
+    let div = |data| Instruction::DIV { data };
+    let divu = |data| Instruction::DIVU { data };
+    let rem = |data| Instruction::REM { data };
+    let remu = |data| Instruction::REMU { data };
+
+    assert_eq!(
+        apply_r(div, -7_i32 as u32, 2),
+        -3_i32 as u32,
+        "rounds toward zero"
+    );
+    assert_eq!(apply_r(divu, 0xFFFFFFFE, 2), 0x7FFFFFFF);
+    assert_eq!(
+        apply_r(rem, -7_i32 as u32, 2),
+        -1_i32 as u32,
+        "takes the dividend's sign"
+    );
+    assert_eq!(apply_r(remu, 7, 2), 1);
+
+    // division by zero
+    assert_eq!(apply_r(div, 5, 0), 0xFFFFFFFF);
+    assert_eq!(apply_r(divu, 5, 0), 0xFFFFFFFF);
+    assert_eq!(apply_r(rem, -5_i32 as u32, 0), -5_i32 as u32);
+    assert_eq!(apply_r(remu, 5, 0), 5);
+
+    // signed overflow
+    assert_eq!(apply_r(div, 0x80000000, -1_i32 as u32), 0x80000000);
+    assert_eq!(apply_r(rem, 0x80000000, -1_i32 as u32), 0);
+}
+
+#[test]
+fn test_multiply_divide_decode() {
+    // mul x3, x1, x2 used to decode as add
+    assert!(matches!(
+        interpret_bytes(0x022081b3),
+        Instruction::MUL { .. }
+    ));
+    // remu x3, x1, x2
+    assert!(matches!(
+        interpret_bytes(0x0220f1b3),
+        Instruction::REMU { .. }
+    ));
+    // add and sub are unaffected
+    assert!(matches!(
+        interpret_bytes(0x002081b3),
+        Instruction::ADD { .. }
+    ));
+    assert!(matches!(
+        interpret_bytes(0x402081b3),
+        Instruction::SUB { .. }
+    ));
+}
+
 #[test]
 fn test_shift_right_logical() {
     let mut state = ArchState::new();
@@ -39,7 +165,7 @@ fn test_shift_right_logical() {
         rs2: 3,
     };
     let inst = Instruction::SRL { data };
-    state.apply(&inst);
+    state.apply(&inst).unwrap();
     println!(
         "rs1: {:#034b}, rs2:      {:#034b}",
         state.get_register(2),
@@ -64,7 +190,7 @@ fn test_shift_right_arithmetic() {
         rs2: 3,
     };
     let inst = Instruction::SRA { data };
-    state.apply(&inst);
+    state.apply(&inst).unwrap();
     println!(
         "rs1: {:#034b}, rs2:      {:#034b}",
         state.get_register(2),
@@ -78,6 +204,32 @@ fn test_shift_right_arithmetic() {
     assert_eq!(2_u32.pow(30) + 2_u32.pow(31), state.get_register(1));
 }
 
+#[test]
+fn test_shift_amount_masked() {
+    let mut state = ArchState::new();
+    state.set_register(2, 0x80000001);
+    // only rs2[4:0] is the shift amount, so 33 shifts by 1
+    state.set_register(3, 33);
+    let data = R {
+        rd: 1,
+        rs1: 2,
+        rs2: 3,
+    };
+
+    for (inst, expected) in [
+        (Instruction::SLL { data }, 0x00000002),
+        (Instruction::SRL { data }, 0x40000000),
+        (Instruction::SRA { data }, 0xC0000000),
+    ] {
+        state.apply(&inst).unwrap();
+        assert_eq!(state.get_register(1), expected, "{}", inst);
+    }
+
+    state.set_register(3, 32);
+    state.apply(&Instruction::SLL { data }).unwrap();
+    assert_eq!(state.get_register(1), 0x80000001);
+}
+
 #[test]
 fn test_comparison() {
     let mut state = ArchState::new();
@@ -90,11 +242,11 @@ fn test_comparison() {
     };
     // signed
     let inst = Instruction::SLT { data };
-    state.apply(&inst);
+    state.apply(&inst).unwrap();
     assert_eq!(1, state.get_register(1));
     // unsigned
     let inst = Instruction::SLTU { data };
-    state.apply(&inst);
+    state.apply(&inst).unwrap();
     assert_eq!(1, state.get_register(1));
 }
 
@@ -105,23 +257,103 @@ fn test_immediate_arithmetic() {
         rs1: 2,
         imm: SmallImmediate::from(1),
     };
-    for (inst, expected) in vec![
-        (Instruction::ADDI { data: data.clone() }, 2),
-        (Instruction::XORI { data: data.clone() }, 0),
-        (Instruction::ORI { data: data.clone() }, 1),
-        (Instruction::ANDI { data: data.clone() }, 1),
-        (Instruction::SLLI { data: data.clone() }, 2),
-        (Instruction::SRLI { data: data.clone() }, 0),
-        (Instruction::SRAI { data: data.clone() }, 0),
+    for (inst, expected) in [
+        (Instruction::ADDI { data }, 2),
+        (Instruction::XORI { data }, 0),
+        (Instruction::ORI { data }, 1),
+        (Instruction::ANDI { data }, 1),
+        (Instruction::SLLI { data }, 2),
+        (Instruction::SRLI { data }, 0),
+        (Instruction::SRAI { data }, 0),
     ] {
         let mut state = ArchState::new();
         state.set_register(2, 1);
-        state.apply(&inst);
+        state.apply(&inst).unwrap();
         println!("Test {:?}", &inst);
         assert_eq!(expected, state.get_register(1));
     }
 }
 
+#[test]
+#[allow(clippy::unusual_byte_groupings)] // grouped by encoding field
+fn test_shift_immediates() {
+    let mut state = ArchState::new();
+    state.set_register(2, 0x80000000);
+
+    state
+        .apply(&Instruction::SRLI {
+            data: I {
+                rd: 1,
+                rs1: 2,
+                imm: SmallImmediate::from(4),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.get_register(1), 0x08000000);
+
+    // imm[10] is set for SRAI, only imm[4:0] is the shift amount
+    state
+        .apply(&Instruction::SRAI {
+            data: I {
+                rd: 1,
+                rs1: 2,
+                imm: SmallImmediate::from(0b0100000_00100),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.get_register(1), 0xF8000000);
+}
+
+#[test]
+fn test_decoded_shift_immediates() {
+    let mut state = ArchState::new();
+    state.set_register(2, 0x80000000);
+
+    // srli x1, x2, 4
+    state.apply_raw(0x00415093).unwrap();
+    assert_eq!(state.get_register(1), 0x08000000);
+
+    // srai x1, x2, 4 keeps imm[10] set through decode
+    state.apply_raw(0x40415093).unwrap();
+    assert_eq!(state.get_register(1), 0xF8000000);
+
+    // srai x1, x2, 31 fills every bit
+    state.apply_raw(0x41f15093).unwrap();
+    assert_eq!(state.get_register(1), 0xFFFFFFFF);
+}
+
+#[test]
+fn test_signed_comparison_boundary() {
+    let slt = |data| Instruction::SLT { data };
+    let sltu = |data| Instruction::SLTU { data };
+    // 0x80000000 is the most negative value signed and the bigger one unsigned
+    assert_eq!(apply_r(slt, 0x80000000, 0x7FFFFFFF), 1);
+    assert_eq!(apply_r(slt, 0x7FFFFFFF, 0x80000000), 0);
+    assert_eq!(apply_r(sltu, 0x80000000, 0x7FFFFFFF), 0);
+    assert_eq!(apply_r(sltu, 0x7FFFFFFF, 0x80000000), 1);
+
+    let mut state = ArchState::with_mem(4);
+    state.set_register(1, 0x80000000);
+    state.set_register(2, 0x7FFFFFFF);
+    let data = B {
+        rs1: 1,
+        rs2: 2,
+        imm: BranchImmediate::from(8),
+    };
+    state.apply(&Instruction::BLT { data }).unwrap();
+    assert_eq!(state.pc, 8);
+    state.apply(&Instruction::BLTU { data }).unwrap();
+    assert_eq!(state.pc, 12);
+    state.apply(&Instruction::BGE { data }).unwrap();
+    assert_eq!(state.pc, 16);
+    state.apply(&Instruction::BGEU { data }).unwrap();
+    assert_eq!(state.pc, 24);
+
+    // slti x3, x1, 0x7ff
+    state.apply_raw(0x7ff0a193).unwrap();
+    assert_eq!(state.get_register(3), 1);
+}
+
 #[test]
 fn test_comparison_immediate() {
     let mut state = ArchState::new();
@@ -133,79 +365,87 @@ fn test_comparison_immediate() {
     };
     // signed
     let inst = Instruction::SLTI { data };
-    state.apply(&inst);
+    state.apply(&inst).unwrap();
     assert_eq!(1, state.get_register(1));
     // unsigned
     let inst = Instruction::SLTUI { data };
-    state.apply(&inst);
+    state.apply(&inst).unwrap();
     assert_eq!(1, state.get_register(1));
 }
 
 #[test]
 fn test_loads() {
     let mut state = ArchState::new();
-    state.mem.insert(0, 1);
-    state.mem.insert(1, 2);
-    state.mem.insert(2, 4);
-    state.mem.insert(3, 8);
-    state.mem.insert(4, 16);
+    state.mem.write(0, &[1, 2, 4, 8, 16]);
 
     // byte
-    state.apply(&Instruction::LB {
-        data: I {
-            rd: 1,
-            rs1: 0,
-            imm: SmallImmediate::from(0),
-        },
-    });
+    state
+        .apply(&Instruction::LB {
+            data: I {
+                rd: 1,
+                rs1: 0,
+                imm: SmallImmediate::from(0),
+            },
+        })
+        .unwrap();
     assert_eq!(state.get_register(1), 1);
     // test offset
-    state.apply(&Instruction::LB {
-        data: I {
-            rd: 1,
-            rs1: 0,
-            imm: SmallImmediate::from(1),
-        },
-    });
+    state
+        .apply(&Instruction::LB {
+            data: I {
+                rd: 1,
+                rs1: 0,
+                imm: SmallImmediate::from(1),
+            },
+        })
+        .unwrap();
     assert_eq!(state.get_register(1), 2);
 
     // half
-    state.apply(&Instruction::LH {
-        data: I {
-            rd: 1,
-            rs1: 0,
-            imm: SmallImmediate::from(0),
-        },
-    });
-    assert_eq!(state.get_register(1), 258);
+    state
+        .apply(&Instruction::LH {
+            data: I {
+                rd: 1,
+                rs1: 0,
+                imm: SmallImmediate::from(0),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.get_register(1), 0x0201);
     // test offset
-    state.apply(&Instruction::LH {
-        data: I {
-            rd: 1,
-            rs1: 0,
-            imm: SmallImmediate::from(1),
-        },
-    });
-    assert_eq!(state.get_register(1), 258 << 1);
+    state
+        .apply(&Instruction::LH {
+            data: I {
+                rd: 1,
+                rs1: 0,
+                imm: SmallImmediate::from(1),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.get_register(1), 0x0402);
 
     // word
-    state.apply(&Instruction::LW {
-        data: I {
-            rd: 1,
-            rs1: 0,
-            imm: SmallImmediate::from(0),
-        },
-    });
-    assert_eq!(state.get_register(1), 16909320);
+    state
+        .apply(&Instruction::LW {
+            data: I {
+                rd: 1,
+                rs1: 0,
+                imm: SmallImmediate::from(0),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.get_register(1), 0x08040201);
     // test offset
-    state.apply(&Instruction::LW {
-        data: I {
-            rd: 1,
-            rs1: 0,
-            imm: SmallImmediate::from(1),
-        },
-    });
-    assert_eq!(state.get_register(1), 16909320 << 1);
+    state
+        .apply(&Instruction::LW {
+            data: I {
+                rd: 1,
+                rs1: 0,
+                imm: SmallImmediate::from(1),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.get_register(1), 0x10080402);
 }
 
 #[test]
@@ -215,79 +455,117 @@ fn test_stores() {
     state.set_register(1, 1 + (2 << 8) + (4 << 16) + (8 << 24));
     println!("register 1: {:b}", state.get_register(1));
 
-    state.apply(&Instruction::SB {
-        data: S {
-            imm: SmallImmediate::from(0),
-            rs1: 0,
-            rs2: 1,
-        },
-    });
+    state
+        .apply(&Instruction::SB {
+            data: S {
+                imm: StoreImmediate::from(0),
+                rs1: 0,
+                rs2: 1,
+            },
+        })
+        .unwrap();
     assert_eq!(state.mem[0], 1);
     state.mem[0] = 0;
 
-    state.apply(&Instruction::SH {
-        data: S {
-            imm: SmallImmediate::from(0),
-            rs1: 0,
-            rs2: 1,
-        },
-    });
+    state
+        .apply(&Instruction::SH {
+            data: S {
+                imm: StoreImmediate::from(0),
+                rs1: 0,
+                rs2: 1,
+            },
+        })
+        .unwrap();
     println!("{} {}", (state.mem[0] as u32), state.mem[1]);
-    assert_eq!(
-        ((state.mem[0] as u32) << 8) + state.mem[1] as u32,
-        1_u32 + (2 << 8)
-    );
+    // least significant byte first
+    assert_eq!(state.mem.read(0, 2).unwrap(), [1, 2]);
     state.mem[0] = 0;
     state.mem[1] = 0;
 
-    state.apply(&Instruction::SW {
-        data: S {
-            imm: SmallImmediate::from(0),
-            rs1: 0,
-            rs2: 1,
-        },
-    });
+    state
+        .apply(&Instruction::SW {
+            data: S {
+                imm: StoreImmediate::from(0),
+                rs1: 0,
+                rs2: 1,
+            },
+        })
+        .unwrap();
     println!("{} {}", (state.mem[0] as u32), state.mem[1]);
-    assert_eq!(
-        ((state.mem[0] as u32) << 24)
-            + ((state.mem[1] as u32) << 16)
-            + ((state.mem[2] as u32) << 8)
-            + state.mem[3] as u32,
-        1 + (2 << 8) + (4 << 16) + (8 << 24)
-    );
+    assert_eq!(state.mem.read(0, 4).unwrap(), [1, 2, 4, 8]);
+}
+
+#[test]
+fn test_last_written() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.set_register(1, 0x08040201);
+    // sb x1, 0x10(x0)
+    state.apply_raw(0x00100823).unwrap();
+    assert_eq!(state.last_written, [0x10]);
+    // sh x1, 0x20(x0)
+    state.apply_raw(0x02101023).unwrap();
+    assert_eq!(state.last_written, [0x20, 0x21]);
+    // sw x1, 0x40(x0)
+    state.apply_raw(0x04102023).unwrap();
+    assert_eq!(state.last_written, [0x40, 0x41, 0x42, 0x43]);
+    // rewriting the same value still counts as a write
+    state.apply_raw(0x04102023).unwrap();
+    assert_eq!(state.last_written.len(), 4);
+
+    state.apply(&Instruction::nop()).unwrap();
+    assert!(state.last_written.is_empty());
+}
+
+#[test]
+fn test_store_immediate_decode() {
+    // sw x2, -8(x1): imm[11:5] = 0x7F, imm[4:0] = 0x18
+    let sw = (0x7F << 25) | (2 << 20) | (1 << 15) | (0b010 << 12) | (0x18 << 7) | 0b0100011;
+    match interpret_bytes(sw) {
+        Instruction::SW { data } => {
+            assert_eq!(data.rs1, 1);
+            assert_eq!(data.rs2, 2);
+            assert_eq!(data.imm.sign_extend(), -8);
+        }
+        inst => panic!("decoded {:?}", inst),
+    }
+
+    // sb x3, 0x7e5(x4) exercises both halves of a positive immediate
+    let sb = (0x3F << 25) | (3 << 20) | (4 << 15) | (0x05 << 7) | 0b0100011;
+    match interpret_bytes(sb) {
+        Instruction::SB { data } => assert_eq!(data.imm.sign_extend(), 0x7e5),
+        inst => panic!("decoded {:?}", inst),
+    }
 }
 
 #[test]
 fn test_load_signs() {
-    let mut state = ArchState::new();
-    // byte loads
-    state.mem[0] = 0b10000000;
-    let test = I {
-        imm: SmallImmediate::from(0),
-        rs1: 1,
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // little-endian, so the half at 0 is 0xff80 and the half at 2 is 0x127f
+    state.load(vec![0x80, 0xff, 0x7f, 0x12], 0);
+    let at = |offset| I {
+        imm: SmallImmediate::from(offset),
+        rs1: 0,
         rd: 4,
     };
-    // unsigned load will 0 pad
-    state.apply(&Instruction::LBU { data: test });
-    println!("unsigned byte: {:b}", state.get_register(4));
-    assert_eq!(state.get_register(4), 128);
-    // signed will sign extend
-    state.apply(&Instruction::LB { data: test });
-    println!("signed byte: {:b}", state.get_register(4));
-    assert_eq!(transmute_to_signed(state.get_register(4)), -128);
-
-    // half loads
-    let val = 1_u32 << 15;
-    state.mem[0] = (val >> 8) as u8;
-    state.mem[1] = val as u8;
-    // unsigned load will 0 pad
-    state.apply(&Instruction::LHU { data: test });
-    println!("unsigned half: {:b}", state.get_register(4));
-    assert_eq!(state.get_register(4), 1 << 15);
-    // signed will sign extend
-    state.apply(&Instruction::LH { data: test });
-    println!("signed half: {:b}", state.get_register(4));
-    assert_eq!(transmute_to_signed(state.get_register(4)), -(1_i32 << 15));
+    let mut load = |inst| {
+        state.apply(&inst).unwrap();
+        state.get_register(4)
+    };
+
+    // unsigned loads zero extend
+    assert_eq!(load(Instruction::LBU { data: at(0) }), 0x80);
+    assert_eq!(load(Instruction::LHU { data: at(0) }), 0xff80);
+    // signed loads copy the top bit of what they read
+    assert_eq!(load(Instruction::LB { data: at(0) }), 0xffffff80);
+    assert_eq!(load(Instruction::LH { data: at(0) }), 0xffffff80);
+    assert_eq!(load(Instruction::LB { data: at(1) }), 0xffffffff);
+    assert_eq!(load(Instruction::LB { data: at(2) }), 0x7f);
+    assert_eq!(load(Instruction::LH { data: at(2) }), 0x127f);
+    assert_eq!(load(Instruction::LHU { data: at(1) }), 0x7fff);
+    assert_eq!(
+        transmute_to_signed(load(Instruction::LH { data: at(0) })),
+        -128
+    );
 }
 
 #[test]
@@ -298,50 +576,97 @@ fn test_conditional_jumps() {
     let test = B {
         rs1: 1,
         rs2: 2,
-        imm: SmallImmediate::from(4),
+        imm: BranchImmediate::from(8),
     };
 
-    state.apply(&Instruction::BEQ { data: test });
+    state.apply(&Instruction::BEQ { data: test }).unwrap();
     assert_eq!(state.pc, 8);
     state.set_register(2, 0);
-    state.apply(&Instruction::BEQ { data: test });
+    state.apply(&Instruction::BEQ { data: test }).unwrap();
     assert_eq!(state.pc, 12);
 
-    state.apply(&Instruction::BNE { data: test });
+    state.apply(&Instruction::BNE { data: test }).unwrap();
     assert_eq!(state.pc, 20);
     state.set_register(2, 1);
-    state.apply(&Instruction::BNE { data: test });
+    state.apply(&Instruction::BNE { data: test }).unwrap();
     assert_eq!(state.pc, 24);
 
-    state.apply(&Instruction::BLT { data: test });
+    state.apply(&Instruction::BLT { data: test }).unwrap();
     assert_eq!(state.pc, 28);
     state.set_register(2, 2);
-    state.apply(&Instruction::BLT { data: test });
+    state.apply(&Instruction::BLT { data: test }).unwrap();
     assert_eq!(state.pc, 36);
 
-    state.apply(&Instruction::BGE { data: test });
+    state.apply(&Instruction::BGE { data: test }).unwrap();
     assert_eq!(state.pc, 40);
     state.set_register(2, 1);
-    state.apply(&Instruction::BGE { data: test });
+    state.apply(&Instruction::BGE { data: test }).unwrap();
     assert_eq!(state.pc, 48);
     state.set_register(2, 0);
-    state.apply(&Instruction::BGE { data: test });
+    state.apply(&Instruction::BGE { data: test }).unwrap();
     assert_eq!(state.pc, 56);
 
-    state.apply(&Instruction::BLTU { data: test });
+    state.apply(&Instruction::BLTU { data: test }).unwrap();
     assert_eq!(state.pc, 28 + 32);
     state.set_register(2, 2);
-    state.apply(&Instruction::BLTU { data: test });
+    state.apply(&Instruction::BLTU { data: test }).unwrap();
     assert_eq!(state.pc, 36 + 32);
 
-    state.apply(&Instruction::BGEU { data: test });
+    state.apply(&Instruction::BGEU { data: test }).unwrap();
     assert_eq!(state.pc, 40 + 32);
     state.set_register(2, 1);
-    state.apply(&Instruction::BGEU { data: test });
+    state.apply(&Instruction::BGEU { data: test }).unwrap();
     assert_eq!(state.pc, 48 + 32);
     state.set_register(2, 0);
-    state.apply(&Instruction::BGEU { data: test });
+    state.apply(&Instruction::BGEU { data: test }).unwrap();
     assert_eq!(state.pc, 56 + 32);
+
+    // backward branches
+    let back = B {
+        rs1: 1,
+        rs2: 2,
+        imm: BranchImmediate::from(0x2000 - 24),
+    };
+    state.pc = 0x100;
+    state.apply(&Instruction::BNE { data: back }).unwrap();
+    assert_eq!(state.pc, 0x100 - 24);
+    state.apply(&Instruction::BEQ { data: back }).unwrap();
+    assert_eq!(state.pc, 0x100 - 20);
+    state.apply(&Instruction::BLTU { data: back }).unwrap();
+    assert_eq!(state.pc, 0x100 - 16);
+    state.apply(&Instruction::BGEU { data: back }).unwrap();
+    assert_eq!(state.pc, 0x100 - 40);
+
+    // decoded from an assembler: bge x5, x6, -8
+    state.pc = 0x100;
+    state.set_register(5, 2);
+    state.set_register(6, 2);
+    state.apply(&interpret_bytes(0xfe62dce3)).unwrap();
+    assert_eq!(state.pc, 0xf8);
+}
+
+#[test]
+fn test_branch_immediate_decode() {
+    // (encoding from an assembler, pc, expected target)
+    for (bytes, pc, target) in [
+        (0x00208863, 0x100, 0x110),   // beq x1, x2, +16
+        (0xfe62dce3, 0x100, 0xf8),    // bge x5, x6, -8
+        (0x000010e3, 0x100, 0x900),   // bne x0, x0, +2048 sets imm[11]
+        (0x80000063, 0x2000, 0x1000), // beq x0, x0, -4096 sets imm[12]
+        (0x7e000fe3, 0x0, 0xffe),     // beq x0, x0, +4094 sets every other bit
+    ] {
+        let (Instruction::BEQ { data } | Instruction::BNE { data } | Instruction::BGE { data }) =
+            interpret_bytes(bytes)
+        else {
+            panic!("{:08x} did not decode to a branch", bytes);
+        };
+        assert_eq!(pc + data.imm.sign_extend(), target, "{:08x}", bytes);
+    }
+
+    let Instruction::BGE { data } = interpret_bytes(0xfe62dce3) else {
+        panic!("bge did not decode");
+    };
+    assert_eq!((data.rs1, data.rs2), (5, 6));
 }
 
 #[test]
@@ -349,24 +674,119 @@ fn test_unconditional_jumps() {
     let mut state = ArchState::new();
     state.set_register(1, 1);
 
-    state.apply(&Instruction::JAL {
-        data: J {
-            rd: 1,
-            imm: BigImmediate::from(8),
-        },
-    });
+    state
+        .apply(&Instruction::JAL {
+            data: J {
+                rd: 1,
+                imm: JumpImmediate::from(16),
+            },
+        })
+        .unwrap();
     assert_eq!(state.pc, 16);
     assert_eq!(state.get_register(1), 4);
 
-    state.apply(&Instruction::JALR {
-        data: I {
-            rd: 1,
-            rs1: 0,
-            imm: SmallImmediate::from(8),
-        },
-    });
+    state
+        .apply(&Instruction::JALR {
+            data: I {
+                rd: 1,
+                rs1: 0,
+                imm: SmallImmediate::from(8),
+            },
+        })
+        .unwrap();
     assert_eq!(state.pc, 8);
     assert_eq!(state.get_register(1), 20);
+
+    // the low bit of the target is cleared, even from a misaligned base
+    state.set_register(2, 0x1001);
+    state
+        .apply(&Instruction::JALR {
+            data: I {
+                rd: 1,
+                rs1: 2,
+                imm: SmallImmediate::from(4),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.pc, 0x1004);
+    assert_eq!(state.get_register(1), 12);
+
+    // backward jumps cover the full 32-bit range
+    state.set_register(2, 0x8000_0000);
+    state
+        .apply(&Instruction::JALR {
+            data: I {
+                rd: 1,
+                rs1: 2,
+                imm: SmallImmediate::from(0xFFF),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.pc, 0x7FFF_FFFE);
+
+    // decoded jal x0, -8
+    let jal = interpret_bytes(0xff9ff06f);
+    state.pc = 0x100;
+    state.apply(&jal).unwrap();
+    assert_eq!(state.pc, 0xF8);
+}
+
+#[test]
+fn test_jump_link_address() {
+    let mut state = ArchState::new();
+    state.pc = 0x1000;
+    state.set_register(5, 0x2000);
+
+    state
+        .apply(&Instruction::JALR {
+            data: I {
+                rd: 1,
+                rs1: 5,
+                imm: SmallImmediate::from(0),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.pc, 0x2000);
+    assert_eq!(state.get_register(1), 0x1004);
+
+    // linking into the base register must not change the target
+    state.pc = 0x1000;
+    state.set_register(1, 0x3000);
+    state
+        .apply(&Instruction::JALR {
+            data: I {
+                rd: 1,
+                rs1: 1,
+                imm: SmallImmediate::from(0),
+            },
+        })
+        .unwrap();
+    assert_eq!(state.pc, 0x3000);
+    assert_eq!(state.get_register(1), 0x1004);
+}
+
+#[test]
+fn test_decode_jalr_registers() {
+    // jalr x1, -1(x31), the sign bit of the immediate sits right above rs1
+    let Some(Instruction::JALR { data }) = try_interpret_bytes(0xffff80e7) else {
+        panic!("expected jalr");
+    };
+    assert_eq!((data.rd, data.rs1), (1, 31));
+    assert_eq!(data.imm.sign_extend(), -1);
+
+    // jalr x31, 0(x1), rd reads up to the func3 bits above it
+    let Some(Instruction::JALR { data }) = try_interpret_bytes(0x00008fe7) else {
+        panic!("expected jalr");
+    };
+    assert_eq!((data.rd, data.rs1), (31, 1));
+
+    let mut state = ArchState::new();
+    state.set_register(31, 0x100);
+    state.apply_raw(0xffff80e7).unwrap();
+    assert_eq!((state.pc, state.get_register(1)), (0xfe, 4));
+
+    // other func3 values are reserved
+    assert!(try_interpret_bytes(0xffff90e7).is_none());
 }
 
 #[test]
@@ -378,9 +798,392 @@ fn test_lui_auipc() {
         imm: BigImmediate::from(1 << 19),
     };
 
-    state.apply(&Instruction::LUI { data: test });
+    state.apply(&Instruction::LUI { data: test }).unwrap();
     assert_eq!(state.get_register(1), 2_u32.pow(31));
 
-    state.apply(&Instruction::AUIPC { data: test });
+    state.apply(&Instruction::AUIPC { data: test }).unwrap();
     assert_eq!(state.get_register(1), 2_u32.pow(31) + 4);
 }
+
+#[test]
+fn test_lui_auipc_decoded() {
+    let mut state = ArchState::new();
+    state.pc = 0x100;
+
+    // lui x1, 0xabcde keeps the immediate unshifted until it is applied
+    let lui = state.apply_raw(0xabcde0b7).unwrap();
+    assert!(matches!(lui, Instruction::LUI { data } if data.imm.val == 0xabcde));
+    assert_eq!(state.get_register(1), 0xabcde000);
+
+    // auipc x2, 0xabcde at 0x104
+    state.apply_raw(0xabcde117).unwrap();
+    assert_eq!(state.get_register(2), 0xabcde104);
+}
+
+#[test]
+fn test_auipc_pc_base() {
+    // auipc x1, 0x1
+    const AUIPC: u32 = 0x00001097;
+    for (pc, expected) in [
+        (0, 0x1000),
+        (0x4, 0x1004),
+        (0x7ffffffc, 0x80000ffc),
+        // the sum wraps around the address space
+        (0xfffff000, 0),
+        (0xfffffffc, 0xffc),
+    ] {
+        let mut state = ArchState::new();
+        state.pc = pc;
+        state.apply_raw(AUIPC).unwrap();
+        assert_eq!(state.get_register(1), expected, "auipc at 0x{:08x}", pc);
+        assert_eq!(state.pc, pc + 4);
+    }
+
+    // the compressed length doesn't change the base
+    let mut state = ArchState::with_mem(0x20);
+    state.load(vec![0x01, 0x00], 0); // c.nop
+    state.load(AUIPC.to_le_bytes().to_vec(), 2);
+    state.tick().unwrap();
+    state.tick().unwrap();
+    assert_eq!(state.get_register(1), 0x1002);
+
+    // jal x1, 8 links past the top of the address space
+    let mut state = ArchState::new();
+    state.pc = 0xfffffffc;
+    state.apply_raw(0x008000ef).unwrap();
+    assert_eq!(state.get_register(1), 0);
+}
+
+#[test]
+fn test_apply_raw() {
+    let mut state = ArchState::new();
+    // addi x1, x0, 7
+    let inst = state.apply_raw(0x00700093).unwrap();
+    assert!(matches!(inst, Instruction::ADDI { .. }));
+    assert_eq!(state.get_register(1), 7);
+    assert_eq!(state.pc, 4);
+
+    assert_eq!(
+        state.apply_raw(0).unwrap_err(),
+        VmError::IllegalInstruction(0)
+    );
+    assert_eq!(state.pc, 4);
+}
+
+#[test]
+fn test_misaligned_load() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(vec![0x11, 0x22, 0x33, 0x44, 0x55], 0x10);
+    state.set_register(1, 0x11);
+    // lw x2, 0(x1)
+    let lw = 0x0000a103;
+
+    // permissive by default, the word is put together from the bytes
+    state.apply_raw(lw).unwrap();
+    assert_eq!(state.get_register(2), 0x55443322);
+
+    state.strict_alignment = true;
+    state.set_register(2, 0);
+    assert_eq!(
+        state.apply_raw(lw).unwrap_err(),
+        VmError::Misaligned {
+            addr: 0x11,
+            width: 4
+        }
+    );
+    assert_eq!(state.get_register(2), 0);
+    assert_eq!(state.pc, 4);
+}
+
+#[test]
+fn test_out_of_bounds_access() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.set_register(1, 0x8000_0000);
+    // lw x2, 0(x1)
+    assert_eq!(
+        state.apply_raw(0x0000a103).unwrap_err(),
+        VmError::OutOfBounds { addr: 0x8000_0000 }
+    );
+    // sw x1, 254(x0) would write two bytes past the end
+    assert_eq!(
+        state.apply_raw(0x0e102f23).unwrap_err(),
+        VmError::OutOfBounds { addr: 0xfe }
+    );
+    assert_eq!(state.pc, 0);
+}
+
+#[test]
+fn test_strict_x0() {
+    let mut state = ArchState::new();
+    // addi x0, x0, 5
+    state.apply_raw(0x00500013).unwrap();
+    assert_eq!(state.last_event, None, "off by default");
+
+    state.strict_x0 = true;
+    state.apply_raw(0x00500013).unwrap();
+    assert_eq!(state.get_register(0), 0);
+    assert_eq!(
+        state.last_event,
+        Some(VmEvent::IgnoredX0Write { pc: 4, val: 5 })
+    );
+
+    // jal x0, 8 is a plain jump
+    state.apply_raw(0x0080006f).unwrap();
+    assert_eq!(state.last_event, None);
+}
+
+#[test]
+fn test_get_instruction() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // addi x1, x0, 7 at 0x10
+    state.load(0x00700093_u32.to_le_bytes().to_vec(), 0x10);
+    state.pc = 0x10;
+    assert!(matches!(
+        state.get_instruction(),
+        Ok(Instruction::ADDI { .. })
+    ));
+    // looking doesn't run it
+    assert_eq!(state.get_register(1), 0);
+    assert_eq!(state.pc, 0x10);
+
+    // a compressed instruction would still fit at 0xFE
+    state.pc = 0x100;
+    assert_eq!(
+        state.get_instruction().unwrap_err(),
+        VmError::FetchOutOfBounds(0x100)
+    );
+}
+
+#[test]
+fn test_ebreak() {
+    const EBREAK: u32 = 0x00100073;
+
+    // under the debugger execution pauses after the break
+    let mut state = ArchState::new();
+    state.interactive = true;
+    assert!(matches!(
+        state.apply_raw(EBREAK),
+        Ok(Instruction::EBREAK { .. })
+    ));
+    assert!(state.paused);
+    assert!(!state.halted);
+    assert_eq!(state.pc, 4);
+
+    // batch runs halt
+    let mut state = ArchState::new();
+    state.apply_raw(EBREAK).unwrap();
+    assert!(!state.paused);
+    assert!(state.halted);
+    assert!(state.tick().is_err());
+}
+
+#[test]
+fn test_fence() {
+    // fence rw, w
+    let fence = 0x0310000f;
+    assert!(matches!(
+        try_interpret_bytes(fence),
+        Some(Instruction::FENCE { .. })
+    ));
+    assert!(matches!(
+        try_interpret_bytes(0x0000100f),
+        Some(Instruction::FENCEI { .. })
+    ));
+    assert!(try_interpret_bytes(0x0000200f).is_none());
+
+    let mut state = ArchState::new();
+    let before = state.regs;
+    let inst = state.apply_raw(fence).unwrap();
+    assert_eq!(state.pc, 4);
+    assert_eq!(state.regs, before);
+    assert!(inst.to_string().starts_with("FENCE "));
+}
+
+#[test]
+fn test_register_abi_name() {
+    assert_eq!(register_abi_name(0), "zero");
+    assert_eq!(register_abi_name(1), "ra");
+    assert_eq!(register_abi_name(2), "sp");
+    assert_eq!(register_abi_name(8), "s0");
+    assert_eq!(register_abi_name(10), "a0");
+    assert_eq!(register_abi_name(17), "a7");
+    assert_eq!(register_abi_name(31), "t6");
+    assert_eq!(register_abi_name(32), "?");
+
+    let inst = try_interpret_bytes(0x00a58023).unwrap();
+    assert!(inst.to_string().starts_with("SB rs1: x11/a1 | rs2: x10/a0"));
+}
+
+#[test]
+fn test_nop_and_hints() {
+    let nop = try_interpret_bytes(0x00000013).unwrap();
+    assert!(nop.is_nop());
+    assert_eq!(nop.to_string(), "NOP");
+    assert_eq!(assembly(&nop, 0), "nop");
+
+    // addi x0, x0, 5 is a hint, it shows as an addi but changes nothing either
+    let mut state = ArchState::new();
+    let hint = state.apply_raw(0x00500013).unwrap();
+    assert!(!hint.is_nop());
+    assert!(hint.to_string().starts_with("ADDI"));
+    assert_eq!(state.get_register(0), 0);
+    assert_eq!(state.pc, 4);
+    assert!(state.last_event.is_none());
+    // so does a register op writing x0
+    state.set_register(1, 3);
+    state.apply_raw(0x00108033).unwrap(); // add x0, x1, x1
+    assert_eq!(state.get_register(0), 0);
+    assert_eq!(state.pc, 8);
+}
+
+#[test]
+fn test_illegal_instruction() {
+    // opcode 0b1111111 isn't assigned to anything
+    let illegal = interpret_bytes(0xdeadbeff);
+    assert_eq!(illegal, Instruction::ILLEGAL { raw: 0xdeadbeff });
+    assert!(!illegal.is_nop());
+    assert_eq!(illegal.to_string(), "illegal 0xdeadbeff");
+    assert_eq!(illegal.encode(), 0xdeadbeff);
+    assert!(illegal.info().is_none());
+    assert!(interpret_bytes(0x00000013).is_nop());
+
+    let mut state = ArchState::new();
+    assert_eq!(
+        state.apply(&illegal),
+        Err(VmError::IllegalInstruction(0xdeadbeff))
+    );
+    assert_eq!(state.pc, 0);
+
+    // the stepper shows the word it is stuck on instead of a nop
+    state.load(0xdeadbeff_u32.to_le_bytes().to_vec(), 0);
+    assert_eq!(state.get_instruction(), Ok(illegal));
+    assert_eq!(state.tick(), Err(VmError::IllegalInstruction(0xdeadbeff)));
+
+    // reserved funct7 values of the register ops and shifts don't fall back to a neighbour
+    for word in [
+        0x04000033_u32, // add with funct7 = 2
+        0x40004033,     // xor with bit 30 set
+        0x40001033,     // sll with bit 30 set
+        0x02001013,     // slli with shamt[5] set
+        0x42005013,     // srai with shamt[5] set
+        0x40001013,     // slli with bit 30 set
+    ] {
+        assert_eq!(
+            interpret_bytes(word),
+            Instruction::ILLEGAL { raw: word },
+            "0x{:08x}",
+            word
+        );
+    }
+}
+
+#[test]
+fn test_all_opcodes() {
+    // one entry per `Instruction` variant but ILLEGAL, bump this when adding one
+    const VARIANTS: usize = 67;
+    let opcodes = all_opcodes();
+    assert_eq!(opcodes.len(), VARIANTS);
+
+    let mut seen = std::collections::HashSet::new();
+    for info in opcodes {
+        let inst = try_interpret_bytes(info.encoding())
+            .unwrap_or_else(|| panic!("{} does not decode", info.mnemonic));
+        // the bare ADDI encoding is the canonical nop
+        let expected = if inst.is_nop() { "NOP" } else { info.mnemonic };
+        let inst = inst.to_string();
+        assert_eq!(inst.split_whitespace().next(), Some(expected));
+        assert_eq!(
+            try_interpret_bytes(info.encoding())
+                .and_then(|inst| inst.info())
+                .map(|found| found.mnemonic),
+            Some(info.mnemonic)
+        );
+        assert!(seen.insert(info.mnemonic), "{} listed twice", info.mnemonic);
+    }
+}
+
+#[test]
+fn test_immediate_round_trip() {
+    // bge x5, x6, -8 scatters imm[12|10:5] into bits 31:25 and imm[4:1|11] into bits 11:7
+    let bge = 0xfe62dce3_u32;
+    let imm = BranchImmediate::decode(bge);
+    assert_eq!(imm.sign_extend(), -8);
+    assert_eq!(imm.encode(), bge & 0xFE000F80);
+
+    // every even offset lands back on itself
+    for val in (0..1 << 13).step_by(2) {
+        let imm = BranchImmediate::from(val);
+        assert_eq!(BranchImmediate::decode(imm.encode()).val, val);
+    }
+
+    // jal x0, -8
+    let jal = 0xff9ff06f_u32;
+    assert_eq!(JumpImmediate::decode(jal).encode(), jal & 0xFFFFF000);
+    // sw x2, 4(x1)
+    let sw = 0x0020a223_u32;
+    assert_eq!(StoreImmediate::decode(sw).encode(), sw & 0xFE000F80);
+    // addi x1, x0, -1
+    let addi = 0xfff00093_u32;
+    assert_eq!(SmallImmediate::decode(addi).encode(), addi & 0xFFF00000);
+}
+
+#[test]
+fn test_instruction_new() {
+    let info = |name| {
+        all_opcodes()
+            .iter()
+            .find(|info| info.mnemonic == name)
+            .unwrap()
+    };
+    assert_eq!(
+        Instruction::new(info("ADDI"), Operands::I(I::new(1, 2, 0xfff))),
+        Some(Instruction::ADDI {
+            data: I {
+                rd: 1,
+                rs1: 2,
+                imm: SmallImmediate::from(0xfff)
+            }
+        })
+    );
+    assert_eq!(
+        Instruction::new(info("SW"), Operands::S(S::new(1, 2, 4))).map(|inst| inst.encode()),
+        Some(0x0020a223)
+    );
+    assert_eq!(
+        Instruction::new(info("AMOSWAPW"), Operands::A(A::new(5, 10, 6, true, true)))
+            .map(|inst| inst.encode()),
+        Some(0x0e6522af)
+    );
+    // fields of the wrong format, a shift amount past 31, and ordering bits on a plain R op
+    assert_eq!(
+        Instruction::new(info("ADD"), Operands::I(I::new(1, 2, 3))),
+        None
+    );
+    assert_eq!(
+        Instruction::new(info("SLLI"), Operands::I(I::new(1, 2, 32))),
+        None
+    );
+    assert_eq!(
+        Instruction::new(info("ADD"), Operands::A(A::new(1, 2, 3, true, false))),
+        None
+    );
+}
+
+#[test]
+fn test_encode_round_trip() {
+    // a fixed xorshift walk over the whole word, most words don't decode and are skipped
+    let mut word = 0x2545f491_u32;
+    let mut decoded = 0;
+    for _ in 0..2_000_000 {
+        word ^= word << 13;
+        word ^= word >> 17;
+        word ^= word << 5;
+        let Some(inst) = try_interpret_bytes(word) else {
+            continue;
+        };
+        // every bit of a word that decodes means something, so it encodes back exactly
+        decoded += 1;
+        assert_eq!(inst.encode(), word, "{} from 0x{:08x}", inst, word);
+    }
+    assert!(decoded > 1000);
+}
@@ -1,6 +1,16 @@
-use crate::vm::{self, ArchState};
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    asm,
+    test_fixtures::accumulator,
+    vm::{self, ArchState, HostSyscalls, PAGE_SIZE, Segment, StopReason, VmError, WatchEvent},
+};
 
 #[test]
+#[allow(clippy::unusual_byte_groupings)] // grouped by encoding field
 fn test_accumulator() {
     const MEM: usize = 2_usize.pow(8);
     let mut state = ArchState::with_mem(MEM);
@@ -9,7 +19,7 @@ fn test_accumulator() {
     state.load(
         (0..MEM)
             .map(|i| {
-                let byte = 3 - (i % 4);
+                let byte = i % 4;
                 (op >> (byte * 8)) as u8
             })
             .collect(),
@@ -19,6 +29,8 @@ fn test_accumulator() {
         "mem: {:?}",
         state
             .mem
+            .read(0, MEM)
+            .unwrap()
             .iter()
             .map(|n| format!("{:0>8b}", n))
             .collect::<Vec<String>>()
@@ -27,9 +39,673 @@ fn test_accumulator() {
 
     let mut i = 0;
     while i < MEM / 4 {
-        state.tick();
+        state.tick().unwrap();
         i += 1;
         print!("{}, ", state.get_register(1));
         assert_eq!(state.get_register(1) as usize, i);
     }
 }
+
+#[test]
+fn test_execute_program() {
+    const LOOPS: u64 = 50;
+    // addi x1, x1, 1
+    let op = 0x00108093_u32;
+    let program = (0..LOOPS)
+        .flat_map(|_| op.to_le_bytes())
+        .collect::<Vec<u8>>();
+
+    let report = ArchState::execute_program(program, 0x100, 1000);
+    assert_eq!(report.steps, LOOPS);
+    assert_eq!(report.registers[1] as u64, LOOPS);
+    assert_eq!(report.pc as u64, 0x100 + LOOPS * 4);
+    // the zero word after the program stops the run
+    assert_eq!(
+        report.stop,
+        StopReason::Fault(VmError::IllegalInstruction(0))
+    );
+
+    let capped = ArchState::execute_program(op.to_le_bytes().repeat(4), 0, 3);
+    assert_eq!(capped.steps, 3);
+    assert_eq!(capped.stop, StopReason::BudgetExhausted);
+}
+
+#[test]
+fn test_run() {
+    let mut state = accumulator();
+    assert_eq!(state.run(25).steps, 25);
+    assert_eq!(state.get_register(1), 25);
+    assert_eq!(state.pc, 25 * 4);
+    assert!(state.summary().contains("x1 : 0x00000019 | 0000000025"));
+}
+
+#[test]
+fn test_summary_negative_pc() {
+    let mut state = ArchState::new();
+    // beq x0, x0, -4 jumps below address 0
+    state.load(0xfe000ee3_u32.to_le_bytes().to_vec(), 0);
+    assert!(state.tick().is_ok());
+    assert!(state.tick().is_err());
+    let summary = state.summary();
+    assert!(summary.contains("pc : 0xFFFFFFFC | 4294967292"));
+    assert!(summary.contains("pc: fffffff0 00 00"));
+}
+
+#[test]
+fn test_run_stops_at_breakpoint() {
+    let mut state = accumulator();
+    state.add_breakpoint(0x40);
+    assert_eq!(state.run(100).steps, 16);
+    assert_eq!(state.pc, 0x40);
+    assert_eq!(state.get_register(1), 16);
+    assert!(state.paused);
+
+    // resuming runs past it until the next one
+    state.add_breakpoint(0x48);
+    assert_eq!(state.run(100).steps, 2);
+    assert!(state.remove_breakpoint(0x40));
+    assert!(!state.remove_breakpoint(0x40));
+    state.pc = 0;
+    assert_eq!(state.run(100).steps, 18);
+}
+
+#[test]
+fn test_run_stops_on_fault() {
+    let mut state = accumulator();
+    state.pc = 2_i64.pow(8);
+    assert_eq!(state.run(25).steps, 0);
+}
+
+#[test]
+fn test_run_to_exit() {
+    const LOOPS: u64 = 20;
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // the accumulator, then addi a7, x0, 93; addi a0, x1, 0; ecall
+    let mut program = 0x00108093_u32.to_le_bytes().repeat(LOOPS as usize);
+    for op in [0x05d00893_u32, 0x00008513, 0x00000073] {
+        program.extend(op.to_le_bytes());
+    }
+    state.load(program, 0);
+    state.set_syscall_handler(Box::new(HostSyscalls));
+
+    let report = state.run(1000);
+    assert_eq!(report.stop, StopReason::Exited(LOOPS as u32));
+    assert_eq!(report.steps, LOOPS + 3);
+    assert_eq!(report.registers[1] as u64, LOOPS);
+
+    // an exited machine doesn't run any further
+    assert_eq!(state.run(1000).steps, 0);
+}
+
+#[test]
+fn test_decode_cache() {
+    const LOOPS: u32 = 1000;
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // addi x1, x1, 1; addi x2, x2, -1; bne x2, x0, -8
+    state.load(
+        [0x00108093_u32, 0xfff10113, 0xfe011ce3]
+            .iter()
+            .flat_map(|op| op.to_le_bytes())
+            .collect(),
+        0,
+    );
+    state.set_register(2, LOOPS);
+    let ran = state.run(3 * LOOPS as u64).steps;
+
+    assert_eq!(ran, 3 * LOOPS as u64);
+    assert_eq!(state.get_register(1), LOOPS);
+    // only the first pass through the loop decodes
+    assert_eq!(state.decode_cache_hits(), 3 * (LOOPS as u64 - 1));
+
+    // sw x3, 0(x0) overwrites the cached addi with an all zero word
+    state.load(0x00302023_u32.to_le_bytes().to_vec(), 12);
+    state.pc = 0;
+    state.tick().unwrap();
+    state.pc = 12;
+    state.tick().unwrap();
+    state.pc = 0;
+    assert_eq!(state.tick(), Err(VmError::IllegalInstruction(0)));
+}
+
+#[test]
+#[allow(clippy::unusual_byte_groupings)] // grouped by encoding field
+fn test_on_retire() {
+    const MEM: usize = 2_usize.pow(8);
+    let mut state = ArchState::with_mem(MEM);
+
+    // addi x1, x1, 1 then addi x2, x2, 2
+    let program = [
+        0b1_00001_000_00001_0010011_u32,
+        0b10_00010_000_00010_0010011,
+    ];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+
+    let retired = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&retired);
+    state.on_retire(Box::new(move |state, inst| {
+        sink.lock().unwrap().push((state.pc, inst.to_string()));
+    }));
+
+    state.tick().unwrap();
+    state.tick().unwrap();
+
+    let retired = retired.lock().unwrap();
+    assert_eq!(retired.len(), 2);
+    assert_eq!(retired[0], (4, vm::interpret_bytes(program[0]).to_string()));
+    assert_eq!(retired[1], (8, vm::interpret_bytes(program[1]).to_string()));
+}
+
+#[test]
+fn test_export_mem_hex() {
+    const MEM: usize = 2_usize.pow(6);
+    let mut state = ArchState::with_mem(MEM);
+    state.load(vec![0xde, 0xad, 0xbe, 0xef], 0);
+    state.load(vec![0x01, 0x02, 0x03], 0x29);
+
+    let hex = state.export_mem_hex();
+
+    // read the image back the way $readmemh would
+    let mut parsed = vec![0_u8; MEM];
+    let mut addr = 0;
+    for token in hex.split_whitespace() {
+        if let Some(marker) = token.strip_prefix('@') {
+            addr = usize::from_str_radix(marker, 16).unwrap();
+        } else {
+            parsed[addr] = u8::from_str_radix(token, 16).unwrap();
+            addr += 1;
+        }
+    }
+    assert_eq!(parsed, state.mem.read(0, MEM).unwrap());
+    // the empty line between the two regions is skipped
+    assert_eq!(hex.matches('@').count(), 2);
+}
+
+// Minimal encoders for hand-assembling test programs
+fn encode_r(funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn encode_i(imm: i32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    ((imm as u32 & 0xFFF) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn encode_s(imm: i32, rs2: u32, rs1: u32, funct3: u32, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    (((imm >> 5) & 0x7F) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | ((imm & 0x1F) << 7)
+        | opcode
+}
+
+fn encode_j(imm: i32, rd: u32) -> u32 {
+    let imm = imm as u32;
+    (((imm >> 20) & 1) << 31)
+        | (((imm >> 1) & 0x3FF) << 21)
+        | (((imm >> 11) & 1) << 20)
+        | (((imm >> 12) & 0xFF) << 12)
+        | (rd << 7)
+        | 0b1101111
+}
+
+#[test]
+fn test_call_and_return() {
+    const MEM: usize = 2_usize.pow(9);
+    const SP: u32 = 2;
+    const RA: u32 = 1;
+    const A0: u32 = 10;
+    const A1: u32 = 11;
+    let program = [
+        // main
+        encode_i(0x100, 0, 0b000, SP, 0b0010011), // 0x00 addi sp, x0, 0x100
+        encode_i(5, 0, 0b000, A0, 0b0010011),     // 0x04 addi a0, x0, 5
+        encode_j(0x14 - 0x08, RA),                // 0x08 jal ra, func
+        encode_i(0, A0, 0b000, A1, 0b0010011),    // 0x0c addi a1, a0, 0
+        encode_j(0, 0),                           // 0x10 jal x0, 0
+        // func
+        encode_i(-16, SP, 0b000, SP, 0b0010011), // 0x14 addi sp, sp, -16
+        encode_s(12, RA, SP, 0b010, 0b0100011),  // 0x18 sw ra, 12(sp)
+        encode_r(0, A0, A0, 0b000, A0, 0b0110011), // 0x1c add a0, a0, a0
+        encode_i(12, SP, 0b010, RA, 0b0000011),  // 0x20 lw ra, 12(sp)
+        encode_i(16, SP, 0b000, SP, 0b0010011),  // 0x24 addi sp, sp, 16
+        encode_i(0, RA, 0b000, 0, 0b1100111),    // 0x28 jalr x0, 0(ra)
+    ];
+    let mut state = ArchState::with_mem(MEM);
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+
+    // three instructions to reach the call, six in func
+    for _ in 0..9 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.pc, 0x0c, "returned to the instruction after the call");
+    assert_eq!(state.get_register(RA as usize), 0x0c);
+    assert_eq!(state.get_register(SP as usize), 0x100, "stack is balanced");
+    assert_eq!(
+        state.mem[0x100 - 16 + 12],
+        0x0c,
+        "ra was spilled to the stack"
+    );
+
+    state.tick().unwrap();
+    assert_eq!(state.get_register(A1 as usize), 10);
+    assert_eq!(state.pc, 0x10);
+}
+
+#[test]
+fn test_load_segments() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let program = Arc::new(Mutex::new(vec![1, 2, 3, 4]));
+    let source = Arc::clone(&program);
+    let segments = vec![
+        Segment {
+            source: Box::new(move || Ok(source.lock().unwrap().clone())),
+            offset: 0,
+        },
+        Segment::from_bytes(vec![0xff], 0x80),
+    ];
+
+    state.load_segments(&segments).unwrap();
+    assert_eq!(state.mem.read(0, 4).unwrap(), [1, 2, 3, 4]);
+    assert_eq!(state.mem[0x80], 0xff);
+
+    // scribble on the state then change the source between reloads
+    state.pc = 8;
+    state.mem[0x40] = 7;
+    *program.lock().unwrap() = vec![5, 6];
+
+    state.load_segments(&segments).unwrap();
+    assert_eq!(state.mem.read(0, 4).unwrap(), [5, 6, 0, 0]);
+    assert_eq!(state.mem[0x40], 0);
+    assert_eq!(state.mem[0x80], 0xff);
+    assert_eq!(state.pc, 0);
+
+    // a failed read leaves the state untouched
+    let missing = vec![Segment {
+        source: Box::new(|| Err(io::Error::new(io::ErrorKind::NotFound, "missing"))),
+        offset: 0,
+    }];
+    assert!(state.load_segments(&missing).is_err());
+    assert_eq!(state.mem.read(0, 4).unwrap(), [5, 6, 0, 0]);
+
+    // so does a segment past the end of memory
+    let too_far = vec![Segment::from_bytes(vec![1, 2], 0xff)];
+    assert!(state.load_segments(&too_far).is_err());
+    assert_eq!(state.mem.read(0, 4).unwrap(), [5, 6, 0, 0]);
+}
+
+#[test]
+fn test_reset_vector() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.reset_vector = 0x40;
+    state
+        .load_segments(&[Segment::from_bytes(vec![1, 2, 3, 4], 0x80)])
+        .unwrap();
+    assert_eq!(state.pc, 0x40);
+
+    state.pc = 0x84;
+    state.reset();
+    assert_eq!(state.pc, 0x40);
+    assert_eq!(state.mem[0x80], 0);
+}
+
+#[test]
+fn test_populated_pages() {
+    let mut state = ArchState::with_mem(2_usize.pow(20));
+    assert_eq!(state.populated_pages().count(), 0);
+    state.load(vec![1, 2], 0x1ffe);
+    state.mem[0xf0123] = 0x42;
+
+    let pages = state.populated_pages().collect::<Vec<_>>();
+    assert_eq!(
+        pages.iter().map(|(base, _)| *base).collect::<Vec<u32>>(),
+        [0x1000, 0xf0000]
+    );
+    assert_eq!(pages[0].1.len(), vm::PAGE_SIZE);
+    assert_eq!(pages[1].1[0x123], 0x42);
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let program = [
+        0x00100093_u32, // addi x1, x0, 1
+        0x08100023,     // sb x1, 0x80(x0)
+        0x08102023,     // sw x1, 0x80(x0)
+        0x081000a3,     // sb x1, 0x81(x0)
+        0x00000093,     // addi x1, x0, 0
+        0x081000a3,     // sb x1, 0x81(x0)
+        0x081000a3,     // sb x1, 0x81(x0)
+    ];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+    state.add_watchpoint(0x81);
+
+    // the store to 0x80 alone passes, the word store writes 0x81 even though it stays zero
+    assert_eq!(state.run(100).stop, StopReason::Paused);
+    assert_eq!(state.pc, 12);
+
+    // each pause leaves its event queued until someone drains them
+    assert_eq!(state.run(100).stop, StopReason::Paused);
+    assert_eq!(state.pc, 16);
+    assert_eq!(state.run(100).stop, StopReason::Paused);
+    assert_eq!(state.pc, 24);
+    let event = |pc, old, new| WatchEvent {
+        pc,
+        addr: 0x81,
+        old,
+        new,
+    };
+    assert_eq!(
+        state.watch_events.drain(..).collect::<Vec<_>>(),
+        [event(8, 0, 0), event(12, 0, 1), event(20, 1, 0)]
+    );
+
+    // writing back the value already there still counts
+    assert_eq!(state.run(100).stop, StopReason::Paused);
+    assert_eq!(state.pc, 28);
+    assert_eq!(state.watch_events, [event(24, 0, 0)]);
+    assert!(state.remove_watchpoint(0x81));
+}
+
+#[test]
+fn test_register_seed() {
+    let seeded = |seed| {
+        let mut state = ArchState::with_mem(2_usize.pow(8));
+        state.register_seed = Some(seed);
+        state.reset();
+        (0..32).map(|i| state.get_register(i)).collect::<Vec<u32>>()
+    };
+    let regs = seeded(7);
+    assert_eq!(regs, seeded(7));
+    assert_ne!(regs, seeded(8));
+    assert_eq!(regs[0], 0);
+    assert!(regs[1..].iter().any(|reg| *reg != 0));
+}
+
+#[test]
+fn test_halt_addr() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.halt_addr = Some(0x80);
+    // addi x1, x0, 0x80; addi x2, x0, 42; sw x2, 4(x1); sw x2, 0(x1); addi x3, x0, 1
+    let program = [
+        0x08000093_u32,
+        0x02a00113,
+        0x0020a223,
+        0x0020a023,
+        0x00100193,
+    ];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+
+    assert_eq!(state.run(10).steps, 4);
+    assert!(state.halted);
+    assert_eq!(state.exit_status, Some(42));
+    assert_eq!(state.pc, 16);
+    // the instruction after the halting store never ran
+    assert_eq!(state.get_register(3), 0);
+    assert!(state.tick().is_err());
+
+    // the halt address doesn't need memory behind it
+    let mut state = ArchState::with_mem(1 << 20);
+    state.halt_addr = Some(0x80001000);
+    state.set_register(2, 7);
+    state.apply_raw(0x800010b7).unwrap(); // lui x1, 0x80001
+    state.apply_raw(0x0020a023).unwrap(); // sw x2, 0(x1)
+    assert!(state.halted);
+    assert_eq!(state.exit_status, Some(7));
+}
+
+#[test]
+fn test_tick_errors() {
+    const MEM: usize = 2_usize.pow(8);
+    let mut state = ArchState::with_mem(MEM);
+    // ecall, then an all zero word
+    state.load(0x00000073_u32.to_le_bytes().to_vec(), 0);
+
+    assert_eq!(state.tick(), Err(VmError::Ecall));
+    assert_eq!(VmError::Ecall.to_string(), "ecall with no handler");
+    assert_eq!(state.pc, 0, "a failed instruction doesn't retire");
+
+    state.pc = 4;
+    assert_eq!(state.tick(), Err(VmError::IllegalInstruction(0)));
+
+    state.pc = 1;
+    assert_eq!(state.tick(), Err(VmError::MisalignedPc(1)));
+
+    state.pc = MEM as i64;
+    assert_eq!(state.tick(), Err(VmError::FetchOutOfBounds(MEM as u32)));
+
+    state.halted = true;
+    assert_eq!(state.tick(), Err(VmError::Halted));
+}
+
+/// Untouched pages inside memory read as zero like any RAM, so the page past the end of memory
+/// is the unmapped one. A fetch straddling into it faults naming the missing upper half
+#[test]
+fn test_fetch_straddling_page_boundary() {
+    let mut state = ArchState::with_mem(PAGE_SIZE);
+    let last = PAGE_SIZE as u32 - 2;
+    // addi x1, x1, 1 cut short after its first two bytes, in the last two bytes of the page
+    state.load(0x00108093_u32.to_le_bytes()[..2].to_vec(), last as usize);
+
+    state.pc = last as i64;
+    assert_eq!(state.fetch(last as usize), None);
+    assert_eq!(
+        state.tick(),
+        Err(VmError::FetchOutOfBounds(PAGE_SIZE as u32))
+    );
+    assert_eq!(state.get_register(1), 0);
+    assert_eq!(state.pc, last as i64);
+}
+
+#[test]
+fn test_run_to() {
+    let program = asm::assemble(
+        "
+            li x1, 0
+            li x2, 5
+        loop:
+            addi x1, x1, 1
+            bne x1, x2, loop
+        done:
+            addi x3, x0, 7
+        ",
+    )
+    .unwrap();
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(program, 0);
+
+    // a target inside the loop pauses on the first pass only
+    state.run_to(0x8);
+    let report = state.run(1000);
+    assert_eq!(report.stop, StopReason::Paused);
+    assert_eq!((report.pc, report.registers[1]), (0x8, 0));
+    assert_eq!(state.temporary_breakpoint(), None);
+
+    state.run_to(0x10);
+    let report = state.run(1000);
+    assert_eq!(report.stop, StopReason::Paused);
+    assert_eq!((report.pc, report.registers[1]), (0x10, 5));
+    assert_eq!(
+        report.registers[3], 0,
+        "stops before the instruction at the target"
+    );
+
+    state.run(1);
+    assert_eq!(state.get_register(3), 7);
+}
+
+#[test]
+fn test_ebreak_resumes() {
+    const EBREAK: u32 = 0x00100073;
+    // addi x1, x1, 1; ebreak; bne x1, x2, -8, with x2 = 3
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(
+        [0x00108093_u32, EBREAK, 0xfe209ce3]
+            .iter()
+            .flat_map(|op| op.to_le_bytes())
+            .collect(),
+        0,
+    );
+    state.set_register(2, 3);
+    state.interactive = true;
+
+    // every pass pauses once past the break, resuming doesn't hit it again
+    for pass in 1..=3 {
+        let report = state.run(1000);
+        assert_eq!(report.stop, StopReason::Paused);
+        assert_eq!((report.pc, report.registers[1]), (0x8, pass));
+    }
+    // the third pass falls through into zeroed memory
+    let report = state.run(1000);
+    assert_eq!(
+        report.stop,
+        StopReason::Fault(VmError::IllegalInstruction(0))
+    );
+    assert_eq!(report.pc, 0xc);
+}
+
+#[test]
+fn test_set_entry() {
+    let mut state = ArchState::with_mem(2_usize.pow(12));
+    state.set_entry(0x100);
+    assert_eq!(state.pc, 0x100);
+    // addi x1, x0, 1 at 0, then addi x2, x0, 2; jal x0, -0x104 at the entry
+    state
+        .load_segments(&[
+            Segment::from_bytes(0x00100093_u32.to_le_bytes().to_vec(), 0),
+            Segment::from_bytes(
+                [0x00200113_u32, 0xefdff06f]
+                    .iter()
+                    .flat_map(|op| op.to_le_bytes())
+                    .collect(),
+                0x100,
+            ),
+        ])
+        .unwrap();
+    assert_eq!(state.pc, 0x100, "loading resets to the entry");
+
+    state.tick().unwrap();
+    assert_eq!((state.get_register(1), state.get_register(2)), (0, 2));
+    // jumping back down to address 0 still works
+    state.tick().unwrap();
+    assert_eq!(state.pc, 0);
+    state.tick().unwrap();
+    assert_eq!(state.get_register(1), 1);
+
+    state.reset();
+    assert_eq!(state.pc, 0x100);
+}
+
+#[test]
+fn test_step_over() {
+    let program = asm::assemble(
+        "
+            li x1, 0
+            jal ra, double
+            addi x3, x0, 7
+            j end
+        double:
+            li x2, 21
+            add x2, x2, x2
+            ret
+        end:
+            nop
+        ",
+    )
+    .unwrap();
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(program, 0);
+    state.interactive = true;
+
+    // not a call, so a plain step
+    assert_eq!(state.call_return_addr(), None);
+    state.tick().unwrap();
+
+    let ret = state.call_return_addr().unwrap();
+    assert_eq!(ret, 8);
+    state.run_to(ret);
+    let report = state.run(1000);
+    assert_eq!(report.stop, StopReason::Paused);
+    assert_eq!(report.pc, 8, "lands after the call");
+    assert_eq!(report.registers[2], 42, "the subroutine ran");
+    assert_eq!(report.registers[3], 0);
+}
+
+#[test]
+fn test_decode_range() {
+    // the accumulator from test_run_to_exit with half a word left over
+    let words = [
+        0x00108093_u32,
+        0x00108093,
+        0x05d00893,
+        0x00008513,
+        0x00000073,
+    ];
+    let mut program = words
+        .iter()
+        .flat_map(|op| op.to_le_bytes())
+        .collect::<Vec<u8>>();
+    program.extend([0x93, 0x80]);
+
+    let decoded = vm::decode_range(&program, 0, u32::MAX)
+        .map(|(addr, inst)| (addr, inst.to_string()))
+        .collect::<Vec<_>>();
+    let expected = words
+        .iter()
+        .enumerate()
+        .map(|(i, word)| (i as u32 * 4, vm::interpret_bytes(*word).to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(decoded, expected);
+
+    let middle = vm::decode_range(&program, 8, 16).collect::<Vec<_>>();
+    assert_eq!(middle.len(), 2);
+    assert_eq!(middle[0].0, 8);
+    assert!(matches!(middle[1].1, vm::Instruction::ADDI { .. }));
+    assert_eq!(vm::decode_range(&program, 20, 22).count(), 0);
+}
+
+#[test]
+fn test_initial_stack_pointer() {
+    let mut state = ArchState::with_mem(0x10000);
+    assert_eq!(state.get_register(2), 0x10000 - 16);
+
+    // the top stays 16 byte aligned, and a 4G memory doesn't wrap to zero
+    state.stack_reserved = Some(0x104);
+    state.reset();
+    assert_eq!(state.get_register(2), 0xfef0);
+    assert_eq!(ArchState::new().get_register(2), 0xfffffff0);
+
+    state.stack_reserved = None;
+    state.reset();
+    assert_eq!(state.get_register(2), 0);
+
+    // seeded registers still get a usable stack
+    let mut state = ArchState::with_mem(0x10000);
+    state.register_seed = Some(7);
+    state.reset();
+    assert_ne!(state.get_register(1), 0);
+    assert_eq!(state.get_register(2), 0xfff0);
+}
+
+#[test]
+fn test_idle_loop() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // addi x1, x1, 1; addi x1, x1, 1; jal x0, 0
+    let program = [0x00108093_u32, 0x00108093, 0x0000006f];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+
+    // off by default, the spin uses up the whole budget
+    assert_eq!(state.run(100).stop, StopReason::BudgetExhausted);
+
+    state.idle_limit = Some(10);
+    let report = state.run(1000);
+    assert_eq!(report.stop, StopReason::IdleLoop(8));
+    assert_eq!(report.steps, 10);
+    assert_eq!(report.registers[1], 2);
+
+    // the count starts over after each trip, and real progress resets it
+    state.pc = 0;
+    let report = state.run(1000);
+    assert_eq!(report.stop, StopReason::IdleLoop(8));
+    assert_eq!(report.steps, 2 + 10);
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::BTreeMap,
+    ops::{Index, IndexMut, Range},
+};
+
+use super::PAGE_SIZE;
+
+#[cfg(test)]
+mod memory_tests;
+
+static ZERO: u8 = 0;
+
+/// Byte addressed memory that only allocates the pages written to, untouched pages read as zero.
+/// Pages are kept ordered by address so dumps can walk them without sorting
+#[derive(Clone, Debug, Default)]
+pub struct Memory {
+    len: usize,
+    pages: BTreeMap<u32, Box<[u8; PAGE_SIZE]>>,
+}
+
+impl Memory {
+    /// `len` bytes of zeroed memory, nothing is allocated until written
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Zeroes everything, freeing every page
+    pub fn clear(&mut self) {
+        self.pages.clear();
+    }
+
+    pub fn get(&self, addr: usize) -> Option<&u8> {
+        if addr >= self.len {
+            return None;
+        }
+        Some(match self.pages.get(&page_number(addr)) {
+            Some(page) => &page[addr % PAGE_SIZE],
+            None => &ZERO,
+        })
+    }
+
+    /// Copies out `len` bytes from `addr`, `None` if any of them are past the end
+    pub fn read(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        if addr.checked_add(len)? > self.len {
+            return None;
+        }
+        Some((addr..addr + len).map(|i| self[i]).collect())
+    }
+
+    /// Copies `bytes` in starting at `addr`, panicking like a slice if they run past the end
+    pub fn write(&mut self, addr: usize, bytes: &[u8]) {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self[addr + offset] = *byte;
+        }
+    }
+
+    /// Allocated pages holding at least one nonzero byte, with their base address
+    pub fn populated_pages(&self) -> impl Iterator<Item = (u32, &[u8])> {
+        self.pages
+            .iter()
+            .filter(|(_, page)| page.iter().any(|byte| *byte != 0))
+            .map(|(number, page)| {
+                let base = *number as usize * PAGE_SIZE;
+                // the last page may extend past the end of memory
+                (base as u32, &page[..PAGE_SIZE.min(self.len - base)])
+            })
+    }
+
+    /// Start of every occurrence of `pattern` in address order, overlapping ones included.
+    /// Untouched pages are skipped unless the pattern is all zeroes
+    pub fn find(&self, pattern: &[u8]) -> Vec<u32> {
+        if pattern.is_empty() || pattern.len() > self.len {
+            return vec![];
+        }
+        let starts = self.len - pattern.len() + 1;
+        let ranges: Vec<Range<usize>> = if pattern.iter().all(|&byte| byte == 0) {
+            std::iter::once(0..starts).collect()
+        } else {
+            // a match holds a nonzero byte, so it overlaps an allocated page
+            self.pages
+                .keys()
+                .map(|number| {
+                    let base = *number as usize * PAGE_SIZE;
+                    base.saturating_sub(pattern.len() - 1)..(base + PAGE_SIZE).min(starts)
+                })
+                .collect()
+        };
+        let mut hits = vec![];
+        // ranges of neighbouring pages overlap, don't check a start twice
+        let mut next = 0;
+        for range in ranges {
+            for start in range.start.max(next)..range.end {
+                if pattern
+                    .iter()
+                    .enumerate()
+                    .all(|(offset, &byte)| self[start + offset] == byte)
+                {
+                    hits.push(start as u32);
+                }
+            }
+            next = next.max(range.end);
+        }
+        hits
+    }
+
+    /// Number of pages backed by an allocation, zeroed or not
+    pub fn allocated_pages(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+fn page_number(addr: usize) -> u32 {
+    (addr / PAGE_SIZE) as u32
+}
+
+impl Index<usize> for Memory {
+    type Output = u8;
+
+    fn index(&self, addr: usize) -> &u8 {
+        self.get(addr).unwrap_or_else(|| {
+            panic!(
+                "address 0x{:x} out of range for 0x{:x} bytes of memory",
+                addr, self.len
+            )
+        })
+    }
+}
+
+impl IndexMut<usize> for Memory {
+    /// Allocates the page holding `addr` if it hasn't been written yet
+    fn index_mut(&mut self, addr: usize) -> &mut u8 {
+        assert!(
+            addr < self.len,
+            "address 0x{:x} out of range for 0x{:x} bytes of memory",
+            addr,
+            self.len
+        );
+        let page = self
+            .pages
+            .entry(page_number(addr))
+            .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+        &mut page[addr % PAGE_SIZE]
+    }
+}
+
+/// Equal when every byte reads the same, whether or not a zeroed page is allocated
+impl PartialEq for Memory {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.populated_pages().eq(other.populated_pages())
+    }
+}
+
+impl Eq for Memory {}
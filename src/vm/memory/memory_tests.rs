@@ -0,0 +1,83 @@
+use crate::vm::{ArchState, Memory, PAGE_SIZE};
+
+#[test]
+fn test_sparse_memory() {
+    let mut state = ArchState::new();
+    assert_eq!(state.mem.len(), 2_usize.pow(32));
+    // lui x1, 0xfffff; addi x2, x0, 0x7b; sw x2, 0x7f0(x1)
+    state.load(
+        [0xfffff0b7_u32, 0x07b00113, 0x7e20a823]
+            .iter()
+            .flat_map(|op| op.to_le_bytes())
+            .collect(),
+        0,
+    );
+    for _ in 0..3 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.mem.read(0xfffff7f0, 4).unwrap(), [0x7b, 0, 0, 0]);
+    assert_eq!(state.mem[0x8000_0000], 0, "untouched pages read as zero");
+    assert_eq!(
+        state.mem.allocated_pages(),
+        2,
+        "only the program and stored pages"
+    );
+
+    state.reset();
+    assert_eq!(state.mem.allocated_pages(), 0);
+}
+
+#[test]
+fn test_memory_bounds() {
+    let mut mem = Memory::new(PAGE_SIZE + 2);
+    mem.write(PAGE_SIZE - 1, &[1, 2, 3]);
+    assert_eq!(mem.read(PAGE_SIZE - 1, 3).unwrap(), [1, 2, 3]);
+    assert_eq!(mem.read(PAGE_SIZE, 3), None);
+    assert_eq!(mem.get(PAGE_SIZE + 2), None);
+    let pages = mem.populated_pages().collect::<Vec<_>>();
+    assert_eq!(pages[1], (PAGE_SIZE as u32, &[2, 3][..]));
+
+    // a zeroed page that was allocated compares equal to one that never was
+    let mut other = mem.clone();
+    other[0] = 0;
+    assert_eq!(mem, other);
+}
+
+#[test]
+fn test_find_bytes() {
+    let mut state = ArchState::with_mem(4 * PAGE_SIZE);
+    state.mem.write(0x10, &[0xef, 0xbe, 0xad, 0xde]);
+    // one match straddles the first page boundary and another ends the memory
+    state.mem.write(PAGE_SIZE - 2, &[0xef, 0xbe, 0xad, 0xde]);
+    state
+        .mem
+        .write(4 * PAGE_SIZE - 4, &[0xef, 0xbe, 0xad, 0xde]);
+    assert_eq!(
+        state.find_bytes(&0xdeadbeef_u32.to_le_bytes()),
+        [0x10, PAGE_SIZE as u32 - 2, 4 * PAGE_SIZE as u32 - 4]
+    );
+
+    // longer than a word, and overlapping matches are all reported
+    state.mem.write(0x100, &[1, 2, 1, 2, 1, 2, 3]);
+    assert_eq!(state.find_bytes(&[1, 2, 1, 2]), [0x100, 0x102]);
+    assert_eq!(state.find_bytes(&[1, 2, 1, 2, 1, 2, 3]), [0x100]);
+
+    // zeroes match untouched pages too
+    let empty = ArchState::with_mem(2 * PAGE_SIZE);
+    assert_eq!(empty.find_bytes(&[0; 8]).len(), 2 * PAGE_SIZE - 7);
+    assert!(state.find_bytes(&[]).is_empty());
+    assert!(state.find_bytes(&[9]).is_empty());
+}
+
+#[test]
+fn test_dump() {
+    let mut state = ArchState::with_mem(2 * PAGE_SIZE);
+    state.mem.write(0x20, &[1, 2, 3, 4]);
+    assert_eq!(state.dump(0x1e, 8), [0, 0, 1, 2, 3, 4, 0, 0]);
+
+    // past the end of memory, and past the end of the address space, reads as zero
+    state.mem.write(2 * PAGE_SIZE - 2, &[0xaa, 0xbb]);
+    assert_eq!(state.dump(2 * PAGE_SIZE as u32 - 2, 4), [0xaa, 0xbb, 0, 0]);
+    assert_eq!(state.dump(u32::MAX, 3), [0, 0, 0]);
+    assert!(state.dump(0, 0).is_empty());
+}
@@ -0,0 +1,148 @@
+use std::{
+    collections::VecDeque,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use super::ArchState;
+
+#[cfg(test)]
+mod mmio_tests;
+
+/// A device behind a range of addresses, see [`ArchState::map_mmio`].
+/// `offset` is relative to the start of the range and `width` is 1, 2, or 4 bytes
+pub trait MmioDevice: Send {
+    fn read(&mut self, offset: u32, width: u32) -> u32;
+    fn write(&mut self, offset: u32, width: u32, val: u32);
+    /// What `read` would return right now, without any of its side effects. Used for display
+    fn peek(&self, offset: u32, width: u32) -> u32;
+}
+
+/// Collects the low byte of every store, reads return zero
+#[derive(Default)]
+pub struct ConsoleDevice {
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl ConsoleDevice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle to everything written so far, still readable once the device is mapped
+    pub fn output(&self) -> Arc<Mutex<Vec<u8>>> {
+        Arc::clone(&self.output)
+    }
+}
+
+impl MmioDevice for ConsoleDevice {
+    fn read(&mut self, _offset: u32, _width: u32) -> u32 {
+        0
+    }
+
+    fn peek(&self, _offset: u32, _width: u32) -> u32 {
+        0
+    }
+
+    fn write(&mut self, _offset: u32, _width: u32, val: u32) {
+        if let Ok(mut output) = self.output.lock() {
+            output.push(val as u8);
+        }
+    }
+}
+
+/// Where `--input` maps an [`InputDevice`], its status word follows at `INPUT_ADDR + 4`
+pub const INPUT_ADDR: u32 = 0x1000_0010;
+
+/// A queue of bytes for the guest to read in order. A load from offset 0 takes the next byte,
+/// or `u32::MAX` once the queue is empty, and offset 4 reads how many bytes are left
+#[derive(Default)]
+pub struct InputDevice {
+    queue: Arc<Mutex<VecDeque<u8>>>,
+}
+
+impl InputDevice {
+    pub fn new(bytes: &[u8]) -> Self {
+        Self {
+            queue: Arc::new(Mutex::new(bytes.iter().copied().collect())),
+        }
+    }
+
+    /// Handle to the pending bytes, pushing to it appends input once the device is mapped
+    pub fn queue(&self) -> Arc<Mutex<VecDeque<u8>>> {
+        Arc::clone(&self.queue)
+    }
+}
+
+impl MmioDevice for InputDevice {
+    fn read(&mut self, offset: u32, _width: u32) -> u32 {
+        let Ok(mut queue) = self.queue.lock() else {
+            return u32::MAX;
+        };
+        match offset {
+            0 => queue.pop_front().map_or(u32::MAX, u32::from),
+            _ => queue.len() as u32,
+        }
+    }
+
+    fn write(&mut self, _offset: u32, _width: u32, _val: u32) {}
+
+    fn peek(&self, offset: u32, _width: u32) -> u32 {
+        let Ok(queue) = self.queue.lock() else {
+            return u32::MAX;
+        };
+        match offset {
+            0 => queue.front().map_or(u32::MAX, |&byte| u32::from(byte)),
+            _ => queue.len() as u32,
+        }
+    }
+}
+
+impl ArchState {
+    /// Routes loads and stores starting inside `range` to `device` instead of memory.
+    /// The first mapping containing an address wins, mappings are kept across reset
+    pub fn map_mmio(&mut self, range: Range<u32>, device: Box<dyn MmioDevice>) {
+        self.mmio.push((range, device));
+    }
+
+    /// Whether a device is mapped over `addr`
+    pub fn is_mmio(&self, addr: u32) -> bool {
+        self.mmio.iter().any(|(range, _)| range.contains(&addr))
+    }
+
+    /// Byte at `addr` for display. Mapped addresses come from the device's
+    /// [`MmioDevice::peek`] so showing them doesn't consume anything, `None` past the end of memory
+    pub fn peek_byte(&self, addr: u32) -> Option<u8> {
+        match self.mmio.iter().find(|(range, _)| range.contains(&addr)) {
+            Some((range, device)) => {
+                let offset = addr - range.start;
+                let word = device.peek(offset & !0b11, 4);
+                Some((word >> (8 * (offset & 0b11))) as u8)
+            }
+            None => self.mem.get(addr as usize).copied(),
+        }
+    }
+
+    pub(super) fn mmio_read(&mut self, addr: u32, width: u32) -> Option<u32> {
+        let (range, device) = self
+            .mmio
+            .iter_mut()
+            .find(|(range, _)| range.contains(&addr))?;
+        Some(device.read(addr - range.start, width))
+    }
+
+    /// Returns whether a device took the store
+    pub(super) fn mmio_write(&mut self, addr: u32, width: u32, val: u32) -> bool {
+        match self
+            .mmio
+            .iter_mut()
+            .find(|(range, _)| range.contains(&addr))
+        {
+            Some((range, device)) => {
+                device.write(addr - range.start, width, val);
+                true
+            }
+            None => false,
+        }
+    }
+}
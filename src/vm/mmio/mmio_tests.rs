@@ -0,0 +1,98 @@
+use crate::{asm::assemble, vm::ArchState};
+
+use super::{ConsoleDevice, INPUT_ADDR, InputDevice, MmioDevice};
+
+const CONSOLE: u32 = 0x1000_0000;
+
+/// Counts reads so a load is visibly routed to the device
+struct Counter(u32);
+
+impl MmioDevice for Counter {
+    fn read(&mut self, offset: u32, _width: u32) -> u32 {
+        self.0 += 1;
+        self.0 + offset
+    }
+
+    fn write(&mut self, _offset: u32, _width: u32, val: u32) {
+        self.0 = val;
+    }
+
+    fn peek(&self, offset: u32, _width: u32) -> u32 {
+        self.0 + 1 + offset
+    }
+}
+
+#[test]
+fn test_console_device() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let console = ConsoleDevice::new();
+    let output = console.output();
+    state.map_mmio(CONSOLE..CONSOLE + 4, Box::new(console));
+
+    // lui a1, 0x10000; addi a0, x0, 'h'; sb a0, 0(a1); addi a0, x0, 'i'; sb a0, 0(a1)
+    let program = [
+        0x100005b7_u32,
+        0x06800513,
+        0x00a58023,
+        0x06900513,
+        0x00a58023,
+    ];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+    for _ in 0..program.len() {
+        state.tick().unwrap();
+    }
+    assert_eq!(*output.lock().unwrap(), b"hi");
+}
+
+#[test]
+fn test_mmio_load() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.map_mmio(0x80..0x90, Box::new(Counter(0)));
+    state.mem[0x90] = 0x7f;
+    state.set_register(1, 0x80);
+
+    // lw x2, 4(x1); lbu x3, 16(x1) just past the mapping
+    state.apply_raw(0x0040a103).unwrap();
+    state.apply_raw(0x0100c183).unwrap();
+    assert_eq!(state.get_register(2), 5);
+    assert_eq!(state.get_register(3), 0x7f);
+    // sw x0, 0(x1) resets the counter without touching memory
+    state.mem[0x80] = 0xaa;
+    state.apply_raw(0x0000a023).unwrap();
+    state.apply_raw(0x0040a103).unwrap();
+    assert_eq!(state.get_register(2), 5);
+    assert_eq!(state.mem[0x80], 0xaa);
+}
+
+#[test]
+fn test_input_device() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let input = InputDevice::new(b"abc");
+    let queue = input.queue();
+    state.map_mmio(INPUT_ADDR..INPUT_ADDR + 8, Box::new(input));
+
+    let source = "
+        lui a1, 0x10000
+        lw a5, 20(a1)
+        lbu a0, 16(a1)
+        lbu a2, 16(a1)
+        lbu a3, 16(a1)
+        lw a4, 16(a1)
+    ";
+    state.load(assemble(source).unwrap(), 0);
+    for _ in 0..6 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.get_register(15), 3, "three bytes were queued");
+    assert_eq!(
+        [10, 12, 13].map(|reg| state.get_register(reg) as u8),
+        *b"abc"
+    );
+    assert_eq!(state.get_register(14), u32::MAX, "then the queue is empty");
+
+    // bytes pushed later, like keystrokes, are read next
+    queue.lock().unwrap().push_back(b'd');
+    state.pc = 8;
+    state.tick().unwrap();
+    assert_eq!(state.get_register(10), u32::from(b'd'));
+}
@@ -0,0 +1,201 @@
+use std::{collections::HashMap, fmt::Write, fs, io, iter::Peekable, path::Path, slice};
+
+use super::{ArchState, Memory};
+
+#[cfg(test)]
+mod save_tests;
+
+/// First line of every saved state, bumped if the format changes
+const HEADER: &str = "rv32-state 2";
+/// Bytes per memory line, blocks that are all zero are left out
+const BLOCK: usize = 32;
+
+/// Everything a saved state holds, parsed before any of it is applied
+struct Saved {
+    pc: u32,
+    regs: [u32; 31],
+    halted: bool,
+    exit_status: Option<u32>,
+    retired: u64,
+    cycles: u64,
+    reservation: Option<u32>,
+    csrs: HashMap<u16, u32>,
+    mem: Memory,
+}
+
+impl ArchState {
+    /// Writes registers, pc, CSRs, the counters, the LR/SC reservation and memory to `path`
+    /// as text. Memory is stored sparsely, one hex line per nonzero 32 byte block, so a mostly
+    /// empty memory stays small
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.save_string())
+    }
+
+    /// Restores a file written by `save`, anything it doesn't hold (history, statistics) is reset.
+    /// The state is left untouched if the file can't be read or parsed
+    pub fn load_snapshot(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let text = fs::read_to_string(path)?;
+        let saved = parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.reset();
+        self.pc = saved.pc as i64;
+        self.regs = saved.regs;
+        self.halted = saved.halted;
+        self.exit_status = saved.exit_status;
+        self.retired = saved.retired;
+        self.cycles = saved.cycles;
+        self.reservation = saved.reservation;
+        self.csrs = saved.csrs;
+        self.mem = saved.mem;
+        Ok(())
+    }
+
+    fn save_string(&self) -> String {
+        let mut out = format!("{}\npc 0x{:08x}\n", HEADER, self.pc as u32);
+        for (index, reg) in self.regs.iter().enumerate() {
+            writeln!(out, "x{} 0x{:08x}", index + 1, reg).unwrap();
+        }
+        writeln!(out, "halted {}", self.halted).unwrap();
+        match self.exit_status {
+            Some(status) => writeln!(out, "exit 0x{:08x}", status).unwrap(),
+            None => writeln!(out, "exit none").unwrap(),
+        }
+        writeln!(out, "retired 0x{:x}", self.retired).unwrap();
+        writeln!(out, "cycles 0x{:x}", self.cycles).unwrap();
+        match self.reservation {
+            Some(addr) => writeln!(out, "reservation 0x{:08x}", addr).unwrap(),
+            None => writeln!(out, "reservation none").unwrap(),
+        }
+        // sorted so the same state always saves the same text
+        let mut csrs = self.csrs.iter().collect::<Vec<_>>();
+        csrs.sort();
+        for (csr, val) in csrs {
+            writeln!(out, "csr 0x{:03x} 0x{:08x}", csr, val).unwrap();
+        }
+        writeln!(out, "mem 0x{:x}", self.mem.len()).unwrap();
+        let blocks = self.mem.populated_pages().flat_map(|(base, page)| {
+            page.chunks(BLOCK)
+                .enumerate()
+                .map(move |(block, bytes)| (base as usize + block * BLOCK, bytes))
+        });
+        for (addr, bytes) in blocks {
+            if bytes.iter().all(|&byte| byte == 0) {
+                continue;
+            }
+            write!(out, "0x{:08x} ", addr).unwrap();
+            bytes
+                .iter()
+                .for_each(|byte| write!(out, "{:02x}", byte).unwrap());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Numbered lines of a saved state
+type Lines<'a> = Peekable<slice::Iter<'a, (usize, &'a str)>>;
+
+/// The value of the next line, which has to be `name value`
+fn next_field(lines: &mut Lines, name: &str) -> Result<(usize, String), String> {
+    let (line_no, line) = lines.next().ok_or_else(|| format!("missing '{}'", name))?;
+    match line.split_once(' ') {
+        Some((key, value)) if key == name => Ok((*line_no, value.to_string())),
+        _ => Err(format!("line {}: expected '{}'", line_no, name)),
+    }
+}
+
+fn parse(text: &str) -> Result<Saved, String> {
+    let numbered = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line))
+        .collect::<Vec<_>>();
+    let mut lines = numbered.iter().peekable();
+
+    let (_, version) = next_field(&mut lines, "rv32-state")?;
+    if format!("rv32-state {}", version) != HEADER {
+        return Err(format!("unsupported version {}", version));
+    }
+    let pc = word(next_field(&mut lines, "pc")?)?;
+    let mut regs = [0; 31];
+    for (index, reg) in regs.iter_mut().enumerate() {
+        *reg = word(next_field(&mut lines, &format!("x{}", index + 1))?)?;
+    }
+    let halted = match next_field(&mut lines, "halted")? {
+        (_, value) if value == "true" => true,
+        (_, value) if value == "false" => false,
+        (line_no, value) => return Err(format!("line {}: invalid bool '{}'", line_no, value)),
+    };
+    let exit_status = match next_field(&mut lines, "exit")? {
+        (_, value) if value == "none" => None,
+        other => Some(word(other)?),
+    };
+    let retired = hex(next_field(&mut lines, "retired")?)?;
+    let cycles = hex(next_field(&mut lines, "cycles")?)?;
+    let reservation = match next_field(&mut lines, "reservation")? {
+        (_, value) if value == "none" => None,
+        other => Some(word(other)?),
+    };
+    let mut csrs = HashMap::new();
+    while lines
+        .peek()
+        .is_some_and(|(_, line)| line.starts_with("csr "))
+    {
+        let (line_no, value) = next_field(&mut lines, "csr")?;
+        let (csr, val) = value
+            .split_once(' ')
+            .ok_or_else(|| format!("line {}: expected a CSR and its value", line_no))?;
+        let csr = u16::try_from(word((line_no, csr.to_string()))?)
+            .ok()
+            .filter(|csr| *csr < 0x1000)
+            .ok_or_else(|| format!("line {}: invalid CSR '{}'", line_no, csr))?;
+        csrs.insert(csr, word((line_no, val.to_string()))?);
+    }
+    let mut mem = Memory::new(hex(next_field(&mut lines, "mem")?)? as usize);
+
+    for &(line_no, line) in lines {
+        let (addr, bytes) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("line {}: expected an address and bytes", line_no))?;
+        let addr = word((line_no, addr.to_string()))? as usize;
+        if !bytes.is_ascii() || bytes.len() % 2 != 0 {
+            return Err(format!("line {}: expected pairs of hex digits", line_no));
+        }
+        if addr + bytes.len() / 2 > mem.len() {
+            return Err(format!(
+                "line {}: block runs past the end of memory",
+                line_no
+            ));
+        }
+        let bytes = (0..bytes.len())
+            .step_by(2)
+            .map(|at| u8::from_str_radix(&bytes[at..at + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|err| format!("line {}: {}", line_no, err))?;
+        mem.write(addr, &bytes);
+    }
+
+    Ok(Saved {
+        pc,
+        regs,
+        halted,
+        exit_status,
+        retired,
+        cycles,
+        reservation,
+        csrs,
+        mem,
+    })
+}
+
+fn hex((line_no, value): (usize, String)) -> Result<u64, String> {
+    value
+        .strip_prefix("0x")
+        .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+        .ok_or_else(|| format!("line {}: invalid hex value '{}'", line_no, value))
+}
+
+fn word(field: (usize, String)) -> Result<u32, String> {
+    let line_no = field.0;
+    u32::try_from(hex(field)?)
+        .map_err(|_| format!("line {}: value doesn't fit in 32 bits", line_no))
+}
@@ -0,0 +1,55 @@
+use std::fs;
+
+use crate::vm::{ArchState, CSR_INSTRET, CSR_MEPC};
+
+#[test]
+fn test_save_round_trip() {
+    let mut state = ArchState::with_mem(2_usize.pow(16));
+    // addi x1, x0, 0x55; sb x1, 0x80(x0); lr.w x3, (x0)
+    state.load(
+        [0x05500093_u32, 0x08100023, 0x100021af]
+            .iter()
+            .flat_map(|op| op.to_le_bytes())
+            .collect(),
+        0,
+    );
+    for _ in 0..3 {
+        state.tick().unwrap();
+    }
+    state.set_register(31, 0xdeadbeef);
+    state.mem[0xfffe] = 0x7f;
+    state.set_trap_vector(Some(0x40));
+    state.csrs.insert(CSR_MEPC, 8);
+
+    let path = std::env::temp_dir().join(format!("rv32-state-{}.txt", std::process::id()));
+    state.save(&path).unwrap();
+    let saved = fs::read_to_string(&path).unwrap();
+    assert_eq!(
+        saved.lines().count(),
+        1 + 1 + 31 + 5 + 2 + 1 + 3,
+        "only nonzero blocks are written"
+    );
+
+    let mut restored = ArchState::with_mem(16);
+    restored.load_snapshot(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(restored.pc, state.pc);
+    for index in 0..32 {
+        assert_eq!(restored.get_register(index), state.get_register(index));
+    }
+    assert_eq!(restored.mem, state.mem);
+    assert_eq!(restored.csrs, state.csrs);
+    assert_eq!(restored.trap_vector(), Some(0x40));
+    assert_eq!(restored.read_csr(CSR_INSTRET), 3);
+    assert_eq!(restored.cycle_count(), state.cycle_count());
+    assert_eq!(restored.reservation, Some(0));
+
+    // files from a newer format are refused
+    fs::write(&path, saved.replace("rv32-state 2", "rv32-state 3")).unwrap();
+    assert!(restored.load_snapshot(&path).is_err());
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        restored.mem[0x80], 0x55,
+        "a failed load leaves the state alone"
+    );
+}
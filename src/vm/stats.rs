@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use super::Instruction;
+
+#[cfg(test)]
+mod stats_tests;
+
+/// Instructions applied since the last reset by kind, see [`super::ArchState::stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InstructionStats {
+    /// Register and immediate ALU ops, multiplies and divides, `lui` and `auipc`
+    pub arithmetic: u64,
+    /// Includes `lr.w`
+    pub loads: u64,
+    /// Includes `sc.w` and the AMOs
+    pub stores: u64,
+    pub branches_taken: u64,
+    pub branches_not_taken: u64,
+    /// `jal` and `jalr`
+    pub jumps: u64,
+    /// CSR accesses, `ecall`, `ebreak`, and fences
+    pub system: u64,
+}
+
+impl InstructionStats {
+    pub fn total(&self) -> u64 {
+        self.arithmetic
+            + self.loads
+            + self.stores
+            + self.branches_taken
+            + self.branches_not_taken
+            + self.jumps
+            + self.system
+    }
+
+    /// Counts `inst`, `taken` is whether it left pc somewhere other than the next instruction
+    pub(super) fn count(&mut self, inst: &Instruction, taken: bool) {
+        let counter = match inst {
+            Instruction::LB { .. }
+            | Instruction::LH { .. }
+            | Instruction::LW { .. }
+            | Instruction::LBU { .. }
+            | Instruction::LHU { .. }
+            | Instruction::LRW { .. } => &mut self.loads,
+            Instruction::SB { .. } | Instruction::SH { .. } | Instruction::SW { .. } => {
+                &mut self.stores
+            }
+            // sc.w and the AMOs all write memory
+            _ if inst.is_atomic() => &mut self.stores,
+            Instruction::JAL { .. } | Instruction::JALR { .. } => &mut self.jumps,
+            _ if inst.is_branch_or_jump() && taken => &mut self.branches_taken,
+            _ if inst.is_branch_or_jump() => &mut self.branches_not_taken,
+            Instruction::ECALL { .. }
+            | Instruction::EBREAK { .. }
+            | Instruction::MRET { .. }
+            | Instruction::FENCE { .. }
+            | Instruction::FENCEI { .. }
+            | Instruction::CSRRW { .. }
+            | Instruction::CSRRS { .. }
+            | Instruction::CSRRC { .. }
+            | Instruction::CSRRWI { .. }
+            | Instruction::CSRRSI { .. }
+            | Instruction::CSRRCI { .. } => &mut self.system,
+            _ => &mut self.arithmetic,
+        };
+        *counter += 1;
+    }
+
+    /// Every kind with its count, in the order [`InstructionStats::report`] lists them
+    fn kinds(&self) -> [(&'static str, u64); 7] {
+        [
+            ("arithmetic", self.arithmetic),
+            ("loads", self.loads),
+            ("stores", self.stores),
+            ("branches taken", self.branches_taken),
+            ("branches not taken", self.branches_not_taken),
+            ("jumps", self.jumps),
+            ("system", self.system),
+        ]
+    }
+
+    /// Every kind with its count, most common first. Ties keep the report's order
+    pub fn rows(&self) -> Vec<(&'static str, u64)> {
+        let mut rows = self.kinds().to_vec();
+        rows.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        rows
+    }
+
+    /// One line short enough for the control panel
+    pub fn summary(&self) -> String {
+        format!(
+            "alu {} ld {} st {} br {}/{} j {} sys {}",
+            self.arithmetic,
+            self.loads,
+            self.stores,
+            self.branches_taken,
+            self.branches_not_taken,
+            self.jumps,
+            self.system
+        )
+    }
+
+    /// Every kind with its share of the total, one per line
+    pub fn report(&self) -> String {
+        let total = self.total();
+        let mut out = format!("instructions {}\n", total);
+        for (name, count) in self.kinds() {
+            let share = if total == 0 {
+                0.0
+            } else {
+                count as f64 * 100.0 / total as f64
+            };
+            writeln!(out, "  {: <18} {: >10} {:5.1}%", name, count, share).unwrap();
+        }
+        out
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{
+    asm,
+    vm::{ArchState, InstructionStats},
+};
+
+#[test]
+fn test_stats_loop() {
+    let program = asm::assemble(
+        "
+            li x1, 0
+            li x2, 5
+        loop:
+            addi x1, x1, 1
+            lw x3, 0x80(x0)
+            sw x1, 0x84(x0)
+            bne x1, x2, loop
+            j done
+        done:
+            nop
+        ",
+    )
+    .unwrap();
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(program, 0);
+    assert_eq!(state.run(2 + 5 * 4 + 2).steps, 24);
+
+    let expected = InstructionStats {
+        arithmetic: 2 + 5 + 1,
+        loads: 5,
+        stores: 5,
+        branches_taken: 4,
+        branches_not_taken: 1,
+        jumps: 1,
+        system: 0,
+    };
+    assert_eq!(*state.stats(), expected);
+    assert_eq!(expected.total(), 24);
+    assert_eq!(expected.summary(), "alu 8 ld 5 st 5 br 4/1 j 1 sys 0");
+    assert_eq!(
+        expected.rows(),
+        [
+            ("arithmetic", 8),
+            ("loads", 5),
+            ("stores", 5),
+            ("branches taken", 4),
+            ("branches not taken", 1),
+            ("jumps", 1),
+            ("system", 0),
+        ]
+    );
+    let jumpy = InstructionStats {
+        jumps: 7,
+        system: 3,
+        ..Default::default()
+    };
+    assert_eq!(
+        jumpy.rows()[..3],
+        [("jumps", 7), ("system", 3), ("arithmetic", 0)]
+    );
+    assert!(
+        expected
+            .report()
+            .contains("  branches taken              4  16.7%\n")
+    );
+
+    // stepping back uncounts, a reset starts over
+    assert!(state.step_back());
+    assert_eq!(state.stats().arithmetic, 7);
+    state.reset();
+    assert_eq!(*state.stats(), InstructionStats::default());
+}
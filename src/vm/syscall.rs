@@ -0,0 +1,123 @@
+use std::io::{self, Read, Write};
+
+use super::{ArchState, Instruction, VmError, try_interpret_bytes};
+
+#[cfg(test)]
+mod syscall_tests;
+
+/// Syscall numbers, passed in a7, following the Linux/newlib RISC-V ABI
+pub const SYS_READ: u32 = 63;
+pub const SYS_WRITE: u32 = 64;
+pub const SYS_EXIT: u32 = 93;
+
+const EBADF: i32 = 9;
+const EFAULT: i32 = 14;
+const ENOSYS: i32 = 38;
+
+/// What the machine should do after a syscall returns
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyscallOutcome {
+    Continue,
+    /// Halt with this exit status
+    Exit(u32),
+}
+
+/// Services `ECALL`s, see [`ArchState::set_syscall_handler`].
+/// Arguments are in a0-a5 and the syscall number in a7, results go back in a0
+pub trait SyscallHandler: Send {
+    fn handle(&mut self, state: &mut ArchState) -> SyscallOutcome;
+}
+
+/// Handles `read` on stdin, `write` on stdout and stderr, and `exit` using the host's streams
+pub struct HostSyscalls;
+
+impl SyscallHandler for HostSyscalls {
+    fn handle(&mut self, state: &mut ArchState) -> SyscallOutcome {
+        let (fd, buf, len) = (
+            state.get_register(10),
+            state.get_register(11) as usize,
+            state.get_register(12) as usize,
+        );
+        let result = match state.get_register(17) {
+            SYS_WRITE => match state.mem.read(buf, len) {
+                Some(bytes) => {
+                    let written = match fd {
+                        1 => io::stdout().write(&bytes),
+                        2 => io::stderr().write(&bytes),
+                        _ => Ok(0),
+                    };
+                    match (fd, written) {
+                        (1 | 2, Ok(written)) => written as i32,
+                        _ => -EBADF,
+                    }
+                }
+                None => -EFAULT,
+            },
+            SYS_READ => match state.mem.read(buf, len) {
+                Some(mut bytes) if fd == 0 => match io::stdin().read(&mut bytes) {
+                    Ok(read) => match state.store_bytes(buf as u32, &bytes[..read]) {
+                        Ok(()) => read as i32,
+                        Err(_) => -EFAULT,
+                    },
+                    Err(_) => -EBADF,
+                },
+                Some(_) => -EBADF,
+                None => -EFAULT,
+            },
+            SYS_EXIT => return SyscallOutcome::Exit(state.get_register(10)),
+            _ => -ENOSYS,
+        };
+        state.set_register(10, result as u32);
+        SyscallOutcome::Continue
+    }
+}
+
+/// Returns straight away, so [`ArchState::run_until_ecall`] can step over an ecall
+struct StepOver;
+
+impl SyscallHandler for StepOver {
+    fn handle(&mut self, _state: &mut ArchState) -> SyscallOutcome {
+        SyscallOutcome::Continue
+    }
+}
+
+impl ArchState {
+    /// Writes `bytes` at `addr` one guest store at a time, so devices, watchpoints, the halt
+    /// address and the decode cache all see them like they would a `sb`
+    fn store_bytes(&mut self, addr: u32, bytes: &[u8]) -> Result<(), VmError> {
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.store_mem(addr.wrapping_add(offset as u32), 1, *byte as u32)?;
+        }
+        Ok(())
+    }
+
+    /// Runs up to `max` instructions until the next `ECALL`, which is stepped over instead of
+    /// going to the syscall handler. Returns the syscall number from a7 and the arguments in
+    /// a0-a7, so a host loop can service the call, write results to a0, and run again
+    pub fn run_until_ecall(&mut self, max: u64) -> Result<(u32, [u32; 8]), VmError> {
+        for _ in 0..max {
+            if self.halted {
+                return Err(VmError::Halted);
+            }
+            let ecall = (self.pc as u32)
+                .is_multiple_of(2)
+                .then(|| self.fetch(self.pc as usize))
+                .flatten()
+                .and_then(try_interpret_bytes)
+                .is_some_and(|inst| matches!(inst, Instruction::ECALL { .. }));
+            if !ecall {
+                self.tick()?;
+                continue;
+            }
+            // retire the ecall like any other instruction, with a handler that leaves the call
+            // to the host loop
+            let handler = self.syscall_handler.replace(Box::new(StepOver));
+            let ticked = self.tick();
+            self.syscall_handler = handler;
+            ticked?;
+            let args = std::array::from_fn(|i| self.get_register(10 + i));
+            return Ok((self.get_register(17), args));
+        }
+        Err(VmError::StepLimit(max))
+    }
+}
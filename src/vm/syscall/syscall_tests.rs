@@ -0,0 +1,128 @@
+use std::sync::{Arc, Mutex};
+
+use crate::vm::{
+    ArchState, HostSyscalls, SYS_EXIT, SYS_WRITE, SyscallHandler, SyscallOutcome, VmError,
+};
+
+/// Captures writes to stdout instead of printing them
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl SyscallHandler for Capture {
+    fn handle(&mut self, state: &mut ArchState) -> SyscallOutcome {
+        match state.get_register(17) {
+            SYS_WRITE if state.get_register(10) == 1 => {
+                let buf = state.get_register(11) as usize;
+                let len = state.get_register(12) as usize;
+                self.0
+                    .lock()
+                    .unwrap()
+                    .extend_from_slice(&state.mem.read(buf, len).unwrap());
+                state.set_register(10, len as u32);
+                SyscallOutcome::Continue
+            }
+            SYS_EXIT => SyscallOutcome::Exit(state.get_register(10)),
+            _ => SyscallOutcome::Continue,
+        }
+    }
+}
+
+/// Writes "hi\n" from 0x80 to stdout, then exits with the byte count
+fn hello() -> ArchState {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    let program = [
+        0x00100513_u32, // addi a0, x0, 1
+        0x08000593,     // addi a1, x0, 0x80
+        0x00300613,     // addi a2, x0, 3
+        0x04000893,     // addi a7, x0, 64
+        0x00000073,     // ecall
+        0x05d00893,     // addi a7, x0, 93
+        0x00000073,     // ecall
+        0x00100093,     // addi x1, x0, 1
+    ];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+    state.load(b"hi\n".to_vec(), 0x80);
+    state
+}
+
+#[test]
+fn test_syscall_write() {
+    let mut state = hello();
+    let output = Arc::new(Mutex::new(vec![]));
+    state.set_syscall_handler(Box::new(Capture(Arc::clone(&output))));
+
+    assert_eq!(state.run(100).steps, 7);
+    assert_eq!(*output.lock().unwrap(), b"hi\n");
+    assert!(state.halted);
+    assert_eq!(
+        state.exit_status,
+        Some(3),
+        "exit code is write's return value"
+    );
+    assert_eq!(state.get_register(1), 0, "nothing runs after exit");
+}
+
+#[test]
+fn test_store_bytes() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.add_watchpoint(0x81);
+    state.store_bytes(0x80, b"hi").unwrap();
+    assert_eq!(state.mem.read(0x80, 2).unwrap(), b"hi");
+    assert_eq!(state.last_written, [0x80, 0x81]);
+    assert_eq!(state.watch_events.len(), 1, "the watchpoint saw the write");
+
+    // the halt address catches it too, and a buffer past memory faults part way
+    state.halt_addr = Some(0x90);
+    state.store_bytes(0x90, &[3]).unwrap();
+    assert!(state.halted);
+    assert_eq!(
+        state.store_bytes(0xff, b"ab"),
+        Err(VmError::OutOfBounds { addr: 0x100 })
+    );
+}
+
+#[test]
+fn test_host_syscalls() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.set_syscall_handler(Box::new(HostSyscalls));
+
+    // unknown syscalls return -ENOSYS
+    state.set_register(17, 1234);
+    state.apply_raw(0x00000073).unwrap();
+    assert_eq!(state.get_register(10), -38_i32 as u32);
+
+    // writes outside memory return -EFAULT
+    state.set_register(17, SYS_WRITE);
+    state.set_register(10, 1);
+    state.set_register(11, 0xF0);
+    state.set_register(12, 0x20);
+    state.apply_raw(0x00000073).unwrap();
+    assert_eq!(state.get_register(10), -14_i32 as u32);
+
+    state.set_register(17, SYS_EXIT);
+    state.set_register(10, 7);
+    state.apply_raw(0x00000073).unwrap();
+    assert_eq!(state.exit_status, Some(7));
+    assert!(state.halted);
+}
+
+#[test]
+fn test_run_until_ecall() {
+    // no handler is installed, the host loop below services the calls instead
+    let mut state = hello();
+
+    let (number, args) = state.run_until_ecall(100).unwrap();
+    assert_eq!(number, SYS_WRITE);
+    assert_eq!(args[..3], [1, 0x80, 3]);
+    assert_eq!(args[7], SYS_WRITE);
+    assert_eq!(state.pc, 20, "stopped after the ecall");
+    assert_eq!(state.instret(), 5);
+    assert_eq!(state.cycle_count(), 5);
+    assert_eq!(state.stats().summary(), "alu 4 ld 0 st 0 br 0/0 j 0 sys 1");
+
+    state.set_register(10, 3);
+    let (number, args) = state.run_until_ecall(100).unwrap();
+    assert_eq!((number, args[0]), (SYS_EXIT, 3));
+
+    assert_eq!(state.run_until_ecall(1), Err(VmError::StepLimit(1)));
+    assert_eq!(state.get_register(1), 1);
+}
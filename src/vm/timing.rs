@@ -0,0 +1,79 @@
+use super::Instruction;
+
+#[cfg(test)]
+mod timing_tests;
+
+/// Cycles each class of instruction takes, see [`super::ArchState::set_timing`].
+/// Without a model every instruction takes one cycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimingModel {
+    /// Register and immediate ALU ops, `lui` and `auipc`
+    pub alu: u64,
+    pub load: u64,
+    pub store: u64,
+    /// Multiplies, divides, and remainders
+    pub mul: u64,
+    pub branch_taken: u64,
+    pub branch_not_taken: u64,
+    /// `jal` and `jalr`
+    pub jump: u64,
+    /// CSR accesses, `ecall`, `ebreak`, and fences
+    pub system: u64,
+}
+
+/// A classic five stage pipeline without forwarding from memory or a branch predictor
+impl Default for TimingModel {
+    fn default() -> Self {
+        Self {
+            alu: 1,
+            load: 2,
+            store: 1,
+            mul: 4,
+            branch_taken: 3,
+            branch_not_taken: 1,
+            jump: 3,
+            system: 1,
+        }
+    }
+}
+
+impl TimingModel {
+    /// Cycles `inst` takes, `taken` is whether it left pc somewhere other than the next instruction
+    pub fn cost(&self, inst: &Instruction, taken: bool) -> u64 {
+        match inst {
+            Instruction::LB { .. }
+            | Instruction::LH { .. }
+            | Instruction::LW { .. }
+            | Instruction::LBU { .. }
+            | Instruction::LHU { .. }
+            | Instruction::LRW { .. } => self.load,
+            Instruction::SB { .. } | Instruction::SH { .. } | Instruction::SW { .. } => self.store,
+            Instruction::SCW { .. } => self.store,
+            // an AMO is a load and a store back to back
+            _ if inst.is_atomic() => self.load + self.store,
+            Instruction::MUL { .. }
+            | Instruction::MULH { .. }
+            | Instruction::MULHSU { .. }
+            | Instruction::MULHU { .. }
+            | Instruction::DIV { .. }
+            | Instruction::DIVU { .. }
+            | Instruction::REM { .. }
+            | Instruction::REMU { .. } => self.mul,
+            Instruction::JAL { .. } | Instruction::JALR { .. } => self.jump,
+            _ if inst.is_branch_or_jump() && taken => self.branch_taken,
+            _ if inst.is_branch_or_jump() => self.branch_not_taken,
+            Instruction::ECALL { .. }
+            | Instruction::EBREAK { .. }
+            | Instruction::MRET { .. }
+            | Instruction::FENCE { .. }
+            | Instruction::FENCEI { .. }
+            | Instruction::CSRRW { .. }
+            | Instruction::CSRRS { .. }
+            | Instruction::CSRRC { .. }
+            | Instruction::CSRRWI { .. }
+            | Instruction::CSRRSI { .. }
+            | Instruction::CSRRCI { .. } => self.system,
+            _ => self.alu,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{
+    asm,
+    vm::{ArchState, CSR_CYCLE, CSR_INSTRET, TimingModel},
+};
+
+#[test]
+fn test_timing_costs() {
+    let program = asm::assemble(
+        "
+            li x1, 0
+            li x2, 3
+        loop:
+            addi x1, x1, 1
+            lw x3, 0x80(x0)
+            mul x4, x1, x1
+            bne x1, x2, loop
+            sw x4, 0x84(x0)
+        ",
+    )
+    .unwrap();
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.load(program.clone(), 0);
+    state.set_timing(Some(TimingModel {
+        alu: 1,
+        load: 5,
+        store: 7,
+        mul: 10,
+        branch_taken: 3,
+        branch_not_taken: 2,
+        jump: 1,
+        system: 1,
+    }));
+    assert_eq!(state.run(2 + 3 * 4 + 1).steps, 15);
+
+    // two li, then three passes of addi, lw, mul, bne with the last one falling through
+    let cycles = 2 + 3 * (1 + 5 + 10) + 2 * 3 + 2 + 7;
+    assert_eq!(state.cycle_count(), cycles);
+    assert_eq!(state.instret(), 15);
+    assert_eq!(state.read_csr(CSR_CYCLE) as u64, cycles);
+    assert_eq!(state.read_csr(CSR_INSTRET), 15);
+
+    // stepping back refunds the store
+    assert!(state.step_back());
+    assert_eq!(state.cycle_count(), cycles - 7);
+    state.reset();
+    assert_eq!(state.cycle_count(), 0);
+
+    // without a model every instruction is one cycle
+    state.set_timing(None);
+    state.load(program, 0);
+    state.run(5);
+    assert_eq!(state.cycle_count(), 5);
+}
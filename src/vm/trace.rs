@@ -0,0 +1,152 @@
+use std::{
+    fmt::{self, Display},
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use super::{ArchState, Instruction, assembly};
+
+#[cfg(test)]
+mod trace_tests;
+
+/// Lines `trace_file` buffers before flushing, so a crash loses at most this many
+const FLUSH_LINES: u64 = 256;
+
+/// Receives every applied instruction, see [`ArchState::set_trace`]
+pub type TraceSink = Box<dyn FnMut(&TraceEntry) + Send>;
+
+/// State an instruction changed, values that were rewritten unchanged aren't reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceChange {
+    Register {
+        index: usize,
+        old: u32,
+        new: u32,
+    },
+    /// A store of `width` bytes, `old` and `new` are read little-endian like a load
+    Memory {
+        addr: u32,
+        width: u32,
+        old: u32,
+        new: u32,
+    },
+}
+
+/// One applied instruction and what it changed
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    /// Address of the instruction, before it moved pc
+    pub pc: u32,
+    pub inst: Instruction,
+    pub changes: Vec<TraceChange>,
+}
+
+/// `pc assembly  changes`, e.g. `00000010 addi x1, x1, 1  x1<-0x00000002`.
+/// Stores show as `mem[addr]<-value` with one hex digit pair per byte stored
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x} {}", self.pc, assembly(&self.inst, self.pc))?;
+        for (index, change) in self.changes.iter().enumerate() {
+            f.write_str(if index == 0 { "  " } else { " " })?;
+            match *change {
+                TraceChange::Register { index, new, .. } => write!(f, "x{}<-0x{:08x}", index, new)?,
+                TraceChange::Memory {
+                    addr, width, new, ..
+                } => write!(
+                    f,
+                    "mem[0x{:08x}]<-0x{:0width$x}",
+                    addr,
+                    new,
+                    width = width as usize * 2
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A sink writing each entry to a new file at `path` as one line, see [`TraceEntry`]'s `Display`.
+/// The file is flushed every few hundred lines and when the sink is dropped
+pub fn trace_file(path: impl AsRef<Path>) -> io::Result<TraceSink> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut lines = 0_u64;
+    Ok(Box::new(move |entry| {
+        // a full disk shouldn't stop the program, the log just ends early
+        let _ = writeln!(out, "{}", entry);
+        lines += 1;
+        if lines.is_multiple_of(FLUSH_LINES) {
+            let _ = out.flush();
+        }
+    }))
+}
+
+/// What `apply` compares against once the instruction has run
+pub(super) struct PreTrace {
+    pc: u32,
+    regs: [u32; 31],
+    /// Stored range and its contents, if it lies in memory
+    store: Option<(u32, u32, u32)>,
+}
+
+impl ArchState {
+    /// Calls `sink` after each successful `apply` with the instruction and the registers
+    /// and memory it changed. `None` turns tracing off
+    pub fn set_trace(&mut self, sink: Option<TraceSink>) {
+        self.trace = sink;
+    }
+
+    pub(super) fn trace_before(&self, inst: &Instruction) -> PreTrace {
+        PreTrace {
+            pc: self.pc as u32,
+            regs: self.regs,
+            store: self
+                .store_target(inst)
+                .and_then(|(addr, width)| self.peek_mem(addr, width).map(|old| (addr, width, old))),
+        }
+    }
+
+    pub(super) fn trace_after(&mut self, before: PreTrace, inst: &Instruction) {
+        let mut changes: Vec<TraceChange> = (1..32)
+            .filter(|&index| before.regs[index - 1] != self.regs[index - 1])
+            .map(|index| TraceChange::Register {
+                index,
+                old: before.regs[index - 1],
+                new: self.regs[index - 1],
+            })
+            .collect();
+        if let Some((addr, width, old)) = before.store
+            && let Some(new) = self.peek_mem(addr, width)
+            && old != new
+        {
+            changes.push(TraceChange::Memory {
+                addr,
+                width,
+                old,
+                new,
+            });
+        }
+        let entry = TraceEntry {
+            pc: before.pc,
+            inst: *inst,
+            changes,
+        };
+        // take the sink out so it can't observe itself mid-call
+        if let Some(mut sink) = self.trace.take() {
+            sink(&entry);
+            self.trace = Some(sink);
+        }
+    }
+
+    /// Reads memory without faulting or touching devices, `None` past the end
+    fn peek_mem(&self, addr: u32, width: u32) -> Option<u32> {
+        let bytes = self.mem.read(addr as usize, width as usize)?;
+        Some(
+            bytes
+                .iter()
+                .enumerate()
+                .map(|(offset, &byte)| (byte as u32) << (8 * offset))
+                .sum(),
+        )
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::{Arc, Mutex};
+
+use crate::vm::{ArchState, Instruction, TraceChange, TraceEntry, trace_file};
+
+#[test]
+fn test_trace_accumulator() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // addi x1, x1, 1 three times; sb x1, 0x80(x0)
+    state.load(
+        [0x00108093_u32, 0x00108093, 0x00108093, 0x08100023]
+            .iter()
+            .flat_map(|op| op.to_le_bytes())
+            .collect(),
+        0,
+    );
+    let entries: Arc<Mutex<Vec<TraceEntry>>> = Arc::default();
+    let sink = entries.clone();
+    state.set_trace(Some(Box::new(move |entry| {
+        sink.lock().unwrap().push(entry.clone())
+    })));
+    for _ in 0..4 {
+        state.tick().unwrap();
+    }
+
+    let entries = entries.lock().unwrap();
+    assert_eq!(
+        entries.iter().map(|entry| entry.pc).collect::<Vec<_>>(),
+        [0, 4, 8, 12]
+    );
+    assert!(matches!(entries[0].inst, Instruction::ADDI { .. }));
+    let changes = entries
+        .iter()
+        .flat_map(|entry| entry.changes.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        [
+            TraceChange::Register {
+                index: 1,
+                old: 0,
+                new: 1
+            },
+            TraceChange::Register {
+                index: 1,
+                old: 1,
+                new: 2
+            },
+            TraceChange::Register {
+                index: 1,
+                old: 2,
+                new: 3
+            },
+            TraceChange::Memory {
+                addr: 0x80,
+                width: 1,
+                old: 0,
+                new: 3
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_trace_file() {
+    const STEPS: u64 = 20;
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    // addi x1, x1, 1 over all of memory, then sb x1, 0x80(x0)
+    let mut program = 0x00108093_u32.to_le_bytes().repeat(STEPS as usize - 1);
+    program.extend(0x08100023_u32.to_le_bytes());
+    state.load(program, 0);
+    let path = std::env::temp_dir().join(format!("rv32-trace-{}.log", std::process::id()));
+    state.set_trace(Some(trace_file(&path).unwrap()));
+    assert_eq!(state.run(STEPS).steps, STEPS);
+    // dropping the sink flushes what's left
+    state.set_trace(None);
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines = log.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len() as u64, STEPS);
+    assert_eq!(lines[1], "00000004 addi x1, x1, 1  x1<-0x00000002");
+    assert_eq!(lines[19], "0000004c sb x1, 128(x0)  mem[0x00000080]<-0x13");
+}
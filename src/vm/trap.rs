@@ -0,0 +1,71 @@
+use super::{ArchState, Instruction, VmError};
+
+#[cfg(test)]
+mod trap_tests;
+
+/// Machine trap CSRs, a handler reads the cause and returns with `mret` to `mepc`
+pub const CSR_MTVEC: u16 = 0x305;
+pub const CSR_MEPC: u16 = 0x341;
+pub const CSR_MCAUSE: u16 = 0x342;
+pub const CSR_MTVAL: u16 = 0x343;
+
+/// Exception codes written to `mcause`
+const CAUSE_MISALIGNED_FETCH: u32 = 0;
+const CAUSE_FETCH_FAULT: u32 = 1;
+const CAUSE_ILLEGAL_INSTRUCTION: u32 = 2;
+const CAUSE_MISALIGNED_LOAD: u32 = 4;
+const CAUSE_LOAD_FAULT: u32 = 5;
+const CAUSE_MISALIGNED_STORE: u32 = 6;
+const CAUSE_STORE_FAULT: u32 = 7;
+const CAUSE_ECALL: u32 = 11;
+
+impl ArchState {
+    /// Address faults jump to instead of being returned, `None` when no handler is set.
+    /// Only direct mode is supported, the low two bits of `mtvec` are ignored
+    pub fn trap_vector(&self) -> Option<u32> {
+        self.csrs.get(&CSR_MTVEC).map(|vector| vector & !0b11)
+    }
+
+    /// Sets or clears the trap handler, the same as writing `mtvec` from the guest
+    /// except that it survives [`ArchState::reset`]
+    pub fn set_trap_vector(&mut self, vector: Option<u32>) {
+        self.host_trap_vector = vector;
+        match vector {
+            Some(vector) => self.csrs.insert(CSR_MTVEC, vector),
+            None => self.csrs.remove(&CSR_MTVEC),
+        };
+    }
+
+    /// Vectors `err` raised by `inst` to the trap handler, saving pc to `mepc`.
+    /// Returns `err` unchanged if no handler is set or it isn't a guest exception
+    pub(super) fn trap(&mut self, err: VmError, inst: Option<&Instruction>) -> Result<(), VmError> {
+        let Some(vector) = self.trap_vector() else {
+            return Err(err);
+        };
+        let pc = self.pc as u32;
+        let store = inst.is_some_and(|inst| self.store_target(inst).is_some());
+        let (cause, tval) = match err {
+            VmError::MisalignedPc(pc) => (CAUSE_MISALIGNED_FETCH, pc),
+            VmError::FetchOutOfBounds(pc) => (CAUSE_FETCH_FAULT, pc),
+            VmError::IllegalInstruction(raw) => (CAUSE_ILLEGAL_INSTRUCTION, raw),
+            // a syscall handler services ECALL itself, without one it traps like on hardware
+            VmError::Ecall => (CAUSE_ECALL, 0),
+            VmError::Unimplemented(_) | VmError::ReadOnlyCsr(_) => (
+                CAUSE_ILLEGAL_INSTRUCTION,
+                self.fetch(pc as usize).unwrap_or(0),
+            ),
+            VmError::Misaligned { addr, .. } if store => (CAUSE_MISALIGNED_STORE, addr),
+            VmError::Misaligned { addr, .. } => (CAUSE_MISALIGNED_LOAD, addr),
+            VmError::OutOfBounds { addr } if store => (CAUSE_STORE_FAULT, addr),
+            VmError::OutOfBounds { addr } => (CAUSE_LOAD_FAULT, addr),
+            _ => return Err(err),
+        };
+        self.csrs.insert(CSR_MEPC, pc);
+        self.csrs.insert(CSR_MCAUSE, cause);
+        self.csrs.insert(CSR_MTVAL, tval);
+        self.pc = vector as i64;
+        // a handler clears the reservation like any other trap
+        self.reservation = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use crate::vm::{ArchState, CSR_MCAUSE, CSR_MEPC, CSR_MTVAL, VmError};
+
+const HANDLER: u32 = 0x40;
+
+/// An illegal word followed by `addi x5, x5, 1`, with a handler at [`HANDLER`] that
+/// bumps x6 and returns past the faulting instruction
+fn load_program(state: &mut ArchState) {
+    let program = [
+        0xffffffff_u32, // illegal
+        0x00128293,     // addi x5, x5, 1
+    ];
+    let handler = [
+        0x00130313_u32, // addi x6, x6, 1
+        0x341023f3,     // csrrs x7, mepc, x0
+        0x00438393,     // addi x7, x7, 4
+        0x34139073,     // csrrw x0, mepc, x7
+        0x30200073,     // mret
+    ];
+    state.load(program.iter().flat_map(|op| op.to_le_bytes()).collect(), 0);
+    state.load(
+        handler.iter().flat_map(|op| op.to_le_bytes()).collect(),
+        HANDLER as usize,
+    );
+}
+
+#[test]
+fn test_illegal_instruction_traps() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    load_program(&mut state);
+    state.set_trap_vector(Some(HANDLER));
+
+    state.tick().unwrap();
+    assert_eq!(state.pc, HANDLER as i64);
+    assert_eq!(state.read_csr(CSR_MEPC), 0);
+    assert_eq!(state.read_csr(CSR_MCAUSE), 2);
+    assert_eq!(state.read_csr(CSR_MTVAL), 0xffffffff);
+
+    for _ in 0..6 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.get_register(6), 1);
+    assert_eq!(state.get_register(5), 1);
+    assert_eq!(state.pc, 8);
+}
+
+#[test]
+fn test_faults_without_vector() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    load_program(&mut state);
+    assert_eq!(state.trap_vector(), None);
+    assert_eq!(state.tick(), Err(VmError::IllegalInstruction(0xffffffff)));
+    assert_eq!(state.pc, 0);
+
+    // csrrw x0, mtvec, x8 sets the vector from the guest, ecall without a handler then traps
+    state.set_register(8, HANDLER | 0b01);
+    state.apply_raw(0x30541073).unwrap();
+    assert_eq!(state.trap_vector(), Some(HANDLER));
+    state.pc = 4;
+    state.apply_raw(0x00000073).unwrap(); // ecall
+    assert_eq!(state.pc, HANDLER as i64);
+    assert_eq!(state.read_csr(CSR_MEPC), 4);
+    assert_eq!(state.read_csr(CSR_MCAUSE), 11);
+
+    state.set_trap_vector(None);
+    assert_eq!(state.apply_raw(0x00000073), Err(VmError::Ecall));
+}
+
+#[test]
+fn test_trap_stepped_back() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    load_program(&mut state);
+    state.set_trap_vector(Some(HANDLER));
+    state.tick().unwrap();
+    assert_eq!(state.pc, HANDLER as i64);
+
+    // back at the faulting word with the trap CSRs as they were
+    assert!(state.step_back());
+    assert_eq!(state.pc, 0);
+    assert_eq!(state.read_csr(CSR_MEPC), 0);
+    assert_eq!(state.read_csr(CSR_MCAUSE), 0);
+    assert_eq!(state.read_csr(CSR_MTVAL), 0);
+
+    // a handler's mepc write goes back to the value the trap left, not the one before it
+    for _ in 0..5 {
+        state.tick().unwrap();
+    }
+    assert_eq!(state.read_csr(CSR_MEPC), 4);
+    assert!(state.step_back());
+    assert_eq!(state.read_csr(CSR_MEPC), 0);
+    assert_eq!(state.read_csr(CSR_MCAUSE), 2);
+}
+
+#[test]
+fn test_reset_keeps_host_vector() {
+    let mut state = ArchState::with_mem(2_usize.pow(8));
+    state.set_trap_vector(Some(HANDLER));
+    // all zero words are illegal
+    state.tick().unwrap();
+    assert_eq!(state.read_csr(CSR_MCAUSE), 2);
+    state.reset();
+    assert_eq!(state.trap_vector(), Some(HANDLER));
+    assert_eq!(state.read_csr(CSR_MCAUSE), 0);
+
+    // a vector the guest wrote is gone after a reset, the host's comes back
+    state.set_register(8, 0x80);
+    state.apply_raw(0x30541073).unwrap(); // csrrw x0, mtvec, x8
+    assert_eq!(state.trap_vector(), Some(0x80));
+    state.reset();
+    assert_eq!(state.trap_vector(), Some(HANDLER));
+
+    state.set_trap_vector(None);
+    state.reset();
+    assert_eq!(state.trap_vector(), None);
+}
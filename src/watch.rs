@@ -0,0 +1,125 @@
+use crate::vm::ArchState;
+
+#[cfg(test)]
+mod watch_tests;
+
+/// Evaluates a watch expression against the current state.
+///
+/// Terms are registers (`x0`..`x31`, `pc`), numbers (decimal or `0x` hex), and memory reads
+/// written `mem[<expr>]` with an optional `:byte`, `:half`, or `:word` width (default word).
+/// Terms are combined left to right with wrapping `+` and `-`.
+pub fn evaluate(expr: &str, state: &ArchState) -> Result<u32, String> {
+    let mut parser = Parser {
+        src: expr,
+        pos: 0,
+        state,
+    };
+    let val = parser.sum()?;
+    parser.skip_whitespace();
+    if parser.pos < expr.len() {
+        return Err(format!("unexpected '{}'", &expr[parser.pos..]));
+    }
+    Ok(val)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    state: &'a ArchState,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.src.len() - self.rest().trim_start().len();
+    }
+
+    /// Consumes `token` if the input continues with it
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn word(&mut self) -> &str {
+        self.skip_whitespace();
+        let len = self
+            .rest()
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(self.rest().len());
+        let start = self.pos;
+        self.pos += len;
+        &self.src[start..self.pos]
+    }
+
+    fn sum(&mut self) -> Result<u32, String> {
+        let mut val = self.term()?;
+        loop {
+            if self.eat("+") {
+                val = val.wrapping_add(self.term()?);
+            } else if self.eat("-") {
+                val = val.wrapping_sub(self.term()?);
+            } else {
+                return Ok(val);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<u32, String> {
+        if self.eat("mem[") {
+            let addr = self.sum()?;
+            if !self.eat("]") {
+                return Err("expected ']'".to_string());
+            }
+            let width = if self.eat(":") {
+                match self.word() {
+                    "byte" => 1,
+                    "half" => 2,
+                    "word" => 4,
+                    other => return Err(format!("unknown width '{}'", other)),
+                }
+            } else {
+                4
+            };
+            return self.read_mem(addr, width);
+        }
+
+        let word = self.word();
+        if word.is_empty() {
+            return Err(format!("expected a term at '{}'", self.rest()));
+        }
+        if word == "pc" {
+            return Ok(self.state.pc as u32);
+        }
+        if let Some(hex) = word.strip_prefix("0x") {
+            return u32::from_str_radix(hex, 16).map_err(|err| format!("'{}': {}", word, err));
+        }
+        if let Some(index) = word.strip_prefix('x') {
+            return match index.parse::<usize>() {
+                Ok(index) if index < 32 => Ok(self.state.get_register(index)),
+                _ => Err(format!("unknown register '{}'", word)),
+            };
+        }
+        word.parse::<u32>()
+            .map_err(|err| format!("'{}': {}", word, err))
+    }
+
+    /// Reads with the same byte order as the load instructions
+    fn read_mem(&self, addr: u32, width: usize) -> Result<u32, String> {
+        let addr = addr as usize;
+        match self.state.mem.read(addr, width) {
+            Some(bytes) => Ok(bytes
+                .iter()
+                .rev()
+                .fold(0, |val, byte| (val << 8) | *byte as u32)),
+            None => Err(format!("address 0x{:08x} out of range", addr)),
+        }
+    }
+}
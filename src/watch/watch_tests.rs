@@ -0,0 +1,43 @@
+use crate::vm::ArchState;
+
+use super::evaluate;
+
+#[test]
+fn test_evaluate() {
+    let mut state = ArchState::with_mem(2_usize.pow(16));
+    state.load(vec![0x78, 0x56, 0x34, 0x12], 0x1000);
+    state.load(vec![0x00, 0x10, 0x00, 0x00], 0x2000);
+    state.pc = 0x40;
+    // addi x10, x0, 40 then addi x11, x0, 2
+    state.load(
+        [0x02800513_u32, 0x00200593]
+            .iter()
+            .flat_map(|op| op.to_le_bytes())
+            .collect(),
+        0x40,
+    );
+    state.tick().unwrap();
+    state.tick().unwrap();
+
+    assert_eq!(evaluate("x10", &state), Ok(40));
+    assert_eq!(evaluate("x10 + x11", &state), Ok(42));
+    assert_eq!(evaluate("x0 - x11", &state), Ok(-2_i32 as u32));
+    assert_eq!(evaluate("pc", &state), Ok(0x48));
+    assert_eq!(evaluate("mem[0x1000]:word", &state), Ok(0x12345678));
+    assert_eq!(evaluate("mem[0x1000]", &state), Ok(0x12345678));
+    assert_eq!(evaluate("mem[0x1000 + 2]:half", &state), Ok(0x1234));
+    assert_eq!(evaluate("mem[0x1001]:byte", &state), Ok(0x56));
+    // dereference through another read
+    assert_eq!(evaluate("mem[mem[0x2000]]:byte + 1", &state), Ok(0x79));
+}
+
+#[test]
+fn test_evaluate_errors() {
+    let state = ArchState::with_mem(2_usize.pow(8));
+    assert!(evaluate("x32", &state).is_err());
+    assert!(evaluate("mem[0x1000]", &state).is_err());
+    assert!(evaluate("mem[0]:dword", &state).is_err());
+    assert!(evaluate("mem[0", &state).is_err());
+    assert!(evaluate("x1 +", &state).is_err());
+    assert!(evaluate("x1 x2", &state).is_err());
+}
@@ -0,0 +1,23 @@
+use riscv_rust_emulator::vm::{ArchState, Segment};
+
+/// Same program as the crate's `test_accumulator`, driven only through the public API
+#[test]
+fn test_golden_accumulator() {
+    const LOOPS: u64 = 50;
+    // addi x1, x1, 1
+    let op = 0x00108093_u32;
+    let program = (0..LOOPS)
+        .flat_map(|_| op.to_le_bytes())
+        .collect::<Vec<u8>>();
+
+    // memory ends right after the program so fetching past it stops the run
+    let mut state = ArchState::with_mem(program.len());
+    state
+        .load_segments(&[Segment::from_bytes(program, 0)])
+        .unwrap();
+    let ran = state.run(u64::MAX).steps;
+
+    assert_eq!(ran, LOOPS);
+    assert_eq!(state.get_register(1) as u64, LOOPS);
+    assert_eq!(state.pc as u64, LOOPS * 4);
+}